@@ -0,0 +1,161 @@
+//! Session-side agent that reports the focused window to the daemon, which is used by focused window profile rules
+mod gnome;
+mod kwin;
+mod sway;
+mod x11;
+
+use anyhow::Context;
+use lact_client::{ConnectionStatusMsg, DaemonClient};
+use lact_schema::{
+    FocusedWindow,
+    args::cli::{FocusAgentArgs, FocusAgentBackend},
+};
+use std::{env, time::Duration};
+use tokio::{
+    select,
+    signal::unix::{SignalKind, signal},
+    sync::mpsc,
+    time,
+};
+use zbus::interface;
+
+/// Name of the agent on the session bus. Compositors without a built-in backend
+/// can report focus changes by calling the methods of the agent interface.
+const DBUS_NAME: &str = "io.github.ilya_zlobintsev.LACT.FocusAgent";
+const DBUS_PATH: &str = "/io/github/ilya_zlobintsev/LACT/FocusAgent";
+/// How often the daemon connection is checked, and how long to wait before retrying a failed report
+const DAEMON_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+type WindowSender = mpsc::Sender<Option<FocusedWindow>>;
+
+pub async fn run(client: &DaemonClient, args: &FocusAgentArgs) -> anyhow::Result<()> {
+    let (window_tx, window_rx) = mpsc::channel(16);
+
+    let conn = zbus::connection::Builder::session()?
+        .name(DBUS_NAME)?
+        .serve_at(
+            DBUS_PATH,
+            FocusAgentInterface {
+                window_tx: window_tx.clone(),
+            },
+        )?
+        .build()
+        .await
+        .context("Could not register the focus agent on the session bus")?;
+
+    let backend = args.backend.unwrap_or_else(detect_backend);
+    println!("Reporting focused windows using the {backend:?} backend");
+
+    let mut kwin_script = None;
+    match backend {
+        FocusAgentBackend::X11 => x11::start(window_tx)?,
+        FocusAgentBackend::Kwin => kwin_script = Some(kwin::load_script(&conn).await?),
+        FocusAgentBackend::Gnome => gnome::start(&conn, window_tx).await?,
+        FocusAgentBackend::Sway => sway::start(window_tx).await?,
+        FocusAgentBackend::Dbus => (),
+    }
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    select! {
+        () = report_windows(client, window_rx) => (),
+        _ = terminate.recv() => (),
+        _ = interrupt.recv() => (),
+    }
+
+    if kwin_script.is_some() {
+        kwin::unload_script(&conn).await?;
+    }
+    client.set_focused_window(None).await
+}
+
+/// Reports focus changes to the daemon. Failed reports are retried, and the window is reported again
+/// after the client reconnects, as the daemon clears it when the connection of the agent is closed.
+async fn report_windows(
+    client: &DaemonClient,
+    mut window_rx: mpsc::Receiver<Option<FocusedWindow>>,
+) {
+    let mut status_rx = client.status_receiver();
+    let mut check_interval = time::interval(DAEMON_CHECK_INTERVAL);
+    let mut last_window = None;
+    // Whether `last_window` still has to be reported
+    let mut pending = false;
+
+    loop {
+        select! {
+            Some(window) = window_rx.recv() => {
+                if window != last_window {
+                    last_window = window;
+                    pending = true;
+                }
+            }
+            Ok(ConnectionStatusMsg::Reconnected) = status_rx.recv() => pending = true,
+            _ = check_interval.tick() => {
+                // The client only notices that the daemon was restarted when making a request
+                if !pending && let Err(err) = client.ping().await {
+                    eprintln!("Could not reach the daemon: {err:#}");
+                }
+            }
+        }
+
+        if pending {
+            match client.set_focused_window(last_window.clone()).await {
+                Ok(()) => pending = false,
+                Err(err) => eprintln!(
+                    "Could not report the focused window: {err:#}, retrying in {}s",
+                    DAEMON_CHECK_INTERVAL.as_secs()
+                ),
+            }
+        }
+    }
+}
+
+fn detect_backend() -> FocusAgentBackend {
+    let desktop = env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let is_desktop = |name: &str| desktop.split(':').any(|item| item == name);
+
+    if is_desktop("kde") {
+        FocusAgentBackend::Kwin
+    } else if is_desktop("gnome") {
+        FocusAgentBackend::Gnome
+    } else if env::var_os("SWAYSOCK").is_some() {
+        FocusAgentBackend::Sway
+    } else if env::var_os("WAYLAND_DISPLAY").is_none() && env::var_os("DISPLAY").is_some() {
+        FocusAgentBackend::X11
+    } else {
+        FocusAgentBackend::Dbus
+    }
+}
+
+/// Builds a window from values where empty strings and a PID of 0 mean that the value is not known.
+/// Returns `None` if nothing is known about the window.
+fn focused_window(app_id: &str, wm_class: &str, pid: i32) -> Option<FocusedWindow> {
+    let window = FocusedWindow {
+        app_id: Some(app_id.to_owned()).filter(|value| !value.is_empty()),
+        wm_class: Some(wm_class.to_owned()).filter(|value| !value.is_empty()),
+        pid: Some(pid).filter(|pid| *pid > 0),
+    };
+    (window != FocusedWindow::default()).then_some(window)
+}
+
+struct FocusAgentInterface {
+    window_tx: WindowSender,
+}
+
+#[interface(name = "io.github.ilya_zlobintsev.LACT.FocusAgent")]
+impl FocusAgentInterface {
+    /// Empty strings and a PID of 0 can be used for unknown values
+    async fn window_focused(&self, app_id: &str, wm_class: &str, pid: i32) {
+        let _ = self
+            .window_tx
+            .send(focused_window(app_id, wm_class, pid))
+            .await;
+    }
+
+    async fn focus_lost(&self) {
+        let _ = self.window_tx.send(None).await;
+    }
+}
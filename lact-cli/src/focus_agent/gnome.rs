@@ -0,0 +1,79 @@
+use super::{WindowSender, focused_window};
+use anyhow::Context;
+use futures::StreamExt;
+use lact_schema::FocusedWindow;
+use std::collections::HashMap;
+use zbus::{Connection, proxy, zvariant::OwnedValue};
+
+pub async fn start(conn: &Connection, window_tx: WindowSender) -> anyhow::Result<()> {
+    let introspect = IntrospectProxy::new(conn)
+        .await
+        .context("Could not connect to GNOME Shell")?;
+
+    let window = get_focused_window(&introspect).await.context(
+        "Could not get windows from GNOME Shell, \
+        access can be allowed with `gsettings set org.gnome.shell introspect true`",
+    )?;
+    window_tx.send(window).await?;
+
+    // The focused app is reported as a running applications change
+    let mut changes = futures::stream::select(
+        introspect.receive_windows_changed().await?.map(|_| ()),
+        introspect
+            .receive_running_applications_changed()
+            .await?
+            .map(|_| ()),
+    );
+
+    tokio::spawn(async move {
+        while changes.next().await.is_some() {
+            match get_focused_window(&introspect).await {
+                Ok(window) => {
+                    if window_tx.send(window).await.is_err() {
+                        break;
+                    }
+                }
+                Err(err) => eprintln!("Could not get windows from GNOME Shell: {err}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn get_focused_window(
+    introspect: &IntrospectProxy<'_>,
+) -> zbus::Result<Option<FocusedWindow>> {
+    let windows = introspect.get_windows().await?;
+
+    let window = windows.into_values().find(|properties| {
+        properties
+            .get("has-focus")
+            .and_then(|value| value.downcast_ref::<bool>().ok())
+            .unwrap_or(false)
+    });
+
+    Ok(window.and_then(|properties| {
+        let app_id = properties
+            .get("app-id")
+            .and_then(|value| value.downcast_ref::<String>().ok())
+            .unwrap_or_default();
+        // GNOME reports the desktop file name
+        focused_window(app_id.trim_end_matches(".desktop"), "", 0)
+    }))
+}
+
+#[proxy(
+    interface = "org.gnome.Shell.Introspect",
+    default_service = "org.gnome.Shell.Introspect",
+    default_path = "/org/gnome/Shell/Introspect"
+)]
+trait Introspect {
+    fn get_windows(&self) -> zbus::Result<HashMap<u64, HashMap<String, OwnedValue>>>;
+
+    #[zbus(signal)]
+    fn windows_changed(&self) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn running_applications_changed(&self) -> zbus::Result<()>;
+}
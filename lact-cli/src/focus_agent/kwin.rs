@@ -0,0 +1,93 @@
+//! KWin has no API for the active window, so a script is loaded which reports it to the agent's D-Bus interface
+use super::{DBUS_NAME, DBUS_PATH};
+use anyhow::{Context, bail};
+use std::{env, io::Write};
+use tempfile::NamedTempFile;
+use zbus::{Connection, proxy};
+
+const SCRIPT_NAME: &str = "lact-focus-agent";
+
+/// Supports both KWin 6 (`window`) and KWin 5 (`client`) APIs
+const SCRIPT: &str = r#"
+function report(window) {
+    if (window) {
+        callDBus("{name}", "{path}", "{name}", "WindowFocused",
+            window.desktopFileName || "", String(window.resourceClass || ""), window.pid || 0);
+    } else {
+        callDBus("{name}", "{path}", "{name}", "FocusLost");
+    }
+}
+
+const activated = workspace.windowActivated || workspace.clientActivated;
+activated.connect(report);
+report(workspace.activeWindow || workspace.activeClient);
+"#;
+
+/// Returns the script file, which has to be kept until the script is unloaded
+pub async fn load_script(conn: &Connection) -> anyhow::Result<NamedTempFile> {
+    let scripting = KWinScriptingProxy::new(conn)
+        .await
+        .context("Could not connect to KWin")?;
+
+    // A script might be left over if the agent did not exit cleanly
+    scripting.unload_script(SCRIPT_NAME).await?;
+
+    // The file is created with a random name in a directory only accessible by the user when possible,
+    // so other users can't replace the script
+    let script_dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, Into::into);
+    let mut script_file = tempfile::Builder::new()
+        .prefix(SCRIPT_NAME)
+        .suffix(".js")
+        .tempfile_in(script_dir)
+        .context("Could not create KWin script file")?;
+    let script = SCRIPT
+        .replace("{name}", DBUS_NAME)
+        .replace("{path}", DBUS_PATH);
+    script_file
+        .write_all(script.as_bytes())
+        .context("Could not write KWin script")?;
+
+    let script_id = scripting
+        .load_script(&script_file.path().to_string_lossy(), SCRIPT_NAME)
+        .await
+        .context("Could not load KWin script")?;
+
+    // Script objects are at `/Scripting/Script<id>` on KWin 6 and at `/<id>` on KWin 5
+    for path in [
+        format!("/Scripting/Script{script_id}"),
+        format!("/{script_id}"),
+    ] {
+        let script = KWinScriptProxy::builder(conn).path(path)?.build().await?;
+        if script.run().await.is_ok() {
+            return Ok(script_file);
+        }
+    }
+
+    bail!("Could not run KWin script")
+}
+
+pub async fn unload_script(conn: &Connection) -> anyhow::Result<()> {
+    KWinScriptingProxy::new(conn)
+        .await?
+        .unload_script(SCRIPT_NAME)
+        .await?;
+    Ok(())
+}
+
+#[proxy(
+    interface = "org.kde.kwin.Scripting",
+    default_service = "org.kde.KWin",
+    default_path = "/Scripting"
+)]
+trait KWinScripting {
+    #[zbus(name = "loadScript")]
+    fn load_script(&self, file_path: &str, plugin_name: &str) -> zbus::Result<i32>;
+
+    #[zbus(name = "unloadScript")]
+    fn unload_script(&self, plugin_name: &str) -> zbus::Result<bool>;
+}
+
+#[proxy(interface = "org.kde.kwin.Script", default_service = "org.kde.KWin")]
+trait KWinScript {
+    fn run(&self) -> zbus::Result<()>;
+}
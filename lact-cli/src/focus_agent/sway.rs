@@ -0,0 +1,96 @@
+use super::{WindowSender, focused_window};
+use anyhow::{Context, bail};
+use lact_schema::FocusedWindow;
+use serde_json::Value;
+use std::env;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+const IPC_MAGIC: &[u8] = b"i3-ipc";
+const IPC_SUBSCRIBE: u32 = 2;
+
+pub async fn start(window_tx: WindowSender) -> anyhow::Result<()> {
+    let socket_path = env::var_os("SWAYSOCK").context("SWAYSOCK is not set")?;
+    let mut stream = UnixStream::connect(socket_path)
+        .await
+        .context("Could not connect to sway")?;
+
+    send_message(&mut stream, IPC_SUBSCRIBE, br#"["window"]"#).await?;
+    let reply = read_message(&mut stream).await?;
+    if serde_json::from_slice::<Value>(&reply)?["success"] != Value::Bool(true) {
+        bail!("Could not subscribe to sway window events");
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let payload = match read_message(&mut stream).await {
+                Ok(payload) => payload,
+                Err(err) => {
+                    eprintln!("Could not read sway event: {err:#}");
+                    break;
+                }
+            };
+
+            let Ok(event) = serde_json::from_slice::<Value>(&payload) else {
+                continue;
+            };
+            if event["change"] != "focus" {
+                continue;
+            }
+
+            if window_tx
+                .send(parse_container(&event["container"]))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Native Wayland windows have an app id, Xwayland windows have a WM class
+fn parse_container(container: &Value) -> Option<FocusedWindow> {
+    focused_window(
+        container["app_id"].as_str().unwrap_or_default(),
+        container["window_properties"]["class"]
+            .as_str()
+            .unwrap_or_default(),
+        container["pid"]
+            .as_i64()
+            .and_then(|pid| i32::try_from(pid).ok())
+            .unwrap_or_default(),
+    )
+}
+
+async fn send_message(
+    stream: &mut UnixStream,
+    message_type: u32,
+    payload: &[u8],
+) -> anyhow::Result<()> {
+    let mut message = IPC_MAGIC.to_vec();
+    message.extend_from_slice(&u32::try_from(payload.len())?.to_ne_bytes());
+    message.extend_from_slice(&message_type.to_ne_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message).await?;
+    Ok(())
+}
+
+/// Returns the payload of the message, the type is not needed as only window events are subscribed to
+async fn read_message(stream: &mut UnixStream) -> anyhow::Result<Vec<u8>> {
+    let mut header = [0; IPC_MAGIC.len() + 8];
+    stream.read_exact(&mut header).await?;
+    if !header.starts_with(IPC_MAGIC) {
+        bail!("Invalid sway IPC message");
+    }
+
+    let length = u32::from_ne_bytes(header[IPC_MAGIC.len()..IPC_MAGIC.len() + 4].try_into()?);
+
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload).await?;
+    Ok(payload)
+}
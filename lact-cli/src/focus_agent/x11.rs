@@ -0,0 +1,134 @@
+use super::WindowSender;
+use anyhow::Context;
+use lact_schema::FocusedWindow;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        Event,
+        xproto::{Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window},
+    },
+    rust_connection::RustConnection,
+};
+
+struct Atoms {
+    net_active_window: Atom,
+    net_wm_pid: Atom,
+}
+
+pub fn start(window_tx: WindowSender) -> anyhow::Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).context("Could not connect to the X server")?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let atoms = Atoms {
+        net_active_window: intern_atom(&conn, "_NET_ACTIVE_WINDOW")?,
+        net_wm_pid: intern_atom(&conn, "_NET_WM_PID")?,
+    };
+
+    // The window manager updates `_NET_ACTIVE_WINDOW` on the root window whenever the focus changes
+    conn.change_window_attributes(
+        root,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )?
+    .check()
+    .context("Could not listen to root window changes")?;
+
+    // The connection is blocking, so events are waited for on a separate thread
+    tokio::task::spawn_blocking(move || {
+        let send_active_window = || {
+            let window = active_window(&conn, root, &atoms).unwrap_or_else(|err| {
+                eprintln!("Could not get the active window: {err:#}");
+                None
+            });
+            window_tx.blocking_send(window).is_ok()
+        };
+
+        if !send_active_window() {
+            return;
+        }
+
+        loop {
+            match conn.wait_for_event() {
+                Ok(Event::PropertyNotify(event)) if event.atom == atoms.net_active_window => {
+                    if !send_active_window() {
+                        return;
+                    }
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    eprintln!("Lost the connection to the X server: {err}");
+                    break;
+                }
+            }
+        }
+
+        eprintln!("Focus changes are no longer reported");
+    });
+
+    Ok(())
+}
+
+fn intern_atom(conn: &RustConnection, name: &str) -> anyhow::Result<Atom> {
+    Ok(conn
+        .intern_atom(false, name.as_bytes())?
+        .reply()
+        .with_context(|| format!("Could not get atom {name}"))?
+        .atom)
+}
+
+fn active_window(
+    conn: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+) -> anyhow::Result<Option<FocusedWindow>> {
+    let window = conn
+        .get_property(false, root, atoms.net_active_window, AtomEnum::WINDOW, 0, 1)?
+        .reply()?
+        .value32()
+        .and_then(|mut values| values.next())
+        .filter(|window| *window != x11rb::NONE);
+    let Some(window) = window else {
+        return Ok(None);
+    };
+
+    let wm_class = conn
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+        .reply()
+        .with_context(|| format!("Could not get the class of window {window:#x}"))?;
+    let pid = conn
+        .get_property(false, window, atoms.net_wm_pid, AtomEnum::CARDINAL, 0, 1)?
+        .reply()
+        .with_context(|| format!("Could not get the pid of window {window:#x}"))?;
+
+    Ok(Some(FocusedWindow {
+        app_id: None,
+        wm_class: parse_wm_class(&wm_class.value),
+        pid: pid.value32().and_then(|mut values| values.next()),
+    }))
+}
+
+/// `WM_CLASS` consists of the instance name and the class, both null-terminated
+fn parse_wm_class(value: &[u8]) -> Option<String> {
+    value
+        .split(|byte| *byte == 0)
+        .rfind(|part| !part.is_empty())
+        .map(|class| String::from_utf8_lossy(class).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_wm_class;
+
+    #[test]
+    fn parse_x11_wm_class() {
+        assert_eq!(
+            Some("steam_app_1091500".to_owned()),
+            parse_wm_class(b"steam_app_1091500\0steam_app_1091500\0")
+        );
+        assert_eq!(
+            Some("firefox".to_owned()),
+            parse_wm_class(b"Navigator\0firefox\0")
+        );
+        assert_eq!(Some("Alacritty".to_owned()), parse_wm_class(b"Alacritty"));
+        assert_eq!(None, parse_wm_class(b""));
+    }
+}
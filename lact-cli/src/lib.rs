@@ -1,13 +1,15 @@
+mod focus_agent;
 mod subcommands;
 
 use crate::subcommands::{
-    current_auto_switch, current_profile, detach, info, list_gpus, list_profiles, power_limit,
-    reattach, set_auto_switch, set_profile, snapshot, stats,
+    calibrate_fan, config_diff, config_history, config_rollback, current_auto_switch,
+    current_profile, detach, export_profile, history, import_profile, info, list_gpus,
+    list_profiles, power_limit, reattach, set_auto_switch, set_profile, snapshot, stats,
 };
 use anyhow::{Context, Result, bail};
 use lact_client::DaemonClient;
 use lact_schema::{
-    args::cli::{CliArgs, CliCommand, ProfileAutoSwitchCommand, ProfileCommand},
+    args::cli::{CliArgs, CliCommand, ConfigCommand, ProfileAutoSwitchCommand, ProfileCommand},
     clean_gpu_name,
     config::GpuConfig,
     request::ConfirmCommand,
@@ -30,8 +32,10 @@ pub fn run(args: CliArgs) -> Result<()> {
             CliCommand::List => list_gpus(ctx).await,
             CliCommand::Info => info(ctx).await,
             CliCommand::Stats => stats(ctx).await,
+            CliCommand::History(history_args) => history(history_args, ctx).await,
             CliCommand::Snapshot => snapshot(ctx).await,
             CliCommand::PowerLimit { cmd } => power_limit(ctx, cmd.as_ref()).await,
+            CliCommand::CalibrateFan => calibrate_fan(ctx).await,
             CliCommand::Profile(profile_args) => match &profile_args.subcommand {
                 None => current_profile(profile_args, ctx).await,
                 Some(profile_subcommand) => match profile_subcommand {
@@ -40,6 +44,8 @@ pub fn run(args: CliArgs) -> Result<()> {
                     ProfileCommand::Set(set_profile_args) => {
                         set_profile(set_profile_args, ctx).await
                     }
+                    ProfileCommand::Export(export_args) => export_profile(export_args, ctx).await,
+                    ProfileCommand::Import(import_args) => import_profile(import_args, ctx).await,
                     ProfileCommand::AutoSwitch(auto_switch_args) => {
                         match &auto_switch_args.subcommand {
                             None => current_auto_switch(auto_switch_args, ctx).await,
@@ -58,8 +64,14 @@ pub fn run(args: CliArgs) -> Result<()> {
                     }
                 },
             },
+            CliCommand::Config(config_args) => match &config_args.subcommand {
+                ConfigCommand::History => config_history(ctx).await,
+                ConfigCommand::Diff { from, to } => config_diff(ctx, *from, *to).await,
+                ConfigCommand::Rollback { revision } => config_rollback(ctx, *revision).await,
+            },
             CliCommand::Detach => detach(ctx).await,
             CliCommand::Reattach => reattach(ctx).await,
+            CliCommand::FocusAgent(agent_args) => focus_agent::run(&ctx.client, agent_args).await,
         }
     })
 }
@@ -2,9 +2,20 @@ use crate::CliContext;
 use anyhow::{Context, Result};
 use lact_schema::{
     FanControlMode,
-    args::cli::{PowerLimitCmd, ProfileArgs, ProfileAutoSwitchArgs, SetProfileArgs},
+    args::cli::{
+        ExportProfileArgs, HistoryArgs, ImportProfileArgs, PowerLimitCmd, ProfileArgs,
+        ProfileAutoSwitchArgs, SetProfileArgs,
+    },
+    export::ProfileExport,
+    history::StatType,
+    request::ConfirmCommand,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
 };
-use std::fmt::Write;
 
 const PROFILE_DEFAULT: &str = "Default";
 
@@ -129,6 +140,7 @@ pub async fn stats(ctx: CliContext<'_>) -> Result<()> {
             match stats.fan.control_mode {
                 Some(FanControlMode::Curve) => "Curve",
                 Some(FanControlMode::Static) => "Static",
+                Some(FanControlMode::Pid) => "PID",
                 None => panic!("Invalid fan control config"),
             }
         } else {
@@ -136,9 +148,110 @@ pub async fn stats(ctx: CliContext<'_>) -> Result<()> {
         }
     );
 
+    if let Some(reason) = &stats.fan.failsafe {
+        println!("Fan Control Failsafe: {reason}");
+    }
+
+    Ok(())
+}
+
+pub async fn history(args: &HistoryArgs, ctx: CliContext<'_>) -> Result<()> {
+    let id = ctx.current_gpu_id().await?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let since = i64::try_from(now.as_millis())?
+        .saturating_sub(i64::try_from(args.window)?.saturating_mul(1000));
+
+    let samples = ctx
+        .client
+        .get_stats_history(&id, Some(since), vec![])
+        .await?;
+
+    let gpu_line = format!("GPU {id} (last {} seconds):", args.window);
+    println!("{gpu_line}");
+    println!("{}", "=".repeat(gpu_line.len()));
+
+    if samples.is_empty() {
+        println!("No stats were recorded in this time window");
+        return Ok(());
+    }
+
+    let mut summaries = BTreeMap::<StatType, StatSummary>::new();
+    let mut throttled_samples = 0;
+
+    for sample in &samples {
+        for (stat_type, value) in &sample.values {
+            summaries
+                .entry(stat_type.clone())
+                .or_insert_with(|| StatSummary::new(*value))
+                .add(*value);
+        }
+
+        if !sample.throttling.is_empty() {
+            throttled_samples += 1;
+        }
+    }
+
+    let names: Vec<String> = summaries
+        .keys()
+        .map(|stat_type| stat_type.display().into_owned())
+        .collect();
+    let name_width = names.iter().map(String::len).max().unwrap_or_default();
+
+    println!(
+        "{:name_width$}  {:>10} {:>10} {:>10}",
+        "", "Min", "Avg", "Max"
+    );
+    for (name, (stat_type, summary)) in names.iter().zip(&summaries) {
+        let precision = stat_type.precision();
+        println!(
+            "{name:name_width$}  {:>10.precision$} {:>10.precision$} {:>10.precision$} {}",
+            summary.min,
+            summary.average(),
+            summary.max,
+            stat_type.metric(),
+        );
+    }
+
+    println!();
+    println!("Samples: {}", samples.len());
+    println!(
+        "Throttling: {:.0}% of samples",
+        throttled_samples as f64 / samples.len() as f64 * 100.0
+    );
+
     Ok(())
 }
 
+struct StatSummary {
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: usize,
+}
+
+impl StatSummary {
+    fn new(value: f64) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn average(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
 pub async fn snapshot(ctx: CliContext<'_>) -> Result<()> {
     let path = ctx.client.generate_debug_snapshot().await?;
     println!("Generated debug snapshot in {path}");
@@ -173,6 +286,104 @@ pub async fn power_limit(ctx: CliContext<'_>, cmd: Option<&PowerLimitCmd>) -> Re
     Ok(())
 }
 
+pub async fn calibrate_fan(ctx: CliContext<'_>) -> Result<()> {
+    let id = ctx.current_gpu_id().await?;
+    println!("Calibrating fan, this may take a minute...");
+    let calibration = ctx.client.calibrate_fan(&id).await?;
+
+    for point in &calibration.points {
+        let percent = f32::from(point.pwm) / f32::from(u8::MAX) * 100.0;
+        println!("{percent:>5.1}%: {} RPM", point.rpm);
+    }
+
+    match calibration.stall_pwm {
+        Some(pwm) => println!("Fan stops below PWM {pwm}"),
+        None => println!("Fan does not stop at low speeds"),
+    }
+    if let Some(pwm) = calibration.start_pwm {
+        println!("Fan starts spinning at PWM {pwm}");
+    }
+
+    if calibration.apply_timer.is_some() {
+        ctx.client
+            .confirm_pending_config(ConfirmCommand::Confirm)
+            .await
+            .context("Failed to confirm config")?;
+        println!("Saved the minimum fan speeds to the fan control settings");
+    } else if calibration.stall_pwm.is_some() || calibration.start_pwm.is_some() {
+        println!("The minimum fan speeds could not be saved, see the daemon logs");
+    }
+
+    Ok(())
+}
+
+pub async fn config_history(ctx: CliContext<'_>) -> Result<()> {
+    let id = ctx.current_gpu_id().await?;
+    let revisions = ctx.client.list_config_revisions(Some(&id)).await?;
+
+    if revisions.is_empty() {
+        println!("No config changes were recorded for this GPU");
+        return Ok(());
+    }
+
+    for revision in revisions {
+        let time = jiff::Timestamp::from_millisecond(revision.timestamp)?
+            .to_zoned(jiff::tz::TimeZone::system())
+            .strftime("%Y-%m-%d %H:%M:%S");
+
+        print!("{}: {time} {}", revision.revision, revision.source.request);
+        if let Some(profile) = &revision.profile {
+            print!(" in profile '{profile}'");
+        }
+        if let Some(client) = &revision.source.client {
+            print!(" by {client}");
+        }
+        if let Some(uid) = revision.source.uid {
+            print!(" (uid {uid})");
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+pub async fn config_diff(ctx: CliContext<'_>, from: u64, to: Option<u64>) -> Result<()> {
+    let diff = ctx.client.diff_config_revisions(from, to).await?;
+
+    if diff.is_empty() {
+        println!("No changes");
+    }
+
+    for entry in diff {
+        println!(
+            "{}: {} -> {}",
+            entry.path,
+            fmt_diff_value(entry.old),
+            fmt_diff_value(entry.new)
+        );
+    }
+
+    Ok(())
+}
+
+fn fmt_diff_value(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| "<unset>".to_owned(), |value| value.to_string())
+}
+
+pub async fn config_rollback(ctx: CliContext<'_>, revision: u64) -> Result<()> {
+    ctx.client
+        .rollback_config(revision)
+        .await
+        .context("Failed to roll back config")?;
+    ctx.client
+        .confirm_pending_config(ConfirmCommand::Confirm)
+        .await
+        .context("Failed to confirm config")?;
+
+    println!("Rolled back to revision {revision}");
+    Ok(())
+}
+
 pub async fn list_profiles(_: &ProfileArgs, ctx: CliContext<'_>) -> Result<()> {
     let profiles_info = ctx.client.list_profiles(false).await?;
     println!("{}", PROFILE_DEFAULT);
@@ -207,6 +418,40 @@ pub async fn set_profile(args: &SetProfileArgs, ctx: CliContext<'_>) -> Result<(
     Ok(())
 }
 
+pub async fn export_profile(args: &ExportProfileArgs, ctx: CliContext<'_>) -> Result<()> {
+    let name = args.name.trim();
+    let name = (name.to_lowercase() != PROFILE_DEFAULT.to_lowercase()).then(|| name.to_owned());
+
+    let export = ctx.client.export_profile(name).await?;
+    fs::write(&args.path, export.to_json()?)
+        .with_context(|| format!("Could not write {}", args.path.display()))?;
+
+    println!(
+        "Exported profile '{}' to {}",
+        export.name,
+        args.path.display()
+    );
+    Ok(())
+}
+
+pub async fn import_profile(args: &ImportProfileArgs, ctx: CliContext<'_>) -> Result<()> {
+    let data = fs::read_to_string(&args.path)
+        .with_context(|| format!("Could not read {}", args.path.display()))?;
+    let export = ProfileExport::from_json(&data)?;
+    let name = args.name.clone().unwrap_or_else(|| export.name.clone());
+
+    let unmatched = ctx
+        .client
+        .import_profile(Some(name.clone()), export)
+        .await?;
+    for model_id in unmatched {
+        eprintln!("No GPU of model {model_id} found, its settings were skipped");
+    }
+
+    println!("Imported profile '{name}'");
+    Ok(())
+}
+
 pub async fn current_auto_switch(_: &ProfileAutoSwitchArgs, ctx: CliContext<'_>) -> Result<()> {
     let auto_switch = ctx.client.list_profiles(false).await?.auto_switch;
     if auto_switch {
@@ -2,7 +2,11 @@ pub mod tcp;
 pub mod unix;
 
 use anyhow::anyhow;
-use futures::future::BoxFuture;
+use futures::{
+    StreamExt,
+    future::BoxFuture,
+    stream::{self, LocalBoxStream},
+};
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 
 pub trait DaemonConnection {
@@ -10,6 +14,12 @@ pub trait DaemonConnection {
 
     /// Establish a new connection to the same service
     fn new_connection(&self) -> BoxFuture<'_, anyhow::Result<Box<dyn DaemonConnection>>>;
+
+    /// Send a request and turn the connection into a stream of responses to it
+    fn into_stream(
+        self: Box<Self>,
+        payload: String,
+    ) -> LocalBoxStream<'static, anyhow::Result<String>>;
 }
 
 async fn request(
@@ -28,3 +38,30 @@ async fn request(
 
     Ok(response_payload)
 }
+
+fn response_stream(
+    socket: BufReader<impl AsyncRead + AsyncWrite + Unpin + 'static>,
+    payload: String,
+) -> LocalBoxStream<'static, anyhow::Result<String>> {
+    stream::unfold(Some((socket, Some(payload))), |state| async move {
+        let (mut socket, payload) = state?;
+
+        if let Some(payload) = payload {
+            let write_result = async {
+                socket.write_all(payload.as_bytes()).await?;
+                socket.write_all(b"\n").await
+            };
+            if let Err(err) = write_result.await {
+                return Some((Err(anyhow::Error::from(err)), None));
+            }
+        }
+
+        let mut response_payload = String::new();
+        match socket.read_line(&mut response_payload).await {
+            Ok(0) => None,
+            Ok(_) => Some((Ok(response_payload), Some((socket, None)))),
+            Err(err) => Some((Err(anyhow::Error::from(err)), None)),
+        }
+    })
+    .boxed_local()
+}
@@ -1,23 +1,96 @@
-use super::{DaemonConnection, request};
+use super::{DaemonConnection, request, response_stream};
 use anyhow::Context;
-use futures::future::BoxFuture;
+use futures::{future::BoxFuture, stream::LocalBoxStream};
+use lact_schema::{Request, Response};
+use std::{path::PathBuf, sync::Arc};
 use tokio::{
-    io::BufReader,
-    net::{TcpStream, ToSocketAddrs},
+    io::{AsyncRead, AsyncWrite, BufReader},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        ClientConfig, RootCertStore,
+        crypto::ring,
+        pki_types::{CertificateDer, ServerName, pem::PemObject},
+    },
 };
 use tracing::info;
 
+trait TcpStreamInner: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> TcpStreamInner for T {}
+
+#[derive(Debug, Clone, Default)]
+pub struct TcpConnectOptions {
+    /// Token to authenticate with. Without it, the daemon only allows the requests of its
+    /// `unauthenticated` access class, which defaults to read-only monitoring requests.
+    pub token: Option<String>,
+    pub tls: Option<TcpTlsOptions>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TcpTlsOptions {
+    /// CA certificate to verify the daemon with, for self-signed certificates.
+    /// The built-in list of root certificates is used if not specified.
+    pub ca_path: Option<PathBuf>,
+    /// Name to verify the certificate against. The host part of the address is used if not specified.
+    pub server_name: Option<String>,
+}
+
 pub struct TcpConnection {
-    inner: BufReader<TcpStream>,
+    inner: BufReader<Box<dyn TcpStreamInner>>,
+    addr: String,
+    options: TcpConnectOptions,
 }
 
 impl TcpConnection {
-    pub async fn connect(addr: impl ToSocketAddrs) -> anyhow::Result<Box<Self>> {
+    pub async fn connect(addr: &str, options: TcpConnectOptions) -> anyhow::Result<Box<Self>> {
         info!("connecting to remote TCP service");
-        let inner = TcpStream::connect(addr).await?;
-        Ok(Box::new(Self {
+        let stream = TcpStream::connect(addr).await?;
+
+        let inner: Box<dyn TcpStreamInner> = match &options.tls {
+            Some(tls_options) => {
+                let connector = build_tls_connector(tls_options)?;
+                let server_name = tls_options
+                    .server_name
+                    .clone()
+                    .unwrap_or_else(|| address_host(addr).to_owned());
+                let server_name =
+                    ServerName::try_from(server_name).context("Invalid TLS server name")?;
+
+                let stream = connector
+                    .connect(server_name, stream)
+                    .await
+                    .context("TLS handshake failed")?;
+                Box::new(stream)
+            }
+            None => Box::new(stream),
+        };
+
+        let mut connection = Self {
             inner: BufReader::new(inner),
-        }))
+            addr: addr.to_owned(),
+            options,
+        };
+
+        if let Some(token) = connection.options.token.clone() {
+            connection.authenticate(token).await?;
+        }
+
+        Ok(Box::new(connection))
+    }
+
+    async fn authenticate(&mut self, token: String) -> anyhow::Result<()> {
+        let payload = serde_json::to_string(&Request::Authenticate { token })?;
+        let response_payload = request(&mut self.inner, &payload).await?;
+
+        let response: Response<()> = serde_json::from_str(&response_payload)
+            .context("Could not deserialize authentication response")?;
+        match response {
+            Response::Ok(()) => Ok(()),
+            Response::Error(err) => Err(anyhow::Error::new(err).context("Could not authenticate")),
+        }
     }
 }
 
@@ -28,13 +101,45 @@ impl DaemonConnection for TcpConnection {
 
     fn new_connection(&self) -> BoxFuture<'_, anyhow::Result<Box<dyn DaemonConnection>>> {
         Box::pin(async {
-            let peer_addr = self
-                .inner
-                .get_ref()
-                .peer_addr()
-                .context("Could not read peer address")?;
-
-            Ok(Self::connect(peer_addr).await? as Box<dyn DaemonConnection>)
+            Ok(Self::connect(&self.addr, self.options.clone()).await? as Box<dyn DaemonConnection>)
         })
     }
+
+    fn into_stream(
+        self: Box<Self>,
+        payload: String,
+    ) -> LocalBoxStream<'static, anyhow::Result<String>> {
+        response_stream(self.inner, payload)
+    }
+}
+
+fn build_tls_connector(options: &TcpTlsOptions) -> anyhow::Result<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+    match &options.ca_path {
+        Some(ca_path) => {
+            let certs = CertificateDer::pem_file_iter(ca_path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .with_context(|| {
+                    format!("Could not read CA certificate from {}", ca_path.display())
+                })?;
+            for cert in certs {
+                roots.add(cert).context("Invalid CA certificate")?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Could not set up TLS protocol versions")?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Strips the port and IPv6 brackets from an address
+fn address_host(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
 }
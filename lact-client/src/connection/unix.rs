@@ -1,6 +1,6 @@
-use super::{DaemonConnection, request};
+use super::{DaemonConnection, request, response_stream};
 use anyhow::Context;
-use futures::future::BoxFuture;
+use futures::{future::BoxFuture, stream::LocalBoxStream};
 use std::os::unix::net::UnixStream as StdUnixStream;
 use std::path::Path;
 use tokio::{io::BufReader, net::UnixStream};
@@ -55,4 +55,11 @@ impl DaemonConnection for UnixConnection {
             Ok(Self::connect(path).await? as Box<dyn DaemonConnection>)
         })
     }
+
+    fn into_stream(
+        self: Box<Self>,
+        payload: String,
+    ) -> LocalBoxStream<'static, anyhow::Result<String>> {
+        response_stream(self.inner, payload)
+    }
 }
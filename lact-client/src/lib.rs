@@ -4,17 +4,24 @@ mod macros;
 
 pub use lact_schema as schema;
 use lact_schema::{
-    DeviceApiInfo, DisplaysInfo, Pong, ProcessList, ProfileRule,
+    ConfigRecovery, DeviceApiInfo, DisplaysInfo, FanCalibration, FocusedWindow, Pong, ProcessList,
+    ProfileRule,
     config::{GpuConfig, Profile, ProfileHooks},
+    export::ProfileExport,
+    history::{StatType, StatsHistorySample},
+    revisions::{ConfigDiffEntry, ConfigRevision},
+    stability::{StabilityTestOptions, StabilityTestStatus},
 };
 
 use amdgpu_sysfs::gpu_handle::power_profile_mode::PowerProfileModesTable;
 use anyhow::Context;
+pub use connection::tcp::{TcpConnectOptions, TcpTlsOptions};
 use connection::{DaemonConnection, tcp::TcpConnection, unix::UnixConnection};
+use futures::{StreamExt, stream::LocalBoxStream};
 use nix::unistd::getuid;
 use schema::{
-    ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, PowerStates, ProfilesInfo, Request,
-    Response, SystemInfo,
+    ClocksInfo, DaemonEvent, DeviceInfo, DeviceListEntry, DeviceStats, PowerStates, ProfilesInfo,
+    Request, Response, SystemInfo,
     request::{ConfirmCommand, ProfileBase, SetClocksCommand},
 };
 use serde::de::DeserializeOwned;
@@ -22,10 +29,7 @@ use std::{
     fmt, future::Future, io, os::unix::net::UnixStream, path::PathBuf, pin::Pin, rc::Rc,
     time::Duration,
 };
-use tokio::{
-    net::ToSocketAddrs,
-    sync::{Mutex, broadcast},
-};
+use tokio::sync::{Mutex, broadcast};
 use tracing::{error, info, trace};
 
 const STATUS_MSG_CHANNEL_SIZE: usize = 16;
@@ -57,8 +61,8 @@ impl DaemonClient {
         })
     }
 
-    pub async fn connect_tcp(addr: impl ToSocketAddrs) -> anyhow::Result<Self> {
-        let stream = TcpConnection::connect(addr).await?;
+    pub async fn connect_tcp(addr: &str, options: TcpConnectOptions) -> anyhow::Result<Self> {
+        let stream = TcpConnection::connect(addr, options).await?;
 
         Ok(Self {
             stream: Rc::new(Mutex::new(stream)),
@@ -93,15 +97,7 @@ impl DaemonClient {
             trace!("sending request {request_payload}");
 
             match stream.request(&request_payload).await {
-                Ok(response_payload) => {
-                    let response: Response<T> = serde_json::from_str(&response_payload)
-                        .context("Could not deserialize response from daemon")?;
-                    match response {
-                        Response::Ok(data) => Ok(data),
-                        Response::Error(err) => Err(anyhow::Error::new(err)
-                            .context("Got error from daemon, end of client boundary")),
-                    }
-                }
+                Ok(response_payload) => parse_response(&response_payload),
                 Err(err) => {
                     let _ = self.status_tx.send(ConnectionStatusMsg::Disconnected);
 
@@ -171,11 +167,74 @@ impl DaemonClient {
     );
     request_with_id!(get_power_states, GetPowerStates, PowerStates);
     request_with_id!(reset_pmfw, ResetPmfw, u64);
+    request_with_id!(calibrate_fan, CalibrateFan, FanCalibration);
     request_with_id!(dump_vbios, VbiosDump, Vec<u8>);
     request_with_id!(get_process_list, ProcessList, ProcessList);
     request_with_id!(get_displays_info, DisplaysInfo, DisplaysInfo);
     request_with_id!(detach, DetachGpu, ());
     request_with_id!(reattach, ReattachGpu, ());
+    request_with_id!(
+        get_stability_test_status,
+        StabilityTestStatus,
+        Option<StabilityTestStatus>
+    );
+    request_with_id!(stop_stability_test, StopStabilityTest, ());
+    request_with_id!(get_config_recovery, ConfigRecovery, Option<ConfigRecovery>);
+
+    pub async fn start_stability_test(
+        &self,
+        id: &str,
+        options: StabilityTestOptions,
+    ) -> anyhow::Result<()> {
+        self.make_request(Request::StartStabilityTest { id, options })
+            .await
+    }
+
+    pub async fn get_stats_history(
+        &self,
+        id: &str,
+        since: Option<i64>,
+        stats: Vec<StatType>,
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        self.make_request(Request::StatsHistory { id, since, stats })
+            .await
+    }
+
+    /// Subscribes to stats updates of the given GPU.
+    ///
+    /// This uses a separate connection to the daemon, which gets closed when the stream is dropped.
+    pub async fn subscribe_stats(
+        &self,
+        id: &str,
+        interval_ms: u64,
+    ) -> anyhow::Result<LocalBoxStream<'static, anyhow::Result<DeviceStats>>> {
+        self.subscribe(Request::SubscribeStats { id, interval_ms })
+            .await
+    }
+
+    /// Subscribes to daemon events, such as profile or configuration changes.
+    ///
+    /// This uses a separate connection to the daemon, which gets closed when the stream is dropped.
+    pub async fn subscribe_events(
+        &self,
+    ) -> anyhow::Result<LocalBoxStream<'static, anyhow::Result<DaemonEvent>>> {
+        self.subscribe(Request::SubscribeEvents).await
+    }
+
+    async fn subscribe<T: DeserializeOwned + 'static>(
+        &self,
+        request: Request<'_>,
+    ) -> anyhow::Result<LocalBoxStream<'static, anyhow::Result<T>>> {
+        let connection = self.stream.lock().await.new_connection().await?;
+
+        let request_payload = serde_json::to_string(&request)?;
+        trace!("sending subscription request {request_payload}");
+
+        let stream = connection
+            .into_stream(request_payload)
+            .map(|response_payload| parse_response::<T>(&response_payload?));
+        Ok(stream.boxed_local())
+    }
 
     pub async fn list_profiles(&self, include_state: bool) -> anyhow::Result<ProfilesInfo> {
         self.make_request(Request::ListProfiles { include_state })
@@ -196,6 +255,22 @@ impl DaemonClient {
             .await
     }
 
+    pub async fn export_profile(&self, name: Option<String>) -> anyhow::Result<ProfileExport> {
+        self.make_request(Request::ExportProfile { name }).await
+    }
+
+    pub async fn import_profile(
+        &self,
+        name: Option<String>,
+        profile: ProfileExport,
+    ) -> anyhow::Result<Vec<String>> {
+        self.make_request(Request::ImportProfile {
+            name,
+            profile: Box::new(profile),
+        })
+        .await
+    }
+
     pub async fn delete_profile(&self, name: String) -> anyhow::Result<()> {
         self.make_request(Request::DeleteProfile { name }).await
     }
@@ -218,6 +293,11 @@ impl DaemonClient {
             .await
     }
 
+    pub async fn set_focused_window(&self, window: Option<FocusedWindow>) -> anyhow::Result<()> {
+        self.make_request(Request::SetFocusedWindow { window })
+            .await
+    }
+
     pub async fn get_gpu_config(&self, id: &str) -> anyhow::Result<Option<GpuConfig>> {
         self.make_request(Request::GetGpuConfig { id }).await
     }
@@ -253,6 +333,27 @@ impl DaemonClient {
         self.make_request(Request::ConfirmPendingConfig(command))
             .await
     }
+
+    pub async fn list_config_revisions(
+        &self,
+        id: Option<&str>,
+    ) -> anyhow::Result<Vec<ConfigRevision>> {
+        self.make_request(Request::ListConfigRevisions { id }).await
+    }
+
+    pub async fn diff_config_revisions(
+        &self,
+        from: u64,
+        to: Option<u64>,
+    ) -> anyhow::Result<Vec<ConfigDiffEntry>> {
+        self.make_request(Request::DiffConfigRevisions { from, to })
+            .await
+    }
+
+    pub async fn rollback_config(&self, revision: u64) -> anyhow::Result<u64> {
+        self.make_request(Request::RollbackConfig { revision })
+            .await
+    }
 }
 
 impl fmt::Debug for DaemonClient {
@@ -263,6 +364,17 @@ impl fmt::Debug for DaemonClient {
     }
 }
 
+fn parse_response<T: DeserializeOwned>(payload: &str) -> anyhow::Result<T> {
+    let response: Response<T> =
+        serde_json::from_str(payload).context("Could not deserialize response from daemon")?;
+    match response {
+        Response::Ok(data) => Ok(data),
+        Response::Error(err) => {
+            Err(anyhow::Error::new(err).context("Got error from daemon, end of client boundary"))
+        }
+    }
+}
+
 fn get_socket_path() -> Option<PathBuf> {
     let root_path = PathBuf::from("/run/lactd.sock");
 
@@ -1,7 +1,10 @@
 use crate::server::gpu_controller::{GpuController, VENDOR_NVIDIA};
 use anyhow::Context;
 use indexmap::IndexMap;
-use lact_schema::config::{GpuConfig, Profile, ProfileHooks};
+use lact_schema::{
+    config::{GpuConfig, Profile, ProfileHooks},
+    request::RequestClass,
+};
 use nix::unistd::{Group, getuid};
 use notify::{RecommendedWatcher, Watcher};
 use serde::{Deserialize, Serialize};
@@ -23,6 +26,8 @@ const DEFAULT_ADMIN_GROUPS: [&str; 2] = ["wheel", "sudo"];
 const CONFIG_RELOAD_INTERVAL_MILLIS: u64 = 50;
 /// Period when config changes are ignored after LACT itself has edited the config
 const SELF_CONFIG_EDIT_PERIOD_MILLIS: u64 = 1000;
+/// Amount of known good settings kept for every GPU in every profile
+const KNOWN_GOOD_HISTORY_SIZE: usize = 5;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Config {
@@ -39,6 +44,12 @@ pub struct Config {
     pub current_profile: Option<Rc<str>>,
     #[serde(default)]
     pub auto_switch_profiles: bool,
+    /// Previous GPU settings that were used long enough without problems, oldest first
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub known_good: IndexMap<String, Vec<KnownGoodConfig>>,
+    /// GPU settings that were applied, but have not been used long enough to become known good
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unverified: Option<UnverifiedConfigs>,
 }
 
 impl Default for Config {
@@ -50,6 +61,8 @@ impl Default for Config {
             profiles: IndexMap::new(),
             current_profile: None,
             auto_switch_profiles: false,
+            known_good: IndexMap::new(),
+            unverified: None,
             version: 6,
         }
     }
@@ -68,7 +81,67 @@ pub struct Daemon {
     pub disable_clocks_cleanup: bool,
     pub disable_nvapi: Option<bool>,
     pub tcp_listen_address: Option<String>,
+    /// Tokens that TCP clients can authenticate with, keyed by client name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tcp_tokens: IndexMap<String, String>,
+    pub tcp_tls: Option<TcpTls>,
+    #[serde(default, skip_serializing_if = "AccessPolicy::is_empty")]
+    pub access: AccessPolicy,
     pub metrics: Option<Metrics>,
+    pub prometheus: Option<Prometheus>,
+    pub stats_history: Option<StatsHistory>,
+    #[serde(default, skip_serializing_if = "ResetRecovery::is_default")]
+    pub reset_recovery: ResetRecovery,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TcpTls {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// Maps clients to the most privileged class of requests they are allowed to use
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccessPolicy {
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub users: IndexMap<String, RequestClass>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub groups: IndexMap<String, RequestClass>,
+    /// Local users that don't match any of the rules and are not admins
+    pub other_users: Option<RequestClass>,
+    /// Keyed by the names in `tcp_tokens`
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tokens: IndexMap<String, RequestClass>,
+    #[serde(default = "default_unauthenticated_class")]
+    pub unauthenticated: RequestClass,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self {
+            users: IndexMap::new(),
+            groups: IndexMap::new(),
+            other_users: None,
+            tokens: IndexMap::new(),
+            unauthenticated: default_unauthenticated_class(),
+        }
+    }
+}
+
+impl AccessPolicy {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// If there are rules for non-admin local users, the socket needs to be accessible to everyone
+    pub fn has_local_rules(&self) -> bool {
+        !self.users.is_empty() || !self.groups.is_empty() || self.other_users.is_some()
+    }
+}
+
+fn default_unauthenticated_class() -> RequestClass {
+    RequestClass::Monitor
 }
 
 impl Default for Daemon {
@@ -88,8 +161,14 @@ impl Default for Daemon {
             admin_groups: vec![],
             disable_clocks_cleanup: false,
             tcp_listen_address: None,
+            tcp_tokens: IndexMap::new(),
+            tcp_tls: None,
+            access: AccessPolicy::default(),
             disable_nvapi: None,
             metrics: None,
+            prometheus: None,
+            stats_history: None,
+            reset_recovery: ResetRecovery::default(),
         }
     }
 }
@@ -246,7 +325,21 @@ impl Config {
 
     /// Gets the GPU configs according to the current profile. Returns an error if the current profile could not be found.
     pub fn gpus(&self) -> anyhow::Result<&IndexMap<String, GpuConfig>> {
-        match &self.current_profile {
+        self.profile_gpus(self.current_profile.as_deref())
+    }
+
+    /// Same as [`gpus`], but with a mutable reference
+    pub fn gpus_mut(&mut self) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
+        let profile = self.current_profile.clone();
+        self.profile_gpus_mut(profile.as_deref())
+    }
+
+    /// Gets the GPU configs of the given profile, or of the default profile if it's `None`
+    pub fn profile_gpus(
+        &self,
+        profile: Option<&str>,
+    ) -> anyhow::Result<&IndexMap<String, GpuConfig>> {
+        match profile {
             Some(profile) => {
                 let profile = self
                     .profiles
@@ -258,9 +351,12 @@ impl Config {
         }
     }
 
-    /// Same as [`gpus`], but with a mutable reference
-    pub fn gpus_mut(&mut self) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
-        match &self.current_profile {
+    /// Same as [`profile_gpus`], but with a mutable reference
+    fn profile_gpus_mut(
+        &mut self,
+        profile: Option<&str>,
+    ) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
+        match profile {
             Some(profile) => {
                 let profile = self
                     .profiles
@@ -292,20 +388,271 @@ impl Config {
         self.gpus.clear();
         self.profiles.clear();
         self.current_profile = None;
+        self.known_good.clear();
+        self.unverified = None;
+    }
+
+    /// Known good settings of a GPU in the given profile, oldest first
+    pub fn known_good_configs<'a>(
+        &'a self,
+        profile: Option<&'a str>,
+        id: &str,
+    ) -> impl DoubleEndedIterator<Item = &'a GpuConfig> {
+        self.known_good
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter(move |known_good| known_good.profile.as_deref() == profile)
+            .map(|known_good| &known_good.config)
+    }
+
+    /// Gets the last known good settings of a GPU in the current profile
+    pub fn last_known_good(&self, id: &str) -> Option<&GpuConfig> {
+        self.known_good_configs(self.current_profile.as_deref(), id)
+            .next_back()
+    }
+
+    pub fn push_known_good(&mut self, id: &str, config: GpuConfig) {
+        let profile = self.current_profile.clone();
+        let history = self.known_good.entry(id.to_owned()).or_default();
+        history.push(KnownGoodConfig {
+            timestamp: jiff::Timestamp::now().as_millisecond(),
+            profile: profile.clone(),
+            config,
+        });
+
+        // The history is limited separately for every profile
+        let profile_history_len = history
+            .iter()
+            .filter(|known_good| known_good.profile == profile)
+            .count();
+        if profile_history_len > KNOWN_GOOD_HISTORY_SIZE
+            && let Some(oldest) = history
+                .iter()
+                .position(|known_good| known_good.profile == profile)
+        {
+            history.remove(oldest);
+        }
+    }
+
+    /// Updates the list of settings that are not known good yet for the given GPUs. Returns whether it was changed.
+    pub fn update_unverified<'a>(
+        &mut self,
+        boot_id: &str,
+        ids: impl IntoIterator<Item = &'a String>,
+    ) -> anyhow::Result<bool> {
+        let gpus = self.gpus()?;
+        let unverified_gpus: IndexMap<String, GpuConfig> = ids
+            .into_iter()
+            .filter_map(|id| {
+                let gpu_config = gpus.get(id).cloned().unwrap_or_default();
+                (self.last_known_good(id) != Some(&gpu_config)).then(|| (id.clone(), gpu_config))
+            })
+            .collect();
+
+        let unverified = (!unverified_gpus.is_empty()).then(|| UnverifiedConfigs {
+            boot_id: boot_id.to_owned(),
+            profile: self.current_profile.clone(),
+            gpus: unverified_gpus,
+        });
+        let changed = unverified != self.unverified;
+        self.unverified = unverified;
+        Ok(changed)
+    }
+
+    /// Replaces settings that were applied in a previous boot, but did not become known good before it ended,
+    /// with the last known good ones of the same profile. Returns the ids of the GPUs whose settings were replaced.
+    pub fn apply_safe_mode(&mut self, boot_id: &str) -> anyhow::Result<Vec<String>> {
+        let Some(unverified) = self
+            .unverified
+            .take_if(|unverified| unverified.boot_id != boot_id)
+        else {
+            return Ok(vec![]);
+        };
+        let profile = unverified.profile.as_deref();
+
+        let mut recovered_ids = vec![];
+        for (id, unverified_config) in unverified.gpus {
+            let Some(known_good) = self.known_good_configs(profile, &id).next_back().cloned()
+            else {
+                continue;
+            };
+
+            let gpus = self.profile_gpus_mut(profile)?;
+            // Settings that were changed since then (e.g. by editing the config file) are not the ones that failed
+            if gpus.get(&id).cloned().unwrap_or_default() == unverified_config {
+                gpus.insert(id.clone(), known_good);
+                recovered_ids.push(id);
+            }
+        }
+
+        Ok(recovered_ids)
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KnownGoodConfig {
+    /// Unix timestamp in milliseconds of when the settings became known good
+    pub timestamp: i64,
+    /// Profile the settings belong to, `None` for the default profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Rc<str>>,
+    pub config: GpuConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnverifiedConfigs {
+    /// Id of the boot in which the settings were applied
+    pub boot_id: String,
+    /// Profile that was active when the settings were applied, `None` for the default profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Rc<str>>,
+    pub gpus: IndexMap<String, GpuConfig>,
+}
+
+#[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Metrics {
     pub collector_address: String,
+    /// How often metrics are uploaded, in seconds
     #[serde(default = "default_metrics_interval")]
     pub interval: u64,
+    /// How often stats are sampled, in seconds. Defaults to the upload interval.
+    pub sample_interval: Option<u64>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub headers: IndexMap<String, String>,
+    #[serde(default)]
+    pub encoding: MetricsEncoding,
+    #[serde(default)]
+    pub compression: MetricsCompression,
+    /// Maximum amount of samples kept while the collector is unreachable
+    #[serde(default = "default_metrics_buffer_size")]
+    pub buffer_size: usize,
+    /// Upper limit for the delay between retries, in seconds
+    #[serde(default = "default_metrics_max_retry_delay")]
+    pub max_retry_delay: u64,
+}
+
+impl Metrics {
+    pub fn sample_interval(&self) -> u64 {
+        self.sample_interval.unwrap_or(self.interval)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsEncoding {
+    #[default]
+    Json,
+    Protobuf,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsCompression {
+    #[default]
+    None,
+    Gzip,
 }
 
 fn default_metrics_interval() -> u64 {
     30
 }
 
+fn default_metrics_buffer_size() -> usize {
+    2880
+}
+
+fn default_metrics_max_retry_delay() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Prometheus {
+    pub listen_address: String,
+    /// How often the stats sample that scrapes are served from is refreshed, in seconds
+    #[serde(default = "default_prometheus_cache_duration")]
+    pub cache_duration: u64,
+}
+
+fn default_prometheus_cache_duration() -> u64 {
+    5
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StatsHistory {
+    /// How often stats are sampled, in milliseconds
+    #[serde(default = "default_stats_history_interval")]
+    pub interval_ms: u64,
+    /// How long samples are kept for, in seconds
+    #[serde(default = "default_stats_history_duration")]
+    pub duration: u64,
+    /// File to persist the history in, so that it survives daemon restarts
+    pub path: Option<PathBuf>,
+}
+
+fn default_stats_history_interval() -> u64 {
+    1000
+}
+
+fn default_stats_history_duration() -> u64 {
+    3600
+}
+
+/// Rollback of GPU settings after the GPU was reset repeatedly
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResetRecovery {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Amount of resets within `window` that trigger a rollback
+    #[serde(default = "default_reset_recovery_max_resets")]
+    pub max_resets: u32,
+    /// In seconds
+    #[serde(default = "default_reset_recovery_window")]
+    pub window: u64,
+    /// In seconds. Settings that have been used for this long without a reset are considered known good.
+    #[serde(default = "default_known_good_time")]
+    pub known_good_time: u64,
+    /// Apply the last known good settings on startup if the settings used in the previous boot did not become known good
+    #[serde(default = "default_true")]
+    pub safe_mode: bool,
+}
+
+impl Default for ResetRecovery {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_resets: default_reset_recovery_max_resets(),
+            window: default_reset_recovery_window(),
+            known_good_time: default_known_good_time(),
+            safe_mode: true,
+        }
+    }
+}
+
+impl ResetRecovery {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_reset_recovery_max_resets() -> u32 {
+    2
+}
+
+fn default_reset_recovery_window() -> u64 {
+    600
+}
+
+fn default_known_good_time() -> u64 {
+    600
+}
+
 pub fn start_watcher(config_last_saved: Rc<Cell<Instant>>) -> mpsc::UnboundedReceiver<Config> {
     let (config_tx, config_rx) = mpsc::unbounded_channel();
     let (event_tx, mut event_rx) = mpsc::channel(64);
@@ -393,7 +740,7 @@ impl notify::EventHandler for SenderEventHandler {
     }
 }
 
-fn get_path(filename: &str) -> PathBuf {
+pub(crate) fn get_path(filename: &str) -> PathBuf {
     if let Ok(path) = env::var("LACT_DAEMON_CONFIG_DIR") {
         PathBuf::from(&path).join(filename)
     } else {
@@ -424,14 +771,17 @@ fn find_existing_group(groups: &[impl AsRef<str>]) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
-    use crate::config::{Config, Daemon};
+    use crate::config::{Config, Daemon, KNOWN_GOOD_HISTORY_SIZE};
     use indexmap::IndexMap;
     use insta::assert_yaml_snapshot;
     use lact_schema::{
         FanControlMode, NvidiaThermalOptions, PmfwOptions,
-        config::{ClocksConfiguration, FanControlSettings, FanCurve, GpuConfig},
+        config::{
+            ClocksConfiguration, FailsafeAction, FanControlSettings, FanCurve, FanFailsafe,
+            FanPidSettings, GpuConfig, Profile, ProfileHooks, SensorCombination, SensorCurve,
+        },
     };
-    use std::collections::BTreeMap;
+    use std::{collections::BTreeMap, rc::Rc};
 
     #[test]
     fn serde_de_full() {
@@ -443,6 +793,11 @@ mod tests {
                     fan_control_enabled: true,
                     fan_control_settings: Some(FanControlSettings {
                         curve: FanCurve::default(),
+                        pid: Some(FanPidSettings {
+                            target_temperature: 85.0,
+                            kd: 0.01,
+                            ..Default::default()
+                        }),
                         temperature_key: "edge".to_owned(),
                         interval_ms: 500,
                         mode: FanControlMode::Curve,
@@ -450,6 +805,27 @@ mod tests {
                         spindown_delay_ms: Some(5000),
                         change_threshold: Some(3),
                         auto_threshold: Some(40),
+                        smoothing_window_ms: Some(2000),
+                        hysteresis_up: Some(2),
+                        hysteresis_down: Some(4),
+                        ramp_rate: Some(0.05),
+                        sensor_curves: [(
+                            "junction".to_owned(),
+                            SensorCurve {
+                                curve: FanCurve::default(),
+                                weight: 2.0,
+                            },
+                        )]
+                        .into(),
+                        sensor_combination: Some(SensorCombination::Weighted),
+                        zero_rpm_stop_temperature: Some(45),
+                        zero_rpm_start_temperature: Some(55),
+                        zero_rpm_start_speed: Some(0.4),
+                        failsafe: Some(FanFailsafe {
+                            action: FailsafeAction::Auto,
+                            critical_temperature: Some(95),
+                        }),
+                        min_speed: Some(0.15),
                     }),
                     ..Default::default()
                 },
@@ -542,6 +918,8 @@ mod tests {
             profiles: IndexMap::new(),
             current_profile: None,
             auto_switch_profiles: false,
+            known_good: IndexMap::new(),
+            unverified: None,
         };
 
         config.migrate_versions(&BTreeMap::new());
@@ -601,4 +979,130 @@ mod tests {
             Some(83),
         );
     }
+
+    #[test]
+    fn safe_mode() {
+        let known_good = GpuConfig {
+            power_cap: Some(300.0),
+            ..Default::default()
+        };
+        let unstable = GpuConfig {
+            power_cap: Some(300.0),
+            clocks_configuration: ClocksConfiguration {
+                voltage_offset: Some(-150),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let ids = ["gpu-1".to_owned(), "gpu-2".to_owned()];
+
+        let mut config = Config::default();
+        config.gpus.insert(ids[0].clone(), known_good.clone());
+        config.push_known_good(&ids[0], known_good.clone());
+        assert!(config.update_unverified("boot-1", &ids).unwrap());
+        assert!(!config.update_unverified("boot-1", &ids).unwrap());
+        // The second GPU has no known good settings yet
+        assert_eq!(
+            vec![&ids[1]],
+            config
+                .unverified
+                .as_ref()
+                .unwrap()
+                .gpus
+                .keys()
+                .collect::<Vec<_>>()
+        );
+
+        config.gpus.insert(ids[0].clone(), unstable.clone());
+        assert!(config.update_unverified("boot-1", &ids).unwrap());
+
+        // Same boot, e.g. after a daemon restart
+        assert!(config.apply_safe_mode("boot-1").unwrap().is_empty());
+
+        let mut crashed_config = config.clone();
+        assert_eq!(
+            vec![ids[0].clone()],
+            crashed_config.apply_safe_mode("boot-2").unwrap()
+        );
+        assert_eq!(Some(&known_good), crashed_config.gpus.get(&ids[0]));
+        assert_eq!(None, crashed_config.unverified);
+
+        // The settings were edited by hand before the next boot
+        let mut edited_config = config.clone();
+        edited_config.gpus.get_mut(&ids[0]).unwrap().power_cap = Some(250.0);
+        assert!(edited_config.apply_safe_mode("boot-2").unwrap().is_empty());
+    }
+
+    #[test]
+    fn safe_mode_profiles() {
+        let known_good = GpuConfig {
+            power_cap: Some(300.0),
+            ..Default::default()
+        };
+        let unstable = GpuConfig {
+            power_cap: Some(350.0),
+            ..Default::default()
+        };
+        let id = "gpu-1".to_owned();
+        let profile: Rc<str> = "gaming".into();
+
+        let mut config = Config::default();
+        config.gpus.insert(id.clone(), known_good.clone());
+        config.push_known_good(&id, known_good.clone());
+        config.profiles.insert(
+            profile.clone(),
+            Profile {
+                gpus: [(id.clone(), unstable.clone())].into_iter().collect(),
+                rule: None,
+                hooks: ProfileHooks::default(),
+            },
+        );
+        config.current_profile = Some(profile.clone());
+
+        // The known good settings of the default profile don't apply to other profiles
+        assert_eq!(None, config.last_known_good(&id));
+        assert!(config.update_unverified("boot-1", [&id]).unwrap());
+        assert_eq!(
+            Some(&profile),
+            config.unverified.as_ref().unwrap().profile.as_ref()
+        );
+
+        let mut crashed_config = config.clone();
+        assert!(crashed_config.apply_safe_mode("boot-2").unwrap().is_empty());
+        assert_eq!(Some(&unstable), crashed_config.gpus().unwrap().get(&id));
+        assert_eq!(Some(&known_good), crashed_config.gpus.get(&id));
+
+        // The profile was switched before the crash was noticed
+        let gaming_known_good = GpuConfig {
+            power_cap: Some(320.0),
+            ..Default::default()
+        };
+        config.push_known_good(&id, gaming_known_good.clone());
+        config.current_profile = None;
+        let mut crashed_config = config.clone();
+        assert_eq!(
+            vec![id.clone()],
+            crashed_config.apply_safe_mode("boot-2").unwrap()
+        );
+        assert_eq!(Some(&known_good), crashed_config.gpus().unwrap().get(&id));
+        assert_eq!(
+            Some(&gaming_known_good),
+            crashed_config.profiles[&profile].gpus.get(&id)
+        );
+
+        // Every profile keeps its own history
+        for _ in 0..KNOWN_GOOD_HISTORY_SIZE {
+            config.push_known_good(&id, unstable.clone());
+        }
+        assert_eq!(
+            vec![&unstable; KNOWN_GOOD_HISTORY_SIZE],
+            config.known_good_configs(None, &id).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![&gaming_known_good],
+            config
+                .known_good_configs(Some(&*profile), &id)
+                .collect::<Vec<_>>()
+        );
+    }
 }
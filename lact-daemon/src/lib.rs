@@ -13,25 +13,28 @@ mod tests;
 use anyhow::Context;
 use config::Config;
 use futures::future::select_all;
-use server::{Server, handle_stream, handler::Handler};
+use server::{Server, events, handle_stream, handler::Handler};
 use std::os::unix::net::UnixStream as StdUnixStream;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::UnixStream;
 use tokio::runtime::LocalOptions;
-use tokio::sync::Notify;
+use tokio::sync::{Notify, mpsc};
 use tokio::{
     runtime,
     signal::unix::{SignalKind, signal},
+    time,
 };
 use tracing::level_filters::LevelFilter;
 use tracing::{Instrument, debug_span, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 use crate::server::ClientContext;
+use lact_schema::{DaemonEvent, request::RequestClass};
 pub use system::BASE_MODULE_CONF_PATH;
 
 const DRM_EVENT_COLLECT_DURATION: Duration = Duration::from_millis(500);
+const KNOWN_GOOD_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 const SHUTDOWN_SIGNALS: [SignalKind; 4] = [
     SignalKind::terminate(),
@@ -85,7 +88,11 @@ pub fn run_embedded(stream: StdUnixStream) -> anyhow::Result<()> {
         let config = Config::default();
         let handler = Handler::new(config).await?;
         let stream = UnixStream::try_from(stream)?;
-        let ctx = ClientContext::default();
+        // The embedded daemon only serves the process that started it
+        let ctx = ClientContext {
+            access: Some(RequestClass::Admin),
+            ..Default::default()
+        };
 
         handle_stream(stream, handler, ctx).await
     })
@@ -99,6 +106,8 @@ async fn listen_exit_signals(handler: Handler) {
 
     info!("cleaning up and shutting down...");
     async {
+        handler.clear_unverified_configs().await;
+        handler.stats_history.save();
         handler.cleanup().await;
         socket::cleanup();
     }
@@ -115,6 +124,7 @@ async fn listen_config_changes(handler: Handler) {
         match handler.apply_current_config().await {
             Ok(()) => {
                 info!("configuration reloaded");
+                events::emit(DaemonEvent::ConfigReloaded);
             }
             Err(err) => {
                 error!("could not apply new config: {err:#}");
@@ -125,12 +135,23 @@ async fn listen_config_changes(handler: Handler) {
 
 async fn listen_device_events(handler: Handler) {
     let notify = Arc::new(Notify::new());
+    let (reset_tx, reset_rx) = mpsc::unbounded_channel();
+
     let task_notify = notify.clone();
+    let task_reset_tx = reset_tx.clone();
     tokio::task::spawn_blocking(move || {
-        if let Err(err) = system::listen_netlink_kernel_event(&task_notify) {
+        if let Err(err) = system::listen_netlink_kernel_event(&task_notify, &task_reset_tx) {
             error!("kernel event listener error: {err:#}");
         }
     });
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = system::listen_kernel_log_resets(&reset_tx) {
+            warn!(
+                "kernel log listener error, GPU resets will only be detected through uevents: {err:#}"
+            );
+        }
+    });
+    tokio::task::spawn_local(listen_gpu_resets(handler.clone(), reset_rx));
 
     loop {
         notify.notified().await;
@@ -139,3 +160,18 @@ async fn listen_device_events(handler: Handler) {
         handler.notify_reload_gpus(DRM_EVENT_COLLECT_DURATION).await;
     }
 }
+
+async fn listen_gpu_resets(handler: Handler, mut reset_rx: mpsc::UnboundedReceiver<String>) {
+    let mut known_good_interval = time::interval(KNOWN_GOOD_CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            Some(pci_slot) = reset_rx.recv() => {
+                handler.handle_gpu_reset(&pci_slot).await;
+            }
+            _ = known_good_interval.tick() => {
+                handler.observe_gpu_configs().await;
+            }
+        }
+    }
+}
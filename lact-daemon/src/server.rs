@@ -1,31 +1,54 @@
+mod access;
 #[cfg(feature = "display-info")]
 mod display;
+pub(crate) mod events;
 pub mod gpu_controller;
 pub mod handler;
+mod history;
 mod metrics;
 mod opencl;
 mod profiles;
+mod recovery;
+mod revisions;
+mod stability;
+mod stats;
+mod tcp;
 mod vulkan;
 
 use self::handler::Handler;
 use crate::{config::Config, socket, system};
-use anyhow::Context;
+use anyhow::{Context, anyhow};
 use futures::future::join_all;
-use lact_schema::{Pong, Request, Response};
+use lact_schema::{
+    DaemonEvent, Pong, Request, Response, request::RequestClass, revisions::ConfigChangeSource,
+};
 use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
 use serde::Serialize;
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    fs,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
     net::{TcpListener, UnixListener},
-    sync::Notify,
+    select,
+    sync::{Notify, broadcast},
 };
+use tokio_rustls::TlsAcceptor;
 use tracing::{debug, error, info, instrument, trace, warn};
 
+tokio::task_local! {
+    /// What caused the request that is currently being handled, recorded in config revisions
+    pub(crate) static CHANGE_SOURCE: RequestSource;
+}
+
 pub struct Server {
     pub handler: Handler,
     unix_listener: UnixListener,
     tcp_listener: Option<TcpListener>,
+    tls_acceptor: Option<TlsAcceptor>,
 }
 
 impl Server {
@@ -43,6 +66,22 @@ impl Server {
             None
         };
 
+        let tls_acceptor = match &config.daemon.tcp_tls {
+            Some(tls) if tcp_listener.is_some() => {
+                let acceptor = tcp::load_tls_acceptor(tls)?;
+                info!("TLS enabled for the TCP listener");
+                Some(acceptor)
+            }
+            _ => None,
+        };
+
+        if tcp_listener.is_some() && config.daemon.tcp_tokens.is_empty() {
+            warn!(
+                "no TCP tokens are configured, TCP clients are limited to {} requests",
+                config.daemon.access.unauthenticated
+            );
+        }
+
         system::power_profiles_daemon::setup().await;
 
         let handler = Handler::new(config).await?;
@@ -60,10 +99,19 @@ impl Server {
             debug!("metrics exporter disabled");
         }
 
+        if let Some(history_config) = handler.config.read().await.daemon.stats_history.clone() {
+            handler.stats_history.setup(handler.clone(), history_config);
+        }
+
+        if let Some(prometheus_config) = handler.config.read().await.daemon.prometheus.clone() {
+            metrics::prometheus::setup(handler.clone(), prometheus_config).await?;
+        }
+
         Ok(Self {
             handler,
             unix_listener,
             tcp_listener,
+            tls_acceptor,
         })
     }
 
@@ -79,12 +127,20 @@ impl Server {
                             .inspect_err(|err| warn!("could not get client credentials: {err:#}"))
                             .ok();
 
-                        let ctx = ClientContext {
-                            pid: client_credentials.map(|creds| creds.pid().cast_unsigned()),
-                            uid: client_credentials.map(|creds| creds.uid()),
-                        };
+                        let uid = client_credentials.map(|creds| creds.uid());
                         let handler = unix_handler.clone();
                         tokio::task::spawn_local(async move {
+                            let access = access::local_client_class(
+                                &handler.config.read().await.daemon,
+                                uid,
+                            );
+                            let ctx = ClientContext {
+                                pid: client_credentials.map(|creds| creds.pid().cast_unsigned()),
+                                uid,
+                                tcp: None,
+                                access,
+                            };
+
                             if let Err(error) = handle_stream(stream, handler, ctx).await {
                                 error!("{error}");
                             }
@@ -102,13 +158,31 @@ impl Server {
             let tcp_task = tokio::task::spawn_local(async move {
                 loop {
                     match tcp_listener.accept().await {
-                        Ok((stream, _)) => {
-                            let ctx = ClientContext::default();
+                        Ok((stream, peer_addr)) => {
                             let handler = self.handler.clone();
+                            let tls_acceptor = self.tls_acceptor.clone();
 
                             tokio::task::spawn_local(async move {
-                                if let Err(error) = handle_stream(stream, handler, ctx).await {
-                                    error!("{error}");
+                                let access =
+                                    handler.config.read().await.daemon.access.unauthenticated;
+                                let ctx = ClientContext {
+                                    tcp: Some(TcpAuthState::Unauthenticated),
+                                    tcp_peer: Some(peer_addr.ip()),
+                                    access: Some(access),
+                                    ..Default::default()
+                                };
+
+                                let result = match tls_acceptor {
+                                    Some(acceptor) => match acceptor.accept(stream).await {
+                                        Ok(stream) => handle_stream(stream, handler, ctx).await,
+                                        Err(err) => Err(anyhow::Error::new(err).context(format!(
+                                            "TLS handshake with {peer_addr} failed"
+                                        ))),
+                                    },
+                                    None => handle_stream(stream, handler, ctx).await,
+                                };
+                                if let Err(error) = result {
+                                    error!("{error:#}");
                                 }
                             });
                         }
@@ -129,13 +203,73 @@ impl Server {
 pub(crate) struct ClientContext {
     pub pid: Option<u32>,
     pub uid: Option<u32>,
+    /// Only present for TCP clients
+    pub tcp: Option<TcpAuthState>,
+    /// Address of TCP clients
+    pub tcp_peer: Option<IpAddr>,
+    /// The most privileged class of requests the client is allowed to use
+    pub access: Option<RequestClass>,
+}
+
+impl ClientContext {
+    fn is_allowed(&self, request: &Request) -> bool {
+        matches!(request, Request::Ping)
+            || self.access.is_some_and(|access| access >= request.class())
+    }
+
+    fn access_error(&self, request: &Request) -> anyhow::Error {
+        if self.tcp == Some(TcpAuthState::Unauthenticated) {
+            anyhow!("Authentication is required for this request")
+        } else {
+            let access = self
+                .access
+                .map_or_else(|| "none".to_owned(), |access| access.to_string());
+            anyhow!(
+                "Client is not allowed to use {} requests (access level: {access})",
+                request.class()
+            )
+        }
+    }
+}
+
+/// Client and name of a request, only resolved into a `ConfigChangeSource` when a config change is recorded,
+/// as that requires reading the process name of the client
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RequestSource {
+    ctx: ClientContext,
+    command: &'static str,
+}
+
+impl RequestSource {
+    pub(crate) fn resolve(&self) -> ConfigChangeSource {
+        let client = if self.ctx.tcp.is_some() {
+            Some("tcp".to_owned())
+        } else {
+            self.ctx
+                .pid
+                .and_then(|pid| fs::read_to_string(format!("/proc/{pid}/comm")).ok())
+                .map(|name| name.trim().to_owned())
+        };
+
+        ConfigChangeSource {
+            client,
+            uid: self.ctx.uid,
+            request: self.command.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TcpAuthState {
+    Unauthenticated,
+    Authenticated,
 }
 
 #[instrument(level = "debug", skip(stream, handler))]
 pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
     stream: T,
     handler: Handler,
-    ctx: ClientContext,
+    mut ctx: ClientContext,
 ) -> anyhow::Result<()> {
     let disconnect_notify = std::sync::Arc::new(Notify::new());
 
@@ -147,10 +281,42 @@ pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
 
         let maybe_request = serde_json::from_str(&buf);
         let response = match maybe_request {
-            Ok(request) => match handle_request(request, &handler, &disconnect_notify, ctx).await {
-                Ok(response) => response,
-                Err(error) => serde_json::to_vec(&Response::<()>::from(error))?,
-            },
+            Ok(Request::Authenticate { token }) => {
+                match tcp::authenticate(&handler, &mut ctx, &token).await {
+                    Ok(()) => ok_response(())?,
+                    Err(error) => serde_json::to_vec(&Response::<()>::from(error))?,
+                }
+            }
+            Ok(request) if !ctx.is_allowed(&request) => {
+                let error = ctx.access_error(&request);
+                debug!("denied request: {error}");
+                serde_json::to_vec(&Response::<()>::from(error))?
+            }
+            // The connection is used exclusively for the subscription from now on
+            Ok(Request::SubscribeStats { id, interval_ms }) => {
+                stream_stats(&mut stream, &handler, id, interval_ms).await?;
+                break;
+            }
+            Ok(Request::SubscribeEvents) => {
+                stream_events(&mut stream).await?;
+                break;
+            }
+            Ok(request) => {
+                let source = RequestSource {
+                    ctx,
+                    command: request.command(),
+                };
+                let result = CHANGE_SOURCE
+                    .scope(
+                        source,
+                        handle_request(request, &handler, &disconnect_notify, ctx),
+                    )
+                    .await;
+                match result {
+                    Ok(response) => response,
+                    Err(error) => serde_json::to_vec(&Response::<()>::from(error))?,
+                }
+            }
             Err(error) => serde_json::to_vec(&Response::<()>::from(
                 anyhow::Error::new(error).context("Failed to deserialize"),
             ))?,
@@ -167,6 +333,108 @@ pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
     Ok(())
 }
 
+#[instrument(level = "debug", skip(stream, handler))]
+async fn stream_stats<T: AsyncBufRead + AsyncWrite + Unpin>(
+    stream: &mut T,
+    handler: &Handler,
+    id: &str,
+    interval_ms: u64,
+) -> anyhow::Result<()> {
+    let interval = Duration::from_millis(interval_ms);
+
+    let mut subscription = match handler.subscribe_stats(id, interval).await {
+        Ok(subscription) => subscription,
+        Err(err) => {
+            let response = serde_json::to_vec(&Response::<()>::from(err))?;
+            write_line(stream, &response).await?;
+            return Ok(());
+        }
+    };
+
+    // The sampler may be running faster than requested if another client asked for a shorter interval,
+    // in which case some of the samples are skipped. Samples arriving slightly early are still sent.
+    let min_elapsed = interval.saturating_sub(stats::MIN_SAMPLE_INTERVAL / 2);
+    let mut last_sent: Option<Instant> = None;
+
+    loop {
+        let sampler_alive = select! {
+            result = subscription.rx.changed() => result.is_ok(),
+            () = client_disconnected(stream) => {
+                debug!("stats subscriber disconnected");
+                return Ok(());
+            }
+        };
+        if !sampler_alive {
+            break;
+        }
+
+        if last_sent.is_some_and(|last_sent| last_sent.elapsed() < min_elapsed) {
+            continue;
+        }
+
+        let Some(sample) = subscription.rx.borrow_and_update().clone() else {
+            continue;
+        };
+        last_sent = Some(Instant::now());
+
+        let response = serde_json::to_vec(&Response::Ok(&*sample))?;
+        if let Err(err) = write_line(stream, &response).await {
+            debug!("stats subscriber disconnected: {err}");
+            return Ok(());
+        }
+    }
+
+    let response = serde_json::to_vec(&Response::<()>::from(anyhow!(
+        "Stats are no longer available for GPU '{id}'"
+    )))?;
+    let _ = write_line(stream, &response).await;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip(stream))]
+async fn stream_events<T: AsyncBufRead + AsyncWrite + Unpin>(stream: &mut T) -> anyhow::Result<()> {
+    let mut rx = events::subscribe();
+
+    loop {
+        let result = select! {
+            result = rx.recv() => result,
+            () = client_disconnected(stream) => break,
+        };
+
+        let event = match result {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                warn!("event subscriber is lagging behind, {count} events were dropped");
+                DaemonEvent::EventsMissed { count }
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        let response = serde_json::to_vec(&Response::Ok(event))?;
+        if let Err(err) = write_line(stream, &response).await {
+            debug!("event subscriber disconnected: {err}");
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves once the client closes the connection. Anything sent by the client in the meantime is discarded.
+async fn client_disconnected<T: AsyncBufRead + Unpin>(stream: &mut T) {
+    let mut buf = String::new();
+    while let Ok(1..) = stream.read_line(&mut buf).await {
+        buf.clear();
+    }
+}
+
+async fn write_line<T: AsyncWrite + Unpin>(stream: &mut T, data: &[u8]) -> std::io::Result<()> {
+    stream.write_all(data).await?;
+    stream.write_all(b"\n").await?;
+    stream.flush().await
+}
+
 #[instrument(level = "debug", skip(handler, disconnect_notify))]
 async fn handle_request<'a>(
     request: Request<'a>,
@@ -184,6 +452,14 @@ async fn handle_request<'a>(
         } => ok_response(handler.get_device_info(id, include_api_info).await?),
         Request::DeviceApiInfo { id } => ok_response(handler.get_device_api_info(id).await?),
         Request::DeviceStats { id } => ok_response(handler.get_gpu_stats(id).await?),
+        Request::StatsHistory { id, since, stats } => {
+            ok_response(handler.get_stats_history(id, since, &stats).await?)
+        }
+        Request::Authenticate { .. }
+        | Request::SubscribeStats { .. }
+        | Request::SubscribeEvents => {
+            Err(anyhow!("This request is handled by the connection stream"))
+        }
         Request::DeviceClocksInfo { id } => ok_response(handler.get_clocks_info(id).await?),
         Request::DisplaysInfo { id } => ok_response(handler.get_displays_info(id).await?),
         Request::DevicePowerProfileModes { id } => {
@@ -191,6 +467,20 @@ async fn handle_request<'a>(
         }
         Request::SetFanControl(opts) => ok_response(handler.set_fan_control(opts).await?),
         Request::ResetPmfw { id } => ok_response(handler.reset_pmfw(id).await?),
+        Request::CalibrateFan { id } => ok_response(handler.calibrate_fan(id).await?),
+        Request::StartStabilityTest { id, options } => {
+            ok_response(handler.start_stability_test(id, options, ctx).await?)
+        }
+        Request::StabilityTestStatus { id } => ok_response(handler.stability_test_status(id)),
+        Request::StopStabilityTest { id } => ok_response(handler.stop_stability_test(id)?),
+        Request::ConfigRecovery { id } => ok_response(handler.config_recovery(id)),
+        Request::ListConfigRevisions { id } => ok_response(handler.list_config_revisions(id)),
+        Request::DiffConfigRevisions { from, to } => {
+            ok_response(handler.diff_config_revisions(from, to).await?)
+        }
+        Request::RollbackConfig { revision } => {
+            ok_response(handler.rollback_config(revision).await?)
+        }
         Request::SetPowerCap { id, cap } => ok_response(handler.set_power_cap(id, cap).await?),
         Request::SetPerformanceLevel {
             id,
@@ -231,6 +521,12 @@ async fn handle_request<'a>(
             ok_response(handler.create_profile(name, base, ctx).await?)
         }
         Request::DeleteProfile { name } => ok_response(handler.delete_profile(name).await?),
+        Request::ExportProfile { name } => {
+            ok_response(handler.export_profile(name.map(Into::into)).await?)
+        }
+        Request::ImportProfile { name, profile } => {
+            ok_response(handler.import_profile(name, *profile, ctx).await?)
+        }
         Request::MoveProfile { name, new_position } => {
             ok_response(handler.move_profile(&name, new_position).await?)
         }
@@ -241,6 +537,11 @@ async fn handle_request<'a>(
         ),
         Request::ReleaseProfile { cookie } => ok_response(handler.release_profile(cookie).await?),
         Request::EvaluateProfileRule { rule } => ok_response(handler.evaluate_profile_rule(&rule)?),
+        Request::SetFocusedWindow { window } => ok_response(
+            handler
+                .set_focused_window(window, disconnect_notify.clone())
+                .await,
+        ),
         Request::DetachGpu { id } => ok_response(handler.detach_gpu(id).await?),
         Request::ReattachGpu { id } => ok_response(handler.reattach_gpu(id).await?),
         Request::SetProfileRule { name, rule, hooks } => {
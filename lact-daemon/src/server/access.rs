@@ -0,0 +1,124 @@
+use crate::config::Daemon;
+use lact_schema::request::RequestClass;
+use nix::unistd::{Gid, Group, Uid, User, getgrouplist};
+use std::ffi::CString;
+use tracing::warn;
+
+/// Resolves the most privileged request class a local client is allowed to use.
+/// `None` means the client is not allowed to make any requests.
+pub fn local_client_class(config: &Daemon, uid: Option<u32>) -> Option<RequestClass> {
+    let policy = &config.access;
+
+    let Some(uid) = uid else {
+        // Without any local rules, everyone who can open the socket is an admin
+        return if policy.has_local_rules() {
+            warn!("could not get client credentials, denying access");
+            None
+        } else {
+            Some(RequestClass::Admin)
+        };
+    };
+
+    let uid = Uid::from_raw(uid);
+    if uid.is_root() || !policy.has_local_rules() {
+        return Some(RequestClass::Admin);
+    }
+
+    let user = match User::from_uid(uid) {
+        Ok(Some(user)) => user,
+        Ok(None) => return policy.other_users,
+        Err(err) => {
+            warn!("could not get user info for uid {uid}: {err}");
+            return policy.other_users;
+        }
+    };
+
+    if let Some(class) = policy.users.get(&user.name) {
+        return Some(*class);
+    }
+
+    let user_groups = user_groups(&user);
+
+    let group_class = policy
+        .groups
+        .iter()
+        .filter(|(name, _)| group_gid(name).is_some_and(|gid| user_groups.contains(&gid)))
+        .map(|(_, class)| *class)
+        .max();
+    if group_class.is_some() {
+        return group_class;
+    }
+
+    let is_admin_user = config.admin_user.as_ref() == Some(&user.name);
+    let is_admin_group = config
+        .admin_group
+        .as_deref()
+        .and_then(group_gid)
+        .is_some_and(|gid| user_groups.contains(&gid));
+    if is_admin_user || is_admin_group {
+        return Some(RequestClass::Admin);
+    }
+
+    policy.other_users
+}
+
+pub fn user_groups(user: &User) -> Vec<Gid> {
+    let Ok(name) = CString::new(user.name.as_str()) else {
+        return vec![user.gid];
+    };
+    getgrouplist(&name, user.gid).unwrap_or_else(|err| {
+        warn!("could not get groups of user {}: {err}", user.name);
+        vec![user.gid]
+    })
+}
+
+fn group_gid(name: &str) -> Option<Gid> {
+    Group::from_name(name).ok().flatten().map(|group| group.gid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::local_client_class;
+    use crate::config::Daemon;
+    use lact_schema::request::RequestClass;
+
+    // Should not exist on any system running the tests
+    const UNKNOWN_UID: u32 = 4_000_000_000;
+
+    #[test]
+    fn no_rules_allow_everything() {
+        let config = Daemon::default();
+        assert_eq!(
+            Some(RequestClass::Admin),
+            local_client_class(&config, Some(UNKNOWN_UID))
+        );
+        assert_eq!(Some(RequestClass::Admin), local_client_class(&config, None));
+    }
+
+    #[test]
+    fn other_users_fallback() {
+        let mut config = Daemon::default();
+        config.access.other_users = Some(RequestClass::Monitor);
+
+        assert_eq!(
+            Some(RequestClass::Monitor),
+            local_client_class(&config, Some(UNKNOWN_UID))
+        );
+        assert_eq!(None, local_client_class(&config, None));
+        assert_eq!(
+            Some(RequestClass::Admin),
+            local_client_class(&config, Some(0))
+        );
+    }
+
+    #[test]
+    fn unmatched_users_denied() {
+        let mut config = Daemon::default();
+        config
+            .access
+            .users
+            .insert("lact-test-nonexistent".to_owned(), RequestClass::Tune);
+
+        assert_eq!(None, local_client_class(&config, Some(UNKNOWN_UID)));
+    }
+}
@@ -0,0 +1,20 @@
+use lact_schema::DaemonEvent;
+use std::sync::LazyLock;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+const EVENTS_CHANNEL_SIZE: usize = 64;
+
+static EVENTS_TX: LazyLock<broadcast::Sender<DaemonEvent>> =
+    LazyLock::new(|| broadcast::Sender::new(EVENTS_CHANNEL_SIZE));
+
+/// Sends an event to all subscribed clients
+pub fn emit(event: DaemonEvent) {
+    debug!("emitting event {event:?}");
+    // An error only means that there are no subscribers
+    let _ = EVENTS_TX.send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<DaemonEvent> {
+    EVENTS_TX.subscribe()
+}
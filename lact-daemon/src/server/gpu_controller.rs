@@ -18,6 +18,7 @@ pub const VENDOR_AMD: &str = "1002";
 pub const VENDOR_NVIDIA: &str = "10DE";
 
 use crate::config::Config;
+use crate::server::gpu_controller::common::fan_control::FanCalibrator;
 use crate::server::handler::{AMD_DRM, INTEL_DRM};
 use crate::server::opencl::get_opencl_info;
 use crate::server::vulkan::get_vulkan_info;
@@ -82,6 +83,11 @@ pub trait GpuController {
 
     fn reset_pmfw_settings(&self) {}
 
+    /// Stops fan control and prepares the fan for calibration
+    fn fan_calibrator(&self) -> LocalBoxFuture<'_, anyhow::Result<FanCalibrator>> {
+        async { Err(anyhow!("Fan calibration is not supported on this GPU")) }.boxed_local()
+    }
+
     fn cleanup(&self) -> LocalBoxFuture<'_, ()> {
         async {}.boxed_local()
     }
@@ -1,7 +1,17 @@
 use super::{CommonControllerInfo, FanControlHandle, GpuController, VENDOR_AMD};
-use crate::server::gpu_controller::common::{
-    fan_control::FanCurveExt,
-    fdinfo::{self, DrmUtilMap},
+use crate::server::{
+    events,
+    gpu_controller::common::{
+        fan_control::{
+            AppliedPwm, CurveSmoothing, FAN_CONTROL_RETRIES, Failsafe, FailsafeState,
+            FanCalibrator, FanCurveExt, FanStop, PidController, combine_pwm,
+        },
+        fdinfo::{self, DrmUtilMap},
+        system_sensors::{
+            is_system_sensor, list_system_sensors, read_system_temp, read_system_temps,
+            resolve_system_sensor,
+        },
+    },
 };
 use amdgpu_sysfs::{
     error::Error,
@@ -19,11 +29,11 @@ use futures::{FutureExt, future::LocalBoxFuture};
 use indexmap::IndexMap;
 use lact_schema::{
     ActivePowerStates, AmdCacheInstance, AmdIpInfo, CacheInfo, CacheType, ClocksInfo,
-    ClockspeedStats, DeviceApiInfo, DeviceFlag, DeviceInfo, DeviceStats, DeviceType, DrmInfo,
-    FanControlMode, FanStats, IntelDrmInfo, LinkInfo, NvidiaThermalInfo, PmfwInfo, PowerState,
-    PowerStates, PowerStats, ProcessList, ProcessUtilizationType, RopInfo, TemperatureEntry,
-    VoltageStats, VramStats,
-    config::{ClocksConfiguration, FanControlSettings, FanCurve, GpuConfig},
+    ClockspeedStats, DaemonEvent, DeviceApiInfo, DeviceFlag, DeviceInfo, DeviceStats, DeviceType,
+    DrmInfo, FanControlMode, FanStats, IntelDrmInfo, LinkInfo, NvidiaThermalInfo, PmfwInfo,
+    PowerState, PowerStates, PowerStats, ProcessList, ProcessUtilizationType, RopInfo,
+    TemperatureEntry, VoltageStats, VramStats,
+    config::{ClocksConfiguration, FailsafeAction, FanControlSettings, FanCurve, GpuConfig},
 };
 #[cfg(feature = "display-info")]
 use lact_schema::{DisplayConnector, DisplaysInfo};
@@ -49,7 +59,6 @@ use {
 /// RDNA3 - minimum family with PMFW
 const AMDGPU_FAMILY_GC_11_0_0: u32 = 145;
 
-const FAN_CONTROL_RETRIES: u32 = 10;
 const MAX_PSTATE_READ_ATTEMPTS: u32 = 5;
 const REQUIRE_MANUAL_DEVICE_IDS: [&str; 3] = ["163F", "1435", "15BF"];
 const AMDGPU_IDS_FLAGS_FUSION: u64 = 0x1;
@@ -85,6 +94,8 @@ pub struct AmdGpuController {
     drm_handle: Option<DrmHandle>,
     common: CommonControllerInfo,
     fan_control_handle: RefCell<Option<FanControlHandle>>,
+    fan_failsafe: FailsafeState,
+    applied_fan_pwm: AppliedPwm,
     last_drm_util: RefCell<Option<DrmUtilMap>>,
 }
 
@@ -113,6 +124,8 @@ impl AmdGpuController {
             drm_handle,
             common,
             fan_control_handle: RefCell::new(None),
+            fan_failsafe: FailsafeState::default(),
+            applied_fan_pwm: AppliedPwm::default(),
             last_drm_util: RefCell::new(None),
         })
     }
@@ -187,6 +200,7 @@ impl AmdGpuController {
             hw_mon
                 .set_fan_pwm(static_pwm)
                 .context("could not set fan speed")?;
+            self.applied_fan_pwm.set(Some(static_pwm));
 
             debug!("set fan speed to {}", static_speed);
 
@@ -201,7 +215,11 @@ impl AmdGpuController {
     ) -> anyhow::Result<Option<CommitHandle>> {
         // Use the PMFW curve functionality when it is available
         // Otherwise, fall back to manual fan control via a task
-        if let Ok(current_curve) = self.handle.get_fan_curve() {
+        // PMFW curves only follow the GPU's own sensor, so per-sensor curves and system sensors always use the task
+        if settings.sensor_curves.is_empty()
+            && !is_system_sensor(&settings.temperature_key)
+            && let Ok(current_curve) = self.handle.get_fan_curve()
+        {
             let new_curve = curve
                 .into_pmfw_curve(current_curve.clone())
                 .context("Invalid fan curve")?;
@@ -235,25 +253,55 @@ impl AmdGpuController {
             .cloned()
             .context("This GPU has no monitor")?;
 
+        let mut pid = match settings.mode {
+            FanControlMode::Pid => Some(
+                PidController::new(settings.pid.clone().unwrap_or_default())
+                    .context("Invalid PID settings")?,
+            ),
+            _ => None,
+        };
+        // The PID controller only follows `temperature_key`
+        let use_sensor_curves = pid.is_none() && !settings.sensor_curves.is_empty();
+
         let temps = hw_mon.get_temps();
+        let sensor_keys: Vec<&String> = if !use_sensor_curves {
+            vec![&settings.temperature_key]
+        } else {
+            settings.sensor_curves.keys().collect()
+        };
+
+        let (system_keys, gpu_keys): (Vec<&String>, Vec<&String>) = sensor_keys
+            .into_iter()
+            .partition(|key| is_system_sensor(key));
+
+        let mut system_sensor_paths = HashMap::new();
+        for key in system_keys {
+            let path = resolve_system_sensor(key)?;
+            debug!("using system sensor {key} at {}", path.display());
+            system_sensor_paths.insert(key.clone(), path);
+        }
+
         match temps.len() {
-            0 => return Err(anyhow!("GPU has no temperature reporting")),
-            1 => {
+            0 if !gpu_keys.is_empty() => {
+                return Err(anyhow!("GPU has no temperature reporting"));
+            }
+            1 if !use_sensor_curves && !gpu_keys.is_empty() => {
                 warn!(
                     "GPU has only one temperature sensor, 'temperature_key' setting will be ignored"
                 );
             }
             _ => {
-                if !temps.contains_key(&settings.temperature_key) {
-                    return Err(anyhow!(
-                        "Sensor with name {} not found, available sensors: {}",
-                        settings.temperature_key,
-                        temps
-                            .keys()
-                            .map(String::as_str)
-                            .collect::<Vec<&str>>()
-                            .join(",")
-                    ));
+                for key in gpu_keys {
+                    if !temps.contains_key(key) {
+                        return Err(anyhow!(
+                            "Sensor with name {key} not found, available sensors: {}",
+                            temps
+                                .keys()
+                                .map(String::as_str)
+                                .collect::<Vec<&str>>()
+                                .join(",")
+                        ));
+                    }
                 }
             }
         }
@@ -269,20 +317,40 @@ impl AmdGpuController {
 
         let notify = Rc::new(Notify::new());
         let task_notify = notify.clone();
+        let gpu_id = self.common.build_id();
+        let failsafe = Failsafe::new(gpu_id.clone(), &settings, self.fan_failsafe.clone());
+        let applied_pwm = self.applied_fan_pwm.clone();
 
         debug!("spawning new fan control task");
         let handle = tokio::task::spawn_local(async move {
             let mut last_pwm = (None, Instant::now());
-            let mut last_temp = 0.0;
+            // Set when the task exits on its own instead of being stopped
+            let mut exit_reason = None;
 
             // If the fan speed could was able to be set at least once
             let mut control_available = false;
 
-            let temp_key = settings.temperature_key.clone();
+            // Sensor name, curve and weight of every sensor the fan speed is based on
+            let sensors: Vec<(String, FanCurve, f32)> = if !use_sensor_curves {
+                vec![(settings.temperature_key.clone(), curve, 1.0)]
+            } else {
+                settings
+                    .sensor_curves
+                    .iter()
+                    .map(|(key, sensor)| (key.clone(), sensor.curve.clone(), sensor.weight))
+                    .collect()
+            };
+            let combination = settings.sensor_combination.unwrap_or_default();
+            let mut last_temps = vec![0.0; sensors.len()];
+
             let interval = Duration::from_millis(settings.interval_ms);
             let spindown_delay = Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0));
             #[allow(clippy::cast_precision_loss)]
             let change_threshold = settings.change_threshold.unwrap_or(0) as f32;
+            let mut smoothing = CurveSmoothing::new(&settings);
+            let mut fan_stop = FanStop::new(&settings);
+            // Ticks can be skipped or delayed, so the PID controller gets the actual time between updates
+            let mut last_pid_update = Instant::now();
 
             let mut retries = 0;
 
@@ -293,56 +361,168 @@ impl AmdGpuController {
                 }
 
                 let mut temps = hw_mon.get_temps();
-                let temp = if temps.len() == 1 {
-                    temps.into_values().next().unwrap()
-                } else if let Some(value) = temps.remove(&temp_key) {
-                    value
-                } else {
+                if temps.len() == 1 && !use_sensor_curves && system_sensor_paths.is_empty() {
+                    // The only available sensor is used regardless of the configured key
+                    temps = temps
+                        .into_values()
+                        .map(|temp| (settings.temperature_key.clone(), temp))
+                        .collect();
+                }
+
+                let mut readings = Vec::with_capacity(sensors.len());
+                let mut missing_key = None;
+                for (key, _, _) in &sensors {
+                    let temp = match system_sensor_paths.get(key) {
+                        Some(path) => read_system_temp(path),
+                        None => temps.remove(key),
+                    };
+                    match temp.filter(|temp| temp.current.is_some()) {
+                        Some(temp) => readings.push(temp),
+                        None => {
+                            missing_key = Some(key);
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(key) = missing_key {
+                    if failsafe.is_enabled() {
+                        // Keep the task running, so the curve is followed again once the sensor is back
+                        failsafe.trigger(format!("Could not get temperature sensor {key}"));
+                        applied_pwm.set(apply_failsafe(&hw_mon, failsafe.action()));
+                        continue;
+                    }
+
                     retries += 1;
 
                     if retries == FAN_CONTROL_RETRIES {
                         error!(
-                            "could not get temperature sensor {temp_key}, exiting fan control (reached max attempts)"
+                            "could not get temperature sensor {key}, exiting fan control (reached max attempts)"
                         );
+                        exit_reason = Some(format!("Could not get temperature sensor {key}"));
                         break;
                     }
                     error!(
-                        "could not get temperature sensor {temp_key} from {} sensors (assuming error is temporary, attempt {retries}/{FAN_CONTROL_RETRIES})",
-                        temps.len()
+                        "could not get temperature sensor {key} (assuming error is temporary, attempt {retries}/{FAN_CONTROL_RETRIES})"
                     );
                     continue;
-                };
+                }
+
+                let raw_max_temp = readings
+                    .iter()
+                    .filter_map(|temp| temp.current)
+                    .fold(f32::MIN, f32::max);
+                if failsafe.is_critical(raw_max_temp) {
+                    failsafe.trigger(format!(
+                        "Temperature {raw_max_temp}°C reached the critical threshold"
+                    ));
+                    applied_pwm.set(apply_failsafe(&hw_mon, failsafe.action()));
+                    continue;
+                }
+
+                let recovered = failsafe.clear();
+                if recovered
+                    && failsafe.action() == FailsafeAction::Auto
+                    && let Err(err) = hw_mon.set_fan_control_method(FanControlMethod::Manual)
+                {
+                    error!("could not set fan control back to manual: {err}");
+                }
 
-                let current_temp = temp.current.expect("Missing temp");
+                let current_temps: Vec<f32> = sensors
+                    .iter()
+                    .zip(&readings)
+                    .map(|((key, _, _), temp)| {
+                        smoothing.smooth_temp(key, temp.current.expect("Missing temp"))
+                    })
+                    .collect();
+                // The speed has to be set again after recovering from the failsafe,
+                // and the PID controller has to be updated on every tick
+                let force_update = recovered || smoothing.is_ramping() || pid.is_some();
+
+                if !force_update
+                    && last_temps
+                        .iter()
+                        .zip(&current_temps)
+                        .all(|(last_temp, current_temp)| {
+                            (last_temp - current_temp).abs() < change_threshold
+                        })
+                {
+                    trace!(
+                        "temperature changed from {last_temps:?}°C to {current_temps:?}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment"
+                    );
+                    continue;
+                }
 
-                if (last_temp - current_temp).abs() < change_threshold {
+                if !force_update
+                    && !last_temps
+                        .iter()
+                        .zip(&current_temps)
+                        .any(|(last_temp, current_temp)| {
+                            smoothing.outside_hysteresis(*last_temp, *current_temp)
+                        })
+                {
                     trace!(
-                        "temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment"
+                        "temperature changed from {last_temps:?}°C to {current_temps:?}°C, which is within the hysteresis band, skipping speed adjustment"
                     );
                     continue;
                 }
 
-                let target_pwm = curve.pwm_at_temp(temp);
+                // The PID output is not applied while the fan is stopped or its spindown is delayed,
+                // so the state from before the update is restored to keep the integral from winding up
+                let pid_snapshot = pid.clone();
+                let target_pwm = if let Some(pid) = &mut pid {
+                    let elapsed = last_pid_update.elapsed();
+                    last_pid_update = Instant::now();
+                    pid.update(current_temps[0], elapsed)
+                } else {
+                    let speeds: Vec<(u8, f32)> = sensors
+                        .iter()
+                        .zip(readings)
+                        .zip(&current_temps)
+                        .map(|(((_, curve, weight), temp), current_temp)| {
+                            let pwm = curve.pwm_at_temp(Temperature {
+                                current: Some(*current_temp),
+                                ..temp
+                            });
+                            (pwm, *weight)
+                        })
+                        .collect();
+                    combine_pwm(&speeds, combination).expect("No sensors configured")
+                };
+                let max_temp = current_temps.iter().copied().fold(f32::MIN, f32::max);
+                let target_pwm = fan_stop.apply(max_temp, target_pwm);
+                if fan_stop.is_stopped() {
+                    pid.clone_from(&pid_snapshot);
+                }
                 let now = Instant::now();
 
                 if let (Some(previous_pwm), previous_timestamp) = last_pwm {
                     let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
+                    if target_pwm < previous_pwm
+                        && diff < spindown_delay
+                        && !smoothing.is_ramping_down()
+                    {
                         trace!(
                             "delaying fan spindown ({}ms left)",
                             spindown_delay.checked_sub(diff).unwrap().as_millis()
                         );
+                        pid.clone_from(&pid_snapshot);
                         continue;
                     }
                 }
 
+                let target_pwm = smoothing.limit_ramp(last_pwm.0, target_pwm);
+                let target_pwm = fan_stop.kick_start(last_pwm.0, target_pwm);
                 last_pwm = (Some(target_pwm), now);
-                last_temp = current_temp;
+                last_temps = current_temps;
 
                 trace!("fan control tick: setting pwm to {target_pwm}");
 
                 match hw_mon.set_fan_pwm(target_pwm) {
-                    Ok(()) => control_available = true,
+                    Ok(()) => {
+                        control_available = true;
+                        applied_pwm.set(Some(target_pwm));
+                    }
                     Err(err) => {
                         error!("could not set fan speed: {err}");
                         if control_available {
@@ -352,6 +532,7 @@ impl AmdGpuController {
                                 error!(
                                     "could not set fan speed after {retries} attempts, exiting fan control (reached max attempts)"
                                 );
+                                exit_reason = Some(format!("Could not set fan speed: {err}"));
                                 break;
                             }
 
@@ -370,11 +551,15 @@ impl AmdGpuController {
                                     hw_mon.set_fan_control_method(FanControlMethod::Manual)
                                 {
                                     error!("could not set fan control back to manual: {err}");
+                                    exit_reason = Some(format!(
+                                        "Could not set fan control back to manual: {err}"
+                                    ));
                                     break;
                                 }
                             }
                         } else {
                             info!("disabling fan control");
+                            exit_reason = Some(format!("Could not set fan speed: {err}"));
                             break;
                         }
                     }
@@ -382,10 +567,16 @@ impl AmdGpuController {
                 retries = 0;
             }
             debug!("exited fan control task");
+            applied_pwm.set(None);
 
             if let Err(err) = hw_mon.set_fan_control_method(FanControlMethod::Auto) {
                 error!("could not reset fan control back to auto: {err}");
             }
+
+            if let Some(reason) = exit_reason {
+                failsafe.exited(&reason);
+                events::emit(DaemonEvent::FanControlExited { id: gpu_id, reason });
+            }
         });
 
         *notify_guard = Some((notify, handle));
@@ -408,6 +599,8 @@ impl AmdGpuController {
             notify.notify_one();
             handle.await?;
         }
+        self.fan_failsafe.replace(None);
+        self.applied_fan_pwm.set(None);
 
         if reset_mode {
             if self.handle.get_fan_curve().is_ok()
@@ -1000,6 +1193,25 @@ impl GpuController for AmdGpuController {
                 change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
                 temperature_key: fan_settings.map(|settings| settings.temperature_key.clone()),
                 auto_threshold: None,
+                sensor_curves: fan_settings
+                    .map(|settings| settings.sensor_curves.clone())
+                    .unwrap_or_default(),
+                sensor_combination: fan_settings.and_then(|settings| settings.sensor_combination),
+                pid: fan_settings.and_then(|settings| settings.pid.clone()),
+                failsafe: self.fan_failsafe.borrow().clone(),
+                pwm_target: self.applied_fan_pwm.get(),
+                system_sensors: if pmfw_curve.is_none() {
+                    list_system_sensors()
+                } else {
+                    Vec::new()
+                },
+                system_temperatures: match fan_settings {
+                    Some(settings) if pmfw_curve.is_none() => read_system_temps(
+                        std::iter::once(&settings.temperature_key)
+                            .chain(settings.sensor_curves.keys()),
+                    ),
+                    _ => IndexMap::new(),
+                },
                 speed_current: self.hw_mon_and_then(HwMon::get_fan_current).or_else(|| {
                     metrics
                         .and_then(MetricsInfo::get_current_fan_speed)
@@ -1270,7 +1482,12 @@ impl GpuController for AmdGpuController {
                             }
                         }
                         lact_schema::FanControlMode::Curve => {
-                            if settings.curve.0.is_empty() {
+                            if settings.curve.0.is_empty()
+                                || settings
+                                    .sensor_curves
+                                    .values()
+                                    .any(|sensor| sensor.curve.0.is_empty())
+                            {
                                 return Err(anyhow!("Cannot use empty fan curve"));
                             }
 
@@ -1282,6 +1499,21 @@ impl GpuController for AmdGpuController {
                                 commit_handles.push_front(commit_handle);
                             }
                         }
+                        lact_schema::FanControlMode::Pid => {
+                            // The fan speed can't be set directly when the firmware controls the fan curve
+                            if self.handle.get_fan_curve().is_ok() {
+                                return Err(anyhow!(
+                                    "PID fan control is not supported on GPUs with a firmware fan curve, use a fan curve instead"
+                                ));
+                            }
+
+                            self.start_curve_fan_control_task(
+                                settings.curve.clone(),
+                                settings.clone(),
+                            )
+                            .await
+                            .context("Failed to set PID fan control")?;
+                        }
                     }
                 } else {
                     return Err(anyhow!(
@@ -1467,6 +1699,28 @@ impl GpuController for AmdGpuController {
         Ok(())
     }
 
+    fn fan_calibrator(&self) -> LocalBoxFuture<'_, anyhow::Result<FanCalibrator>> {
+        async {
+            if self.handle.get_fan_curve().is_ok() {
+                bail!("Fan calibration is not supported on GPUs with a firmware fan curve");
+            }
+
+            let hw_mon = self
+                .handle
+                .hw_monitors
+                .first()
+                .cloned()
+                .context("This GPU has no monitor")?;
+            hw_mon
+                .get_fan_current()
+                .context("This GPU does not report the fan speed")?;
+            self.stop_fan_control(false).await?;
+
+            FanCalibrator::new(hw_mon)
+        }
+        .boxed_local()
+    }
+
     fn cleanup(&self) -> LocalBoxFuture<'_, ()> {
         async {
             if let Some((fan_notify, fan_handle)) = self.fan_control_handle.take() {
@@ -1537,6 +1791,17 @@ impl GpuController for AmdGpuController {
     }
 }
 
+fn apply_failsafe(hw_mon: &HwMon, action: FailsafeAction) -> Option<u8> {
+    let (result, pwm) = match action {
+        FailsafeAction::MaxSpeed => (hw_mon.set_fan_pwm(u8::MAX), Some(u8::MAX)),
+        FailsafeAction::Auto => (hw_mon.set_fan_control_method(FanControlMethod::Auto), None),
+    };
+    if let Err(err) = result {
+        error!("could not apply fan control failsafe: {err}");
+    }
+    pwm
+}
+
 #[cfg(not(test))]
 fn get_drm_handle(
     common: &CommonControllerInfo,
@@ -1,7 +1,44 @@
-use amdgpu_sysfs::{gpu_handle::fan_control::FanCurve as PmfwCurve, hw_mon::Temperature};
+use crate::server::events;
+use amdgpu_sysfs::{
+    gpu_handle::fan_control::FanCurve as PmfwCurve,
+    hw_mon::{FanControlMethod, HwMon, Temperature},
+};
 use anyhow::{Context, anyhow, bail};
-use lact_schema::config::FanCurve;
-use tracing::warn;
+use lact_schema::{
+    DaemonEvent, FanCalibration, FanCalibrationPoint,
+    config::{
+        FailsafeAction, FanControlSettings, FanCurve, FanFailsafe, FanPidSettings,
+        SensorCombination,
+    },
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    time::Duration,
+};
+use tokio::time::{sleep, timeout};
+use tracing::{debug, error, info, warn};
+
+/// Consecutive errors after which a fan control task gives up and hands the fans back to the firmware
+pub const FAN_CONTROL_RETRIES: u32 = 10;
+/// PWM step when lowering the fan speed during calibration
+const CALIBRATION_STEP_DOWN: usize = 16;
+/// PWM step when looking for the start threshold during calibration
+const CALIBRATION_STEP_UP: usize = 4;
+/// How long to wait for the fan speed to settle after changing the PWM during calibration
+const CALIBRATION_SETTLE_TIME: Duration = Duration::from_secs(3);
+/// Calibration is aborted when any of the GPU's sensors reaches this temperature
+const CALIBRATION_MAX_TEMPERATURE: f32 = 80.0;
+/// Calibration is aborted if it takes longer than this
+const CALIBRATION_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Reason of the active failsafe, shared between a fan control task and its controller
+pub type FailsafeState = Rc<RefCell<Option<String>>>;
+
+/// Fan PWM last applied by the daemon, shared between a fan control task and its controller.
+/// Unset while the firmware is in control of the fans.
+pub type AppliedPwm = Rc<Cell<Option<u8>>>;
 
 pub trait FanCurveExt {
     fn pwm_at_temp(&self, temp: Temperature) -> u8;
@@ -93,12 +130,453 @@ impl FanCurveExt for FanCurve {
     }
 }
 
+/// Temperature smoothing, hysteresis and ramp rate limiting applied on top of the curve
+/// in the software fan control loops
+#[derive(Debug)]
+pub struct CurveSmoothing {
+    /// Weight of a new reading in the moving average, `1.0` disables smoothing
+    alpha: f32,
+    hysteresis_up: f32,
+    hysteresis_down: f32,
+    max_step: Option<u8>,
+    /// Moving average of every sensor used by the curve
+    average_temps: HashMap<String, f32>,
+    /// Last applied pwm and the curve target it was heading towards
+    last_step: Option<(u8, u8)>,
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+impl CurveSmoothing {
+    pub fn new(settings: &FanControlSettings) -> Self {
+        let interval = settings.interval_ms.max(1) as f32;
+        let alpha = settings
+            .smoothing_window_ms
+            .map_or(1.0, |window| interval / (window as f32 + interval));
+
+        let max_step = settings
+            .ramp_rate
+            .map(|rate| ((rate.clamp(0.0, 1.0) * f32::from(u8::MAX)) as u8).max(1));
+
+        Self {
+            alpha,
+            hysteresis_up: settings.hysteresis_up.unwrap_or(0) as f32,
+            hysteresis_down: settings.hysteresis_down.unwrap_or(0) as f32,
+            max_step,
+            average_temps: HashMap::new(),
+            last_step: None,
+        }
+    }
+
+    /// Adds a reading to the moving average of a sensor and returns the smoothed temperature
+    pub fn smooth_temp(&mut self, sensor: &str, temp: f32) -> f32 {
+        let average = match self.average_temps.get(sensor) {
+            Some(average) => average + self.alpha * (temp - average),
+            None => temp,
+        };
+        self.average_temps.insert(sensor.to_owned(), average);
+        average
+    }
+
+    /// Whether the temperature moved far enough from the one the current speed was set at
+    pub fn outside_hysteresis(&self, last_temp: f32, temp: f32) -> bool {
+        if temp >= last_temp {
+            temp - last_temp >= self.hysteresis_up
+        } else {
+            last_temp - temp >= self.hysteresis_down
+        }
+    }
+
+    /// Whether the last applied speed was cut short of the curve target by the ramp rate
+    pub fn is_ramping(&self) -> bool {
+        self.last_step.is_some_and(|(pwm, target)| pwm != target)
+    }
+
+    /// Whether the fan is being slowed down in steps, in which case the spindown delay already passed
+    pub fn is_ramping_down(&self) -> bool {
+        self.last_step.is_some_and(|(pwm, target)| target < pwm)
+    }
+
+    /// Limits the change from the previously applied speed to the configured ramp rate
+    pub fn limit_ramp(&mut self, previous_pwm: Option<u8>, target_pwm: u8) -> u8 {
+        let pwm = match (previous_pwm, self.max_step) {
+            (Some(previous), Some(max_step)) if target_pwm > previous => {
+                target_pwm.min(previous.saturating_add(max_step))
+            }
+            (Some(previous), Some(max_step)) => target_pwm.max(previous.saturating_sub(max_step)),
+            _ => target_pwm,
+        };
+        self.last_step = Some((pwm, target_pwm));
+        pwm
+    }
+}
+
+/// Stops the fan below a temperature and restarts it above another one, for GPUs without firmware zero RPM support
+#[derive(Debug)]
+pub struct FanStop {
+    stop_temp: Option<f32>,
+    start_temp: f32,
+    start_pwm: u8,
+    min_pwm: u8,
+    stopped: bool,
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+impl FanStop {
+    pub fn new(settings: &FanControlSettings) -> Self {
+        let stop_temp = settings.zero_rpm_stop_temperature.map(|temp| temp as f32);
+        let start_temp = settings
+            .zero_rpm_start_temperature
+            .map(|temp| temp as f32)
+            .or(stop_temp)
+            .unwrap_or_default();
+        let start_pwm = settings.zero_rpm_start_speed.map_or(0, |speed| {
+            (speed.clamp(0.0, 1.0) * f32::from(u8::MAX)) as u8
+        });
+        let min_pwm = settings.min_speed.map_or(0, |speed| {
+            (speed.clamp(0.0, 1.0) * f32::from(u8::MAX)) as u8
+        });
+
+        Self {
+            stop_temp,
+            start_temp: start_temp.max(stop_temp.unwrap_or_default()),
+            start_pwm,
+            min_pwm,
+            stopped: false,
+        }
+    }
+
+    /// Returns the target pwm, which is 0 while the fan is stopped
+    pub fn apply(&mut self, temp: f32, target_pwm: u8) -> u8 {
+        let Some(stop_temp) = self.stop_temp else {
+            return target_pwm;
+        };
+
+        if self.stopped {
+            if temp > self.start_temp {
+                self.stopped = false;
+            }
+        } else if temp < stop_temp {
+            self.stopped = true;
+        }
+
+        if self.stopped { 0 } else { target_pwm }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Raises the speed to the configured start speed when the fan is starting from a standstill,
+    /// and keeps a spinning fan above its minimum speed
+    pub fn kick_start(&self, previous_pwm: Option<u8>, pwm: u8) -> u8 {
+        if pwm == 0 {
+            0
+        } else if previous_pwm == Some(0) {
+            pwm.max(self.start_pwm).max(self.min_pwm)
+        } else {
+            pwm.max(self.min_pwm)
+        }
+    }
+}
+
+/// Holds a target temperature by adjusting the fan speed
+#[derive(Debug, Clone)]
+pub struct PidController {
+    settings: FanPidSettings,
+    integral: f32,
+    last_error: Option<f32>,
+}
+
+impl PidController {
+    pub fn new(settings: FanPidSettings) -> anyhow::Result<Self> {
+        if !(0.0..=1.0).contains(&settings.min_speed)
+            || !(0.0..=1.0).contains(&settings.max_speed)
+            || settings.min_speed > settings.max_speed
+        {
+            bail!(
+                "Invalid PID speed range {} to {}",
+                settings.min_speed,
+                settings.max_speed
+            );
+        }
+        if settings.kp < 0.0 || settings.ki < 0.0 || settings.kd < 0.0 {
+            bail!("PID gains cannot be negative");
+        }
+        if settings.integral_limit < 0.0 {
+            bail!("PID integral limit cannot be negative");
+        }
+
+        Ok(Self {
+            settings,
+            integral: 0.0,
+            last_error: None,
+        })
+    }
+
+    /// Returns the pwm for the current temperature, `elapsed` being the time since the previous update
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn update(&mut self, temp: f32, elapsed: Duration) -> u8 {
+        let settings = &self.settings;
+        let dt = elapsed.as_secs_f32();
+        let error = temp - settings.target_temperature;
+
+        self.integral = (self.integral + settings.ki * error * dt)
+            .clamp(-settings.integral_limit, settings.integral_limit);
+
+        let derivative = match self.last_error {
+            Some(last_error) if dt > 0.0 => (error - last_error) / dt,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+
+        let output = settings.kp * error + self.integral + settings.kd * derivative;
+        let speed = output.clamp(settings.min_speed, settings.max_speed);
+        (speed * f32::from(u8::MAX)).round() as u8
+    }
+}
+
+/// Tracks when a fan control task can't follow the curve safely and has to fall back to the failsafe action
+pub struct Failsafe {
+    gpu_id: String,
+    config: Option<FanFailsafe>,
+    state: FailsafeState,
+}
+
+impl Failsafe {
+    pub fn new(gpu_id: String, settings: &FanControlSettings, state: FailsafeState) -> Self {
+        state.replace(None);
+        Self {
+            gpu_id,
+            config: settings.failsafe.clone(),
+            state,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    pub fn action(&self) -> FailsafeAction {
+        self.config
+            .as_ref()
+            .map(|config| config.action)
+            .unwrap_or_default()
+    }
+
+    /// Whether the temperature is at or above the configured critical threshold
+    #[allow(clippy::cast_precision_loss)]
+    pub fn is_critical(&self, temp: f32) -> bool {
+        self.config
+            .as_ref()
+            .and_then(|config| config.critical_temperature)
+            .is_some_and(|critical| temp >= critical as f32)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state.borrow().is_some()
+    }
+
+    pub fn trigger(&self, reason: String) {
+        if self.is_active() {
+            return;
+        }
+
+        warn!("fan control failsafe triggered: {reason}");
+        self.state.replace(Some(reason.clone()));
+        events::emit(DaemonEvent::FanFailsafeTriggered {
+            id: self.gpu_id.clone(),
+            reason,
+        });
+    }
+
+    /// Returns `true` if the failsafe was active before
+    pub fn clear(&self) -> bool {
+        if self.state.replace(None).is_none() {
+            return false;
+        }
+
+        info!("fan control failsafe cleared, following the fan curve again");
+        events::emit(DaemonEvent::FanFailsafeCleared {
+            id: self.gpu_id.clone(),
+        });
+        true
+    }
+
+    /// Marks the failsafe as active after the task gave up, at which point the firmware is in control
+    pub fn exited(&self, reason: &str) {
+        self.state
+            .replace(Some(format!("Fan control exited: {reason}")));
+    }
+}
+
+/// Combines the speeds from multiple sensor curves, given as pairs of pwm and weight
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn combine_pwm(speeds: &[(u8, f32)], combination: SensorCombination) -> Option<u8> {
+    let max_pwm = speeds.iter().map(|(pwm, _)| *pwm).max()?;
+
+    match combination {
+        SensorCombination::Max => Some(max_pwm),
+        SensorCombination::Weighted => {
+            let total_weight: f32 = speeds.iter().map(|(_, weight)| weight.max(0.0)).sum();
+            if total_weight <= 0.0 {
+                return Some(max_pwm);
+            }
+
+            let weighted_sum: f32 = speeds
+                .iter()
+                .map(|(pwm, weight)| f32::from(*pwm) * weight.max(0.0))
+                .sum();
+            Some((weighted_sum / total_weight).round().clamp(0.0, 255.0) as u8)
+        }
+    }
+}
+
+/// Measures the speed of a hwmon fan at different PWM values.
+/// Doesn't borrow the GPU controller, so other requests are not blocked while the calibration is running.
+pub struct FanCalibrator {
+    hw_mon: HwMon,
+    previous_method: FanControlMethod,
+    previous_pwm: Option<u8>,
+}
+
+impl FanCalibrator {
+    /// Remembers the current fan state, which is brought back by `restore`
+    pub fn new(hw_mon: HwMon) -> anyhow::Result<Self> {
+        let previous_method = hw_mon
+            .get_fan_control_method()
+            .context("Could not get fan control method")?;
+        let previous_pwm = hw_mon.get_fan_pwm().ok();
+
+        Ok(Self {
+            hw_mon,
+            previous_method,
+            previous_pwm,
+        })
+    }
+
+    /// Lowers the PWM step by step until the fan stalls, then raises it again until the fan starts.
+    /// Aborts when the GPU gets too hot or when the calibration takes too long.
+    pub async fn run(&self) -> anyhow::Result<FanCalibration> {
+        timeout(CALIBRATION_TIMEOUT, self.sweep())
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Fan calibration did not finish within {}s",
+                    CALIBRATION_TIMEOUT.as_secs()
+                )
+            })?
+    }
+
+    async fn sweep(&self) -> anyhow::Result<FanCalibration> {
+        self.check_temperature()?;
+        self.hw_mon
+            .set_fan_control_method(FanControlMethod::Manual)
+            .context("Could not set fan control method")?;
+
+        let mut points = Vec::new();
+        for pwm in (0..=u8::MAX).rev().step_by(CALIBRATION_STEP_DOWN) {
+            let rpm = self.measure_rpm(pwm).await?;
+            debug!("fan calibration: {rpm} RPM at pwm {pwm}");
+            points.push(FanCalibrationPoint { pwm, rpm });
+
+            if rpm == 0 {
+                break;
+            }
+        }
+
+        let stall_pwm = find_stall_pwm(&points);
+
+        let mut start_pwm = None;
+        if let Some(stopped_point) = points.last().filter(|point| point.rpm == 0) {
+            for pwm in (stopped_point.pwm..=u8::MAX).step_by(CALIBRATION_STEP_UP) {
+                let rpm = self.measure_rpm(pwm).await?;
+                debug!("fan calibration: {rpm} RPM at pwm {pwm} when starting");
+
+                if rpm > 0 {
+                    start_pwm = Some(pwm);
+                    break;
+                }
+            }
+        }
+
+        Ok(FanCalibration {
+            points,
+            stall_pwm,
+            start_pwm,
+            apply_timer: None,
+        })
+    }
+
+    async fn measure_rpm(&self, pwm: u8) -> anyhow::Result<u32> {
+        self.hw_mon
+            .set_fan_pwm(pwm)
+            .with_context(|| format!("Could not set fan pwm to {pwm}"))?;
+        sleep(CALIBRATION_SETTLE_TIME).await;
+        self.check_temperature()?;
+        self.hw_mon
+            .get_fan_current()
+            .context("Could not read the fan speed")
+    }
+
+    fn check_temperature(&self) -> anyhow::Result<()> {
+        let max_temp = self
+            .hw_mon
+            .get_temps()
+            .into_values()
+            .filter_map(|temp| temp.current)
+            .reduce(f32::max)
+            .context("Could not read the GPU temperature")?;
+
+        if max_temp >= CALIBRATION_MAX_TEMPERATURE {
+            bail!(
+                "Fan calibration aborted, the GPU reached {max_temp}°C (limit is {CALIBRATION_MAX_TEMPERATURE}°C)"
+            );
+        }
+        Ok(())
+    }
+
+    /// Brings back the fan control method and speed from before the calibration
+    pub fn restore(self) {
+        debug!("restoring fan control method {:?}", self.previous_method);
+        let restore_pwm = matches!(self.previous_method, FanControlMethod::Manual);
+        if let Err(err) = self.hw_mon.set_fan_control_method(self.previous_method) {
+            error!("could not restore fan control method after calibration: {err}");
+        }
+        if restore_pwm
+            && let Some(pwm) = self.previous_pwm
+            && let Err(err) = self.hw_mon.set_fan_pwm(pwm)
+        {
+            error!("could not restore fan speed after calibration: {err}");
+        }
+    }
+}
+
+/// Finds the lowest pwm at which the fan was still spinning before it stalled,
+/// given measurements taken from the highest pwm to the lowest
+pub fn find_stall_pwm(points: &[FanCalibrationPoint]) -> Option<u8> {
+    let stall_index = points.iter().position(|point| point.rpm == 0)?;
+    points[..stall_index].last().map(|point| point.pwm)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{FanCurve, PmfwCurve};
+    use super::{
+        CurveSmoothing, Failsafe, FanCalibrationPoint, FanCurve, FanStop, PidController, PmfwCurve,
+        combine_pwm, find_stall_pwm,
+    };
     use crate::server::gpu_controller::common::fan_control::FanCurveExt;
     use amdgpu_sysfs::{gpu_handle::fan_control::FanCurveRanges, hw_mon::Temperature};
     use anyhow::anyhow;
+    use lact_schema::config::{FanControlSettings, FanFailsafe, FanPidSettings, SensorCombination};
+    use std::{cell::RefCell, rc::Rc, time::Duration};
 
     fn simple_pwm(temp: f32) -> u8 {
         let curve = FanCurve([(0, 0.0), (100, 1.0)].into());
@@ -237,4 +715,270 @@ mod tests {
                 .to_string()
         );
     }
+
+    fn smoothing(settings: FanControlSettings) -> CurveSmoothing {
+        CurveSmoothing::new(&FanControlSettings {
+            interval_ms: 500,
+            ..settings
+        })
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn smoothing_disabled_by_default() {
+        let mut smoothing = smoothing(FanControlSettings::default());
+        assert_eq!(smoothing.smooth_temp("edge", 40.0), 40.0);
+        assert_eq!(smoothing.smooth_temp("edge", 60.0), 60.0);
+        assert_eq!(smoothing.limit_ramp(Some(0), 255), 255);
+        assert!(!smoothing.is_ramping());
+        assert!(smoothing.outside_hysteresis(50.0, 50.0));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn smoothing_moving_average() {
+        let mut smoothing = smoothing(FanControlSettings {
+            smoothing_window_ms: Some(1500),
+            ..Default::default()
+        });
+        assert_eq!(smoothing.smooth_temp("edge", 40.0), 40.0);
+        assert_eq!(smoothing.smooth_temp("edge", 60.0), 45.0);
+        assert_eq!(smoothing.smooth_temp("junction", 80.0), 80.0);
+        assert_eq!(smoothing.smooth_temp("edge", 60.0), 48.75);
+        assert_eq!(smoothing.smooth_temp("edge", 40.0), 46.5625);
+    }
+
+    #[test]
+    fn separate_hysteresis_bands() {
+        let smoothing = smoothing(FanControlSettings {
+            hysteresis_up: Some(2),
+            hysteresis_down: Some(5),
+            ..Default::default()
+        });
+        assert!(!smoothing.outside_hysteresis(60.0, 61.0));
+        assert!(smoothing.outside_hysteresis(60.0, 62.0));
+        assert!(!smoothing.outside_hysteresis(60.0, 56.0));
+        assert!(smoothing.outside_hysteresis(60.0, 55.0));
+    }
+
+    #[test]
+    fn ramp_rate_limit() {
+        let mut smoothing = smoothing(FanControlSettings {
+            ramp_rate: Some(0.1),
+            ..Default::default()
+        });
+
+        assert_eq!(smoothing.limit_ramp(None, 200), 200);
+        assert!(!smoothing.is_ramping());
+
+        assert_eq!(smoothing.limit_ramp(Some(100), 200), 125);
+        assert!(smoothing.is_ramping());
+        assert!(!smoothing.is_ramping_down());
+        assert_eq!(smoothing.limit_ramp(Some(125), 130), 130);
+        assert!(!smoothing.is_ramping());
+
+        assert_eq!(smoothing.limit_ramp(Some(130), 0), 105);
+        assert!(smoothing.is_ramping_down());
+        assert_eq!(smoothing.limit_ramp(Some(10), 0), 0);
+        assert!(!smoothing.is_ramping());
+    }
+
+    #[test]
+    fn combine_sensor_speeds() {
+        let speeds = [(100, 1.0), (200, 3.0), (50, 0.0)];
+        assert_eq!(combine_pwm(&speeds, SensorCombination::Max), Some(200));
+        assert_eq!(combine_pwm(&speeds, SensorCombination::Weighted), Some(175));
+        assert_eq!(
+            combine_pwm(&[(100, 0.0), (150, 0.0)], SensorCombination::Weighted),
+            Some(150)
+        );
+        assert_eq!(combine_pwm(&[], SensorCombination::Max), None);
+    }
+
+    #[test]
+    fn fan_stop_hysteresis() {
+        let mut fan_stop = FanStop::new(&FanControlSettings {
+            zero_rpm_stop_temperature: Some(45),
+            zero_rpm_start_temperature: Some(55),
+            zero_rpm_start_speed: Some(0.5),
+            ..Default::default()
+        });
+
+        assert_eq!(fan_stop.apply(50.0, 80), 80);
+        assert_eq!(fan_stop.apply(44.0, 60), 0);
+        assert_eq!(fan_stop.apply(50.0, 80), 0);
+        assert_eq!(fan_stop.apply(55.0, 90), 0);
+        assert_eq!(fan_stop.apply(56.0, 90), 90);
+        assert_eq!(fan_stop.apply(50.0, 80), 80);
+    }
+
+    #[test]
+    fn fan_stop_kick_start() {
+        let fan_stop = FanStop::new(&FanControlSettings {
+            zero_rpm_stop_temperature: Some(45),
+            zero_rpm_start_speed: Some(0.5),
+            ..Default::default()
+        });
+
+        assert_eq!(fan_stop.kick_start(Some(0), 60), 127);
+        assert_eq!(fan_stop.kick_start(Some(0), 200), 200);
+        assert_eq!(fan_stop.kick_start(Some(0), 0), 0);
+        assert_eq!(fan_stop.kick_start(Some(127), 60), 60);
+    }
+
+    #[test]
+    fn fan_stop_min_speed() {
+        let fan_stop = FanStop::new(&FanControlSettings {
+            min_speed: Some(0.2),
+            zero_rpm_start_speed: Some(0.4),
+            ..Default::default()
+        });
+
+        assert_eq!(fan_stop.kick_start(Some(100), 20), 51);
+        assert_eq!(fan_stop.kick_start(Some(0), 20), 102);
+        assert_eq!(fan_stop.kick_start(Some(100), 80), 80);
+        assert_eq!(fan_stop.kick_start(Some(100), 0), 0);
+    }
+
+    /// Simple thermal model of a GPU: the heat is removed proportionally to the fan speed
+    struct SimulatedGpu {
+        temp: f32,
+        power: f32,
+    }
+
+    impl SimulatedGpu {
+        const AMBIENT: f32 = 25.0;
+        const HEAT_CAPACITY: f32 = 500.0;
+
+        fn step(&mut self, pwm: u8, dt: f32) {
+            let speed = f32::from(pwm) / f32::from(u8::MAX);
+            let cooling = (2.0 + 8.0 * speed) * (self.temp - Self::AMBIENT);
+            self.temp += dt * (self.power - cooling) / Self::HEAT_CAPACITY;
+        }
+    }
+
+    #[test]
+    fn pid_holds_target_temperature() {
+        let mut pid = PidController::new(FanPidSettings {
+            target_temperature: 70.0,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut gpu = SimulatedGpu {
+            temp: 40.0,
+            power: 200.0,
+        };
+
+        let mut pwm = 0;
+        for _ in 0..900 {
+            pwm = pid.update(gpu.temp, Duration::from_secs(1));
+            gpu.step(pwm, 1.0);
+        }
+        assert!((gpu.temp - 70.0).abs() < 0.5, "temperature {}", gpu.temp);
+        let high_load_pwm = pwm;
+
+        gpu.power = 150.0;
+        for _ in 0..900 {
+            pwm = pid.update(gpu.temp, Duration::from_secs(1));
+            gpu.step(pwm, 1.0);
+        }
+        assert!((gpu.temp - 70.0).abs() < 0.5, "temperature {}", gpu.temp);
+        assert!(pwm < high_load_pwm);
+    }
+
+    #[test]
+    fn pid_output_limits() {
+        let mut pid = PidController::new(FanPidSettings {
+            target_temperature: 70.0,
+            min_speed: 0.2,
+            max_speed: 0.8,
+            integral_limit: 0.1,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(pid.update(30.0, Duration::from_secs(1)), 51);
+        assert_eq!(pid.update(100.0, Duration::from_secs(1)), 204);
+
+        // The integral term stays within its limit after a long time above the target
+        for _ in 0..1000 {
+            pid.update(75.0, Duration::from_secs(1));
+        }
+        assert_eq!(pid.update(70.0, Duration::from_secs(1)), 51);
+    }
+
+    #[test]
+    fn pid_invalid_settings() {
+        let settings = |min_speed, max_speed, kp| FanPidSettings {
+            min_speed,
+            max_speed,
+            kp,
+            ..Default::default()
+        };
+
+        assert!(PidController::new(settings(0.0, 1.0, 0.05)).is_ok());
+        assert!(PidController::new(settings(0.8, 0.2, 0.05)).is_err());
+        assert!(PidController::new(settings(0.0, 1.5, 0.05)).is_err());
+        assert!(PidController::new(settings(0.0, 1.0, -0.05)).is_err());
+    }
+
+    #[test]
+    fn calibration_stall_pwm() {
+        let points = |values: &[(u8, u32)]| {
+            values
+                .iter()
+                .map(|&(pwm, rpm)| FanCalibrationPoint { pwm, rpm })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            Some(47),
+            find_stall_pwm(&points(&[
+                (255, 3000),
+                (79, 900),
+                (47, 410),
+                (31, 0),
+                (15, 0)
+            ]))
+        );
+        assert_eq!(None, find_stall_pwm(&points(&[(255, 3000), (15, 300)])));
+        assert_eq!(None, find_stall_pwm(&points(&[(255, 0), (15, 0)])));
+        assert_eq!(None, find_stall_pwm(&[]));
+    }
+
+    #[test]
+    fn fan_stop_disabled() {
+        let mut fan_stop = FanStop::new(&FanControlSettings::default());
+        assert_eq!(fan_stop.apply(0.0, 0), 0);
+        assert_eq!(fan_stop.apply(20.0, 50), 50);
+        assert_eq!(fan_stop.kick_start(Some(0), 50), 50);
+    }
+
+    #[test]
+    fn failsafe_state() {
+        let state = Rc::new(RefCell::new(Some("stale".to_owned())));
+        let failsafe = Failsafe::new(
+            "gpu".to_owned(),
+            &FanControlSettings {
+                failsafe: Some(FanFailsafe {
+                    critical_temperature: Some(95),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            state.clone(),
+        );
+
+        assert!(!failsafe.is_active());
+        assert!(!failsafe.is_critical(94.5));
+        assert!(failsafe.is_critical(95.0));
+
+        failsafe.trigger("first".to_owned());
+        failsafe.trigger("second".to_owned());
+        assert_eq!(Some("first".to_owned()), *state.borrow());
+
+        assert!(failsafe.clear());
+        assert!(!failsafe.clear());
+        assert_eq!(None, *state.borrow());
+    }
 }
@@ -0,0 +1,209 @@
+//! Temperature sensors of other system components (CPU, NVMe drives, etc.) that can drive the GPU fan curve
+use amdgpu_sysfs::hw_mon::Temperature;
+use anyhow::{Context, bail};
+use indexmap::IndexMap;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::debug;
+
+const HWMON_PATH: &str = "/sys/class/hwmon";
+/// Prefix of temperature keys that refer to a system sensor instead of one of the GPU's own sensors
+pub const SYSTEM_SENSOR_PREFIX: &str = "hwmon:";
+
+/// Scanning all hwmon devices can wake up sleeping devices such as NVMe drives,
+/// so the list is only refreshed when a sensor has to be resolved
+static SYSTEM_SENSORS: Mutex<Option<IndexMap<String, PathBuf>>> = Mutex::new(None);
+
+/// Whether the key is either a `hwmon:<chip>/<label>` name or a path to a `temp*_input` file
+pub fn is_system_sensor(key: &str) -> bool {
+    key.starts_with(SYSTEM_SENSOR_PREFIX) || key.starts_with('/')
+}
+
+/// Lists the keys of all system temperature sensors in the `hwmon:<chip>/<label>` format
+pub fn list_system_sensors() -> Vec<String> {
+    let mut sensors = SYSTEM_SENSORS.lock().unwrap();
+    sensors
+        .get_or_insert_with(|| list_sensors(Path::new(HWMON_PATH)))
+        .keys()
+        .cloned()
+        .collect()
+}
+
+/// Reads the current values of the given keys that refer to system sensors.
+/// Sensors which are not known or can't be read are skipped.
+pub fn read_system_temps<'a>(keys: impl IntoIterator<Item = &'a String>) -> IndexMap<String, f32> {
+    let mut sensors = SYSTEM_SENSORS.lock().unwrap();
+    let sensors = sensors.get_or_insert_with(|| list_sensors(Path::new(HWMON_PATH)));
+
+    keys.into_iter()
+        .filter(|key| is_system_sensor(key))
+        .filter_map(|key| {
+            let path = if key.starts_with('/') {
+                let path = Path::new(key);
+                is_hwmon_temp_input(path).then_some(path)
+            } else {
+                sensors.get(key).map(PathBuf::as_path)
+            }?;
+            Some((key.clone(), read_temp(path)?))
+        })
+        .collect()
+}
+
+/// Finds the `temp*_input` file of a system sensor
+pub fn resolve_system_sensor(key: &str) -> anyhow::Result<PathBuf> {
+    if key.starts_with('/') {
+        let path = PathBuf::from(key);
+        if !is_hwmon_temp_input(&path) {
+            bail!("System sensor path {key} is not a {HWMON_PATH}/*/temp*_input file");
+        }
+        if !path.exists() {
+            bail!("System sensor {key} not found");
+        }
+        return Ok(path);
+    }
+
+    // Sensors might have appeared since the list was cached
+    let sensors = list_sensors(Path::new(HWMON_PATH));
+    let path = sensors.get(key).cloned();
+    *SYSTEM_SENSORS.lock().unwrap() = Some(sensors);
+
+    path.with_context(|| format!("System sensor {key} not found"))
+}
+
+pub fn read_system_temp(path: &Path) -> Option<Temperature> {
+    Some(Temperature {
+        current: Some(read_temp(path)?),
+        crit: None,
+        crit_hyst: None,
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn read_temp(path: &Path) -> Option<f32> {
+    let contents = fs::read_to_string(path).ok()?;
+    let millidegrees: i64 = contents.trim().parse().ok()?;
+    Some(millidegrees as f32 / 1000.0)
+}
+
+/// Only temperature inputs of hwmon devices are allowed, so the daemon can't be used to read arbitrary files
+fn is_hwmon_temp_input(path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(HWMON_PATH) else {
+        return false;
+    };
+
+    match relative.components().collect::<Vec<_>>().as_slice() {
+        [Component::Normal(_), Component::Normal(file)] => file
+            .to_str()
+            .and_then(|file| file.strip_prefix("temp"))
+            .and_then(|file| file.strip_suffix("_input"))
+            .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit())),
+        _ => false,
+    }
+}
+
+fn list_sensors(root: &Path) -> IndexMap<String, PathBuf> {
+    let mut sensors = IndexMap::new();
+
+    let Ok(entries) = fs::read_dir(root) else {
+        debug!("could not read {}", root.display());
+        return sensors;
+    };
+    let mut hwmon_dirs: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    hwmon_dirs.sort();
+
+    for hwmon_dir in hwmon_dirs {
+        let Ok(chip) = fs::read_to_string(hwmon_dir.join("name")) else {
+            continue;
+        };
+        let chip = chip.trim();
+
+        let Ok(files) = fs::read_dir(&hwmon_dir) else {
+            continue;
+        };
+        let mut inputs: Vec<String> = files
+            .flatten()
+            .filter_map(|file| file.file_name().into_string().ok())
+            .filter(|name| name.starts_with("temp") && name.ends_with("_input"))
+            .collect();
+        inputs.sort();
+
+        for input in inputs {
+            let prefix = input.trim_end_matches("_input");
+            let label = fs::read_to_string(hwmon_dir.join(format!("{prefix}_label")))
+                .map(|label| label.trim().to_owned())
+                .unwrap_or_else(|_| prefix.to_owned());
+
+            // With multiple chips of the same kind (e.g. NVMe drives) the first one gets the name,
+            // the others can still be referenced by path
+            sensors
+                .entry(format!("{SYSTEM_SENSOR_PREFIX}{chip}/{label}"))
+                .or_insert_with(|| hwmon_dir.join(&input));
+        }
+    }
+
+    sensors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_hwmon_temp_input, list_sensors, read_temp};
+    use std::{fs, path::Path};
+    use tempfile::tempdir;
+
+    #[test]
+    fn list_hwmon_sensors() {
+        let root = tempdir().unwrap();
+
+        let k10temp = root.path().join("hwmon1");
+        fs::create_dir(&k10temp).unwrap();
+        fs::write(k10temp.join("name"), "k10temp\n").unwrap();
+        fs::write(k10temp.join("temp1_input"), "61250\n").unwrap();
+        fs::write(k10temp.join("temp1_label"), "Tctl\n").unwrap();
+        fs::write(k10temp.join("temp3_input"), "45000\n").unwrap();
+
+        let nvme = root.path().join("hwmon2");
+        fs::create_dir(&nvme).unwrap();
+        fs::write(nvme.join("name"), "nvme\n").unwrap();
+        fs::write(nvme.join("temp1_input"), "38850\n").unwrap();
+        fs::write(nvme.join("temp1_label"), "Composite\n").unwrap();
+
+        let sensors = list_sensors(root.path());
+        assert_eq!(
+            vec![
+                "hwmon:k10temp/Tctl",
+                "hwmon:k10temp/temp3",
+                "hwmon:nvme/Composite"
+            ],
+            sensors.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(Some(61.25), read_temp(&sensors["hwmon:k10temp/Tctl"]));
+        assert_eq!(Some(38.85), read_temp(&sensors["hwmon:nvme/Composite"]));
+    }
+
+    #[test]
+    fn only_hwmon_temp_inputs_allowed() {
+        assert!(is_hwmon_temp_input(Path::new(
+            "/sys/class/hwmon/hwmon3/temp1_input"
+        )));
+        assert!(is_hwmon_temp_input(Path::new(
+            "/sys/class/hwmon/hwmon0/temp12_input"
+        )));
+
+        assert!(!is_hwmon_temp_input(Path::new("/etc/shadow")));
+        assert!(!is_hwmon_temp_input(Path::new(
+            "/sys/class/hwmon/hwmon3/fan1_input"
+        )));
+        assert!(!is_hwmon_temp_input(Path::new(
+            "/sys/class/hwmon/hwmon3/temp_input"
+        )));
+        assert!(!is_hwmon_temp_input(Path::new(
+            "/sys/class/hwmon/../../../etc/temp1_input"
+        )));
+        assert!(!is_hwmon_temp_input(Path::new(
+            "/sys/class/hwmon/hwmon3/device/temp1_input"
+        )));
+    }
+}
@@ -915,6 +915,9 @@ impl GpuController for IntelGpuController {
                         lact_schema::FanControlMode::Curve => {
                             self.apply_fan_curve(&settings.curve)?;
                         }
+                        lact_schema::FanControlMode::Pid => {
+                            bail!("PID fan control is not supported on Intel GPUs");
+                        }
                     }
                 }
             } else if self.has_fan_control() && self.get_hwmon_fan_control_mode().is_some() {
@@ -4,10 +4,21 @@ pub mod nvapi;
 use super::{CommonControllerInfo, FanControlHandle, GpuController};
 use crate::{
     bindings::nvidia::NvPhysicalGpuHandle,
-    server::gpu_controller::{
-        common::{fan_control::FanCurveExt, resolve_process_name},
-        nvidia::nvapi::{
-            CLOCK_CLIENT_CLK_VF_POINT_TYPE_PROG, ClockClientClkVfPointInfoV1, NvGpuClockDomainId,
+    server::{
+        events,
+        gpu_controller::{
+            common::{
+                fan_control::{
+                    AppliedPwm, CurveSmoothing, FAN_CONTROL_RETRIES, Failsafe, FailsafeState,
+                    FanCurveExt, FanStop, PidController,
+                },
+                resolve_process_name,
+                system_sensors::is_system_sensor,
+            },
+            nvidia::nvapi::{
+                CLOCK_CLIENT_CLK_VF_POINT_TYPE_PROG, ClockClientClkVfPointInfoV1,
+                NvGpuClockDomainId,
+            },
         },
     },
 };
@@ -20,12 +31,13 @@ use driver::DriverHandle;
 use futures::{FutureExt, future::LocalBoxFuture};
 use indexmap::IndexMap;
 use lact_schema::{
-    ActivePowerStates, CacheInfo, ClocksInfo, ClocksTable, ClockspeedStats, DeviceApiInfo,
-    DeviceFlag, DeviceInfo, DeviceStats, DeviceType, DrmInfo, DrmMemoryInfo, FanControlMode,
-    FanStats, IntelDrmInfo, LinkInfo, NvidiaClockOffset, NvidiaClocksTable, NvidiaThermalInfo,
-    NvidiaVfPoint, NvidiaVoltageBoost, PmfwInfo, PowerState, PowerStates, PowerStats, ProcessInfo,
-    ProcessList, ProcessType, ProcessUtilizationType, TemperatureEntry, VoltageStats, VramStats,
-    config::{CurvePoint, FanControlSettings, FanCurve, GpuConfig},
+    ActivePowerStates, CacheInfo, ClocksInfo, ClocksTable, ClockspeedStats, DaemonEvent,
+    DeviceApiInfo, DeviceFlag, DeviceInfo, DeviceStats, DeviceType, DrmInfo, DrmMemoryInfo,
+    FanControlMode, FanStats, IntelDrmInfo, LinkInfo, NvidiaClockOffset, NvidiaClocksTable,
+    NvidiaThermalInfo, NvidiaVfPoint, NvidiaVoltageBoost, PmfwInfo, PowerState, PowerStates,
+    PowerStats, ProcessInfo, ProcessList, ProcessType, ProcessUtilizationType, TemperatureEntry,
+    VoltageStats, VramStats,
+    config::{CurvePoint, FailsafeAction, FanControlSettings, FanCurve, GpuConfig},
 };
 use nvapi::NvApi;
 use nvml_wrapper::{
@@ -45,7 +57,7 @@ use std::{
     time::{Duration, Instant},
 };
 use tokio::{select, sync::Notify, time::sleep};
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 
 const SUPPORTED_UTIL_TYPES: &[ProcessUtilizationType] = &[
     ProcessUtilizationType::Graphics,
@@ -60,6 +72,8 @@ pub struct NvidiaGpuController {
     nvml: Rc<Nvml>,
     common: CommonControllerInfo,
     fan_control_handle: RefCell<Option<FanControlHandle>>,
+    fan_failsafe: FailsafeState,
+    applied_fan_pwm: AppliedPwm,
     initial_target_temp: Option<u32>,
 
     nvapi: Option<(Rc<NvApi>, NvPhysicalGpuHandle)>,
@@ -146,6 +160,8 @@ impl NvidiaGpuController {
             initial_target_temp: target_temp,
             last_util_timestamp: Cell::new(None),
             fan_control_handle: RefCell::new(None),
+            fan_failsafe: FailsafeState::default(),
+            applied_fan_pwm: AppliedPwm::default(),
             last_applied_offsets: RefCell::new(HashMap::new()),
             last_applied_gpu_locked_clocks: RefCell::new(None),
             last_applied_vram_locked_clocks: RefCell::new(None),
@@ -191,6 +207,10 @@ impl NvidiaGpuController {
         curve: FanCurve,
         settings: FanControlSettings,
     ) -> anyhow::Result<()> {
+        if is_system_sensor(&settings.temperature_key) {
+            bail!("System temperature sensors are not supported on Nvidia GPUs");
+        }
+
         // Stop existing task to re-apply new curve
         self.stop_fan_control().await?;
 
@@ -212,8 +232,19 @@ impl NvidiaGpuController {
         let notify = Rc::new(Notify::new());
         let task_notify = notify.clone();
 
+        let mut pid = match settings.mode {
+            FanControlMode::Pid => Some(
+                PidController::new(settings.pid.clone().unwrap_or_default())
+                    .context("Invalid PID settings")?,
+            ),
+            _ => None,
+        };
+
         let nvml = self.nvml.clone();
         let pci_slot_id = self.common.pci_slot_name.clone();
+        let gpu_id = self.common.build_id();
+        let failsafe = Failsafe::new(gpu_id.clone(), &settings, self.fan_failsafe.clone());
+        let applied_pwm = self.applied_fan_pwm.clone();
         debug!("spawning new fan control task");
 
         let handle = tokio::task::spawn_local(async move {
@@ -222,16 +253,31 @@ impl NvidiaGpuController {
                 .expect("Can no longer get device");
 
             let mut last_pwm = (None, Instant::now());
-            let mut last_temp = 0;
+            let mut last_temp = 0.0;
 
             let interval = Duration::from_millis(settings.interval_ms);
             let spindown_delay = Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0));
-            #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-            let change_threshold = settings.change_threshold.unwrap_or(0) as i32;
-            #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-            let auto_threshold = settings.auto_threshold.unwrap_or(0) as i32;
+            #[allow(clippy::cast_precision_loss)]
+            let change_threshold = settings.change_threshold.unwrap_or(0) as f32;
+            #[allow(clippy::cast_precision_loss)]
+            let auto_threshold = settings.auto_threshold.unwrap_or(0) as f32;
+            let mut smoothing = CurveSmoothing::new(&settings);
+            let mut fan_stop = FanStop::new(&settings);
+            // Ticks can be skipped or delayed, so the PID controller gets the actual time between updates
+            let mut last_pid_update = Instant::now();
+            let min_speed = device
+                .min_max_fan_speed()
+                .map(|(min_speed, _)| min_speed)
+                .unwrap_or(0);
+            if min_speed > 0 && settings.zero_rpm_stop_temperature.is_some() {
+                info!(
+                    "fans can't be set below {min_speed}% on this GPU, zero RPM depends on the fan policy of the vBIOS"
+                );
+            }
 
             let mut manual_mode = true;
+            let mut exit_reason = None;
+            let mut retries = 0;
 
             loop {
                 select! {
@@ -239,73 +285,169 @@ impl NvidiaGpuController {
                     () = task_notify.notified() => break,
                 }
 
-                #[allow(clippy::cast_possible_wrap)]
-                let current_temp = device
-                    .temperature(TemperatureSensor::Gpu)
-                    .expect("Could not read temperature") as i32;
+                #[allow(clippy::cast_precision_loss)]
+                let raw_temp = match device.temperature(TemperatureSensor::Gpu) {
+                    Ok(temp) => temp as f32,
+                    Err(err) => {
+                        if failsafe.is_enabled() {
+                            // Keep the task running, so the curve is followed again once the sensor is back
+                            failsafe.trigger(format!("Could not read temperature: {err}"));
+                            manual_mode = apply_failsafe(&mut device, fan_count, failsafe.action());
+                            applied_pwm.set(manual_mode.then_some(u8::MAX));
+                            continue;
+                        }
+
+                        retries += 1;
+
+                        if retries == FAN_CONTROL_RETRIES {
+                            error!(
+                                "could not read temperature: {err}, exiting fan control (reached max attempts)"
+                            );
+                            exit_reason = Some(format!("Could not read temperature: {err}"));
+                            break;
+                        }
+                        error!(
+                            "could not read temperature: {err} (assuming error is temporary, attempt {retries}/{FAN_CONTROL_RETRIES})"
+                        );
+                        continue;
+                    }
+                };
+                retries = 0;
+
+                if failsafe.is_critical(raw_temp) {
+                    failsafe.trigger(format!(
+                        "Temperature {raw_temp}°C reached the critical threshold"
+                    ));
+                    manual_mode = apply_failsafe(&mut device, fan_count, failsafe.action());
+                    applied_pwm.set(manual_mode.then_some(u8::MAX));
+                    continue;
+                }
+
+                let recovered = failsafe.clear();
+                let current_temp = smoothing.smooth_temp("GPU", raw_temp);
+                // The speed has to be set again after recovering from the failsafe,
+                // and the PID controller has to be updated on every tick
+                let force_update = recovered || smoothing.is_ramping() || pid.is_some();
 
-                if (last_temp - current_temp).abs() < change_threshold {
+                if !force_update && (last_temp - current_temp).abs() < change_threshold {
                     trace!(
                         "temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment"
                     );
                     continue;
                 }
 
+                if !force_update && !smoothing.outside_hysteresis(last_temp, current_temp) {
+                    trace!(
+                        "temperature changed from {last_temp}°C to {current_temp}°C, which is within the hysteresis band, skipping speed adjustment"
+                    );
+                    continue;
+                }
+
                 if current_temp < auto_threshold {
                     if manual_mode {
                         trace!("temperature below auto threshold, setting fan policy to auto");
-                        for fan in 0..fan_count {
-                            if let Err(err) = device.set_default_fan_speed(fan) {
-                                error!(
-                                    "could not set fan speed to auto: {err}, disabling fan control"
-                                );
-                                break;
-                            }
+                        if let Err(err) =
+                            (0..fan_count).try_for_each(|fan| device.set_default_fan_speed(fan))
+                        {
+                            error!("could not set fan speed to auto: {err}, disabling fan control");
+                            exit_reason = Some(format!("Could not set fan speed to auto: {err}"));
+                            break;
                         }
 
                         manual_mode = false;
+                        applied_pwm.set(None);
                     } else {
                         trace!("temperature below auto threshold, skipping control");
                     }
                     continue;
                 }
 
-                let target_pwm = curve.pwm_at_temp(Temperature {
-                    #[allow(clippy::cast_precision_loss)]
-                    current: Some(current_temp as f32),
-                    crit: None,
-                    crit_hyst: None,
-                });
+                // The PID output is not applied while the fan is stopped or its spindown is delayed,
+                // so the state from before the update is restored to keep the integral from winding up
+                let pid_snapshot = pid.clone();
+                let target_pwm = if let Some(pid) = &mut pid {
+                    let elapsed = last_pid_update.elapsed();
+                    last_pid_update = Instant::now();
+                    pid.update(current_temp, elapsed)
+                } else {
+                    curve.pwm_at_temp(Temperature {
+                        current: Some(current_temp),
+                        crit: None,
+                        crit_hyst: None,
+                    })
+                };
+                let target_pwm = fan_stop.apply(current_temp, target_pwm);
+                if fan_stop.is_stopped() {
+                    pid.clone_from(&pid_snapshot);
+                }
                 let now = Instant::now();
 
                 if let (Some(previous_pwm), previous_timestamp) = last_pwm {
                     let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
+                    if target_pwm < previous_pwm
+                        && diff < spindown_delay
+                        && !smoothing.is_ramping_down()
+                    {
                         trace!(
                             "delaying fan spindown ({}ms left)",
                             spindown_delay.checked_sub(diff).unwrap().as_millis()
                         );
+                        pid.clone_from(&pid_snapshot);
                         continue;
                     }
                 }
 
+                let target_pwm = smoothing.limit_ramp(last_pwm.0, target_pwm);
+                let target_pwm = fan_stop.kick_start(last_pwm.0, target_pwm);
                 last_pwm = (Some(target_pwm), now);
                 last_temp = current_temp;
 
+                if target_pwm == 0 && min_speed > 0 {
+                    // The fans can't be stopped manually, so they are handed back to the firmware.
+                    // Whether they actually stop depends on the fan policy of the vBIOS.
+                    if manual_mode {
+                        trace!("stopping fans, setting fan policy to auto");
+                        for fan in 0..fan_count {
+                            if let Err(err) = device.set_default_fan_speed(fan) {
+                                error!("could not set fan speed to auto: {err}");
+                                break;
+                            }
+                        }
+                        manual_mode = false;
+                        applied_pwm.set(None);
+                    }
+                    continue;
+                }
+
                 trace!("fan control tick: setting pwm to {target_pwm}");
 
-                for fan in 0..fan_count {
-                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-                    if let Err(err) =
-                        device.set_fan_speed(fan, (f64::from(target_pwm) / 2.55) as u32)
-                    {
-                        error!("could not set fan speed: {err}, disabling fan control");
-                        break;
-                    }
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let speed = ((f64::from(target_pwm) / 2.55) as u32).max(min_speed);
+
+                if let Err(err) =
+                    (0..fan_count).try_for_each(|fan| device.set_fan_speed(fan, speed))
+                {
+                    error!("could not set fan speed: {err}, disabling fan control");
+                    exit_reason = Some(format!("Could not set fan speed: {err}"));
+                    break;
                 }
                 manual_mode = true;
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                applied_pwm.set(Some((f64::from(speed) * 2.55) as u8));
             }
             debug!("exited fan control task");
+            applied_pwm.set(None);
+
+            if let Some(reason) = exit_reason {
+                if let Err(err) =
+                    (0..fan_count).try_for_each(|fan| device.set_default_fan_speed(fan))
+                {
+                    error!("could not reset fan control back to auto: {err}");
+                }
+
+                failsafe.exited(&reason);
+                events::emit(DaemonEvent::FanControlExited { id: gpu_id, reason });
+            }
         });
 
         *notify_guard = Some((notify, handle));
@@ -331,6 +473,8 @@ impl NvidiaGpuController {
             handle.await?;
             fail_on_error = true;
         }
+        self.fan_failsafe.replace(None);
+        self.applied_fan_pwm.set(None);
 
         let mut device = self.device();
         let fan_count = device.num_fans().context("Could not get fan count")?;
@@ -625,6 +769,20 @@ impl NvidiaGpuController {
     }
 }
 
+/// Returns whether the fans are left in manual mode
+fn apply_failsafe(device: &mut Device<'_>, fan_count: u32, action: FailsafeAction) -> bool {
+    for fan in 0..fan_count {
+        let result = match action {
+            FailsafeAction::MaxSpeed => device.set_fan_speed(fan, 100),
+            FailsafeAction::Auto => device.set_default_fan_speed(fan),
+        };
+        if let Err(err) = result {
+            error!("could not apply fan control failsafe: {err}");
+        }
+    }
+    action == FailsafeAction::MaxSpeed
+}
+
 fn vf_curve_point_is_editable(point: ClockClientClkVfPointInfoV1) -> bool {
     point.b_voltage_based == 1 && point.type_ == CLOCK_CLIENT_CLK_VF_POINT_TYPE_PROG
 }
@@ -1026,6 +1184,13 @@ impl GpuController for NvidiaGpuController {
                 change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
                 auto_threshold: fan_settings.and_then(|settings| settings.auto_threshold),
                 temperature_key: None,
+                sensor_curves: IndexMap::new(),
+                sensor_combination: None,
+                pid: fan_settings.and_then(|settings| settings.pid.clone()),
+                system_sensors: Vec::new(),
+                system_temperatures: IndexMap::new(),
+                failsafe: self.fan_failsafe.borrow().clone(),
+                pwm_target: self.applied_fan_pwm.get(),
                 speed_current,
                 speed_max: None,
                 speed_min: None,
@@ -1302,9 +1467,16 @@ impl GpuController for NvidiaGpuController {
                                 .set_fan_speed(fan, speed)
                                 .context("Could not reset fan speed to default")?;
                         }
+                        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                        self.applied_fan_pwm
+                            .set(Some((f64::from(speed) * 2.55) as u8));
                     }
 
                     FanControlMode::Curve => {
+                        if !settings.sensor_curves.is_empty() {
+                            bail!("Per-sensor fan curves are not supported on Nvidia GPUs");
+                        }
+
                         let (min_speed, max_speed) = device
                             .min_max_fan_speed()
                             .context("Could not get fan speed range")?;
@@ -1322,6 +1494,12 @@ impl GpuController for NvidiaGpuController {
                         self.start_curve_fan_control_task(settings.curve.clone(), settings.clone())
                             .await?;
                     }
+
+                    FanControlMode::Pid => {
+                        self.start_curve_fan_control_task(settings.curve.clone(), settings.clone())
+                            .await
+                            .context("Failed to set PID fan control")?;
+                    }
                 }
             } else {
                 self.stop_fan_control()
@@ -10,9 +10,14 @@ use crate::{
     bindings::intel::IntelDrm,
     config::Config,
     server::{
-        ClientContext,
+        CHANGE_SOURCE, ClientContext, RequestSource, events,
         gpu_controller::{build_controller_info, init_controller},
+        history::StatsHistoryStore,
         profiles,
+        recovery::RecoveryState,
+        revisions::ConfigRevisions,
+        stability::StabilityTests,
+        stats::{StatsSamplers, StatsSubscription},
         system::DAEMON_VERSION,
     },
 };
@@ -21,18 +26,22 @@ use amdgpu_sysfs::gpu_handle::{
 };
 use anyhow::{Context, anyhow, bail};
 use lact_schema::{
-    ClocksInfo, DeviceApiInfo, DeviceInfo, DeviceListEntry, DeviceStats, DisplaysInfo,
-    FanControlMode, FanOptions, PmfwOptions, PowerStates, ProcessList, ProfileRule,
-    ProfileWatcherState, ProfilesInfo,
+    ClocksInfo, ConfigRecovery, DaemonEvent, DeviceApiInfo, DeviceInfo, DeviceListEntry,
+    DeviceStats, DisplaysInfo, FanCalibration, FanControlMode, FanOptions, FocusedWindow,
+    PmfwOptions, PowerStates, ProcessList, ProfileRule, ProfileWatcherState, ProfilesInfo,
     config::{
         FanControlSettings, FanCurve, GpuConfig, Profile, ProfileHooks, default_fan_static_speed,
     },
     default_fan_curve,
+    export::{PROFILE_EXPORT_VERSION, ProfileExport},
+    history::{StatType, StatsHistorySample},
     request::{ClockspeedType, ConfirmCommand, ProfileBase, SetClocksCommand},
+    revisions::{ConfigChangeSource, ConfigDiffEntry, ConfigRevision, diff_configs},
+    stability::{StabilityTestOptions, StabilityTestStatus},
 };
 use libdrm_amdgpu_sys::LibDrmAmdgpu;
 use libflate::gzip;
-use nix::libc;
+use nix::{libc, unistd::Uid};
 use pciid_parser::Database;
 use serde_json::json;
 use std::{
@@ -50,7 +59,7 @@ use std::{
 use tokio::{
     process::Command,
     select,
-    sync::{RwLock, RwLockReadGuard, mpsc, oneshot},
+    sync::{RwLock, RwLockReadGuard, broadcast, mpsc, oneshot},
     task::JoinHandle,
     time::sleep,
 };
@@ -91,6 +100,7 @@ const CONFIG_RESET_CMDLINE_ARG: &str = "lact-reset";
 
 mod polkit_actions {
     pub const PROFILE_HOOK: &str = "io.github.ilya_zlobintsev.LACT.profile-hook";
+    pub const STABILITY_TEST: &str = "io.github.ilya_zlobintsev.LACT.stability-test";
 }
 
 type ProfileHolds = Rc<RefCell<Vec<(u64, Rc<str>, mpsc::Sender<()>)>>>;
@@ -100,10 +110,15 @@ type ProfileHoldSnapshot = Rc<RefCell<Option<(Option<Rc<str>>, bool)>>>;
 pub struct Handler {
     pub config: Rc<RwLock<Config>>,
     gpu_controllers: Rc<RwLock<BTreeMap<String, DynGpuController>>>,
-    confirm_config_tx: Rc<RefCell<Option<oneshot::Sender<ConfirmCommand>>>>,
+    /// GPU id of the pending config change and its confirmation sender
+    confirm_config_tx: Rc<RefCell<Option<(String, oneshot::Sender<ConfirmCommand>)>>>,
     pub config_last_saved: Rc<Cell<Instant>>,
     profile_watcher_tx: Rc<RefCell<Option<mpsc::Sender<ProfileWatcherCommand>>>>,
     pub profile_watcher_state: Rc<RefCell<Option<ProfileWatcherState>>>,
+    /// Last window reported by a focus agent, kept across profile watcher restarts
+    pub focused_window: Rc<RefCell<Option<FocusedWindow>>>,
+    /// Disconnect notifier of the focus agent connection that reported the focused window
+    focus_agent_connection: Rc<RefCell<Option<std::sync::Arc<tokio::sync::Notify>>>>,
     profile_watcher_join_handle: Rc<RefCell<Option<JoinHandle<()>>>>,
     profile_holds: ProfileHolds,
     profile_hold_snapshot: ProfileHoldSnapshot,
@@ -111,6 +126,12 @@ pub struct Handler {
     polkit_proxy: Option<AuthorityProxy<'static>>,
     ignored_gpu_ids: Rc<RwLock<Vec<String>>>,
     reload_tx: Rc<mpsc::Sender<Duration>>,
+    stats_samplers: StatsSamplers,
+    pub stats_history: StatsHistoryStore,
+    stability_tests: StabilityTests,
+    recovery: Rc<RefCell<RecoveryState>>,
+    boot_id: Option<String>,
+    revisions: ConfigRevisions,
 }
 
 impl<'a> Handler {
@@ -184,7 +205,34 @@ impl<'a> Handler {
 
         let original_config_version = config.version;
         config.migrate_versions(&controllers);
-        if config.version != original_config_version {
+        let mut config_changed = config.version != original_config_version;
+
+        let boot_id = system::boot_id()
+            .inspect_err(|err| warn!("{err:#}, known good settings will not be tracked"))
+            .ok();
+        let mut safe_mode_ids = Vec::new();
+        if let Some(boot_id) = &boot_id {
+            if config.daemon.reset_recovery.safe_mode {
+                match config.apply_safe_mode(boot_id) {
+                    Ok(ids) => safe_mode_ids = ids,
+                    Err(err) => error!("could not apply safe mode: {err:#}"),
+                }
+                if !safe_mode_ids.is_empty() {
+                    warn!(
+                        "settings of {safe_mode_ids:?} did not become known good in the previous boot, using the last known good settings"
+                    );
+                    config_changed = true;
+                }
+            }
+
+            // Saved before applying the settings, so that a crash while applying them is noticed on the next boot
+            match config.update_unverified(boot_id, controllers.keys()) {
+                Ok(changed) => config_changed |= changed,
+                Err(err) => error!("could not update unverified settings: {err:#}"),
+            }
+        }
+
+        if config_changed {
             config.save(&Cell::new(Instant::now()))?;
         }
 
@@ -204,6 +252,8 @@ impl<'a> Handler {
             config_last_saved: Rc::new(Cell::new(Instant::now())),
             profile_watcher_tx: Rc::new(RefCell::new(None)),
             profile_watcher_state: Rc::new(RefCell::new(None)),
+            focused_window: Rc::new(RefCell::new(None)),
+            focus_agent_connection: Rc::new(RefCell::new(None)),
             profile_watcher_join_handle: Rc::new(RefCell::new(None)),
             profile_holds: Rc::new(RefCell::new(Vec::new())),
             profile_hold_snapshot: Rc::new(RefCell::new(None)),
@@ -211,8 +261,25 @@ impl<'a> Handler {
             polkit_proxy,
             ignored_gpu_ids: Rc::new(RwLock::new(Vec::new())),
             reload_tx: Rc::new(reload_tx),
+            stats_samplers: StatsSamplers::default(),
+            stats_history: StatsHistoryStore::default(),
+            stability_tests: StabilityTests::default(),
+            recovery: Rc::new(RefCell::new(RecoveryState::default())),
+            boot_id,
+            revisions: ConfigRevisions::load(),
         };
 
+        for id in safe_mode_ids {
+            handler.recovery.borrow_mut().set_recovery(
+                &id,
+                ConfigRecovery {
+                    timestamp: jiff::Timestamp::now().as_millisecond(),
+                    reason: "The settings applied in the previous boot did not become known good, the system may have crashed".to_owned(),
+                    restored_known_good: true,
+                },
+            );
+        }
+
         if let Err(err) = handler.apply_current_config().await {
             error!("could not apply config: {err:#}");
         }
@@ -267,6 +334,17 @@ impl<'a> Handler {
                     let _ = old_controller.reset_clocks();
                 }
 
+                for id in controllers_guard.keys() {
+                    if !new_controllers.contains_key(id) {
+                        events::emit(DaemonEvent::GpuRemoved { id: id.clone() });
+                    }
+                }
+                for id in new_controllers.keys() {
+                    if !controllers_guard.contains_key(id) {
+                        events::emit(DaemonEvent::GpuAdded { id: id.clone() });
+                    }
+                }
+
                 *controllers_guard = new_controllers;
 
                 match apply_config_to_controllers(&controllers_guard, &config).await {
@@ -308,6 +386,16 @@ impl<'a> Handler {
         &self,
         id: String,
         f: F,
+    ) -> anyhow::Result<u64> {
+        self.edit_gpu_config_with_timer(id, f, None).await
+    }
+
+    /// Same as `edit_gpu_config`, with an optional confirmation timer that overrides `apply_settings_timer`
+    pub(super) async fn edit_gpu_config_with_timer<F: FnOnce(&mut GpuConfig)>(
+        &self,
+        id: String,
+        f: F,
+        apply_timer: Option<u64>,
     ) -> anyhow::Result<u64> {
         if self
             .confirm_config_tx
@@ -322,7 +410,7 @@ impl<'a> Handler {
 
         let (previous_config, apply_timer) = {
             let config = self.config.read().await;
-            let apply_timer = config.apply_settings_timer;
+            let apply_timer = apply_timer.unwrap_or(config.apply_settings_timer);
             let gpu_config = config.gpus()?.get(&id).cloned().unwrap_or_default();
             (gpu_config, apply_timer)
         };
@@ -366,57 +454,52 @@ impl<'a> Handler {
         *self
             .confirm_config_tx
             .try_borrow_mut()
-            .map_err(|err| anyhow!("{err}"))? = Some(tx);
+            .map_err(|err| anyhow!("{err}"))? = Some((id.clone(), tx));
+
+        events::emit(DaemonEvent::ConfigPending {
+            id: id.clone(),
+            apply_timer,
+        });
 
+        // Resolved right away, as the client might not be running anymore once the change is confirmed
+        let source = current_change_source("daemon");
         let handler = self.clone();
 
+        // The controllers are only locked when reverting, as the GPU list can be reloaded while waiting
         tokio::task::spawn_local(async move {
-            let controller = handler
-                .controller_by_id(&id)
-                .await
-                .expect("GPU controller disappeared");
-
             tokio::select! {
                 () = tokio::time::sleep(Duration::from_secs(apply_timer)) => {
                     info!("no confirmation received, reverting settings");
-
-                    let mut config_guard = handler.config.write().await;
-                    match config_guard.gpus_mut() {
-                        Ok(gpus) => {
-                            gpus.insert(id, previous_config.clone());
-                        }
-                        Err(err) => {
-                            error!("could not revert config: {err}") ;
-                        }
-                    }
-
-                    if let Err(err) = controller.apply_config(&previous_config).await {
-                        error!("could not revert settings: {err:#}");
-                    }
+                    handler.revert_config(&id, &previous_config).await;
                 }
                 result = rx => {
                     match result {
                         Ok(ConfirmCommand::Confirm) => {
                             info!("saving updated config");
 
-                            if let Err(err) = handler.config.read().await.save(&handler.config_last_saved) {
+                            let config_guard = handler.config.read().await;
+                            if let Err(err) = config_guard.save(&handler.config_last_saved) {
                                 error!("{err:#}");
                             }
-                        }
-                        Ok(ConfirmCommand::Revert) | Err(_) => {
-                            let mut config_guard = handler.config.write().await;
-                            match config_guard.gpus_mut() {
-                                Ok(gpus) => {
-                                    gpus.insert(id, previous_config.clone());
-                                }
-                                Err(err) => {
-                                    error!("could not revert config: {err}") ;
-                                }
+                            if let Ok(gpus) = config_guard.gpus() {
+                                let new_config = gpus.get(&id).cloned().unwrap_or_default();
+                                handler.revisions.record(
+                                    &id,
+                                    config_guard.current_profile.as_deref(),
+                                    &previous_config,
+                                    &new_config,
+                                    source,
+                                    &handler.config_last_saved,
+                                );
                             }
+                            drop(config_guard);
 
-                            if let Err(err) = controller.apply_config(&previous_config).await {
-                                error!("could not revert settings: {err:#}");
-                            }
+                            events::emit(DaemonEvent::ConfigApplied { id });
+                            // Tracks the new settings as unverified right away
+                            handler.observe_gpu_configs().await;
+                        }
+                        Ok(ConfirmCommand::Revert) | Err(_) => {
+                            handler.revert_config(&id, &previous_config).await;
                         }
                     }
                 }
@@ -431,6 +514,30 @@ impl<'a> Handler {
         Ok(())
     }
 
+    /// Writes back and applies the settings from before a pending change
+    async fn revert_config(&self, id: &str, previous_config: &GpuConfig) {
+        match self.config.write().await.gpus_mut() {
+            Ok(gpus) => {
+                gpus.insert(id.to_owned(), previous_config.clone());
+            }
+            Err(err) => {
+                error!("could not revert config: {err}");
+            }
+        }
+
+        // The config is not locked anymore at this point,
+        // as reloading the GPU list locks the controllers before the config
+        match self.controller_by_id(id).await {
+            Ok(controller) => {
+                if let Err(err) = controller.apply_config(previous_config).await {
+                    error!("could not revert settings: {err:#}");
+                }
+            }
+            Err(err) => error!("could not revert settings: {err:#}"),
+        }
+        events::emit(DaemonEvent::ConfigReverted { id: id.to_owned() });
+    }
+
     async fn controller_by_id(
         &self,
         id: &str,
@@ -489,6 +596,26 @@ impl<'a> Handler {
         Ok(self.controller_by_id(id).await?.get_stats(gpu_config))
     }
 
+    pub async fn subscribe_stats(
+        &'a self,
+        id: &str,
+        interval: Duration,
+    ) -> anyhow::Result<StatsSubscription> {
+        // Make sure the GPU exists before starting a sampler for it
+        self.controller_by_id(id).await?;
+        Ok(self.stats_samplers.subscribe(self, id, interval))
+    }
+
+    pub async fn get_stats_history(
+        &'a self,
+        id: &str,
+        since: Option<i64>,
+        stats: &[StatType],
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        self.controller_by_id(id).await?;
+        self.stats_history.query(id, since, stats)
+    }
+
     pub async fn get_clocks_info(&'a self, id: &str) -> anyhow::Result<ClocksInfo> {
         let config = self.config.read().await;
         let gpu_config = config.gpus()?.get(id);
@@ -576,6 +703,13 @@ impl<'a> Handler {
                             })
                         }
                     }
+                    // PID settings are only available through the full GPU config
+                    FanControlMode::Pid => {
+                        let mut settings =
+                            gpu_config.fan_control_settings.clone().unwrap_or_default();
+                        settings.mode = mode;
+                        Some(settings)
+                    }
                 },
                 None => None,
             }
@@ -603,6 +737,75 @@ impl<'a> Handler {
         .context("Failed to edit GPU config and reset pmfw")
     }
 
+    /// The measured speeds are stored in the fan control settings as a pending change, which has to be confirmed
+    pub async fn calibrate_fan(&self, id: &str) -> anyhow::Result<FanCalibration> {
+        let calibrator = self.controller_by_id(id).await?.fan_calibrator().await?;
+
+        // The controller is not locked during the calibration, which takes minutes
+        info!("calibrating fan");
+        let result = calibrator.run().await;
+        calibrator.restore();
+
+        let mut calibration = match result {
+            Ok(calibration) => {
+                info!(
+                    "fan calibration finished, stall pwm: {:?}, start pwm: {:?}",
+                    calibration.stall_pwm, calibration.start_pwm
+                );
+                calibration
+            }
+            Err(err) => {
+                error!("fan calibration failed: {err:#}");
+                self.reapply_fan_control(id).await?;
+                return Err(err);
+            }
+        };
+
+        if calibration.stall_pwm.is_some() || calibration.start_pwm.is_some() {
+            // Applying the change also starts the fan control task again
+            let result = self
+                .edit_gpu_config(id.to_owned(), |gpu_config| {
+                    let settings = gpu_config
+                        .fan_control_settings
+                        .get_or_insert_with(FanControlSettings::default);
+                    // A configured minimum speed is kept if the fan didn't stall
+                    if let Some(stall_pwm) = calibration.stall_pwm {
+                        settings.min_speed = Some(f32::from(stall_pwm) / f32::from(u8::MAX));
+                    }
+                    if let Some(start_pwm) = calibration.start_pwm {
+                        settings.zero_rpm_start_speed =
+                            Some(f32::from(start_pwm) / f32::from(u8::MAX));
+                    }
+                })
+                .await;
+            match result {
+                Ok(apply_timer) => {
+                    calibration.apply_timer = Some(apply_timer);
+                    return Ok(calibration);
+                }
+                Err(err) => error!("could not store fan calibration results: {err:#}"),
+            }
+        }
+
+        self.reapply_fan_control(id).await?;
+        Ok(calibration)
+    }
+
+    /// Calibration stops the fan control task, so it needs to be started again afterwards
+    async fn reapply_fan_control(&self, id: &str) -> anyhow::Result<()> {
+        let gpu_config = self.config.read().await.gpus()?.get(id).cloned();
+        if let Some(gpu_config) = gpu_config
+            && gpu_config.fan_control_enabled
+        {
+            self.controller_by_id(id)
+                .await?
+                .apply_config(&gpu_config)
+                .await
+                .context("Could not re-apply fan control after calibration")?;
+        }
+        Ok(())
+    }
+
     pub async fn set_power_cap(&'a self, id: &str, maybe_cap: Option<f64>) -> anyhow::Result<u64> {
         self.edit_gpu_config(id.to_owned(), |gpu_config| {
             gpu_config.power_cap = maybe_cap;
@@ -889,6 +1092,47 @@ impl<'a> Handler {
         Ok(profile)
     }
 
+    pub async fn export_profile(&self, name: Option<Rc<str>>) -> anyhow::Result<ProfileExport> {
+        let export_name = name.as_deref().unwrap_or("Default").to_owned();
+        let profile = self
+            .get_profile(name)
+            .await?
+            .with_context(|| format!("Profile {export_name} not found"))?;
+        Ok(ProfileExport::new(export_name, profile))
+    }
+
+    pub async fn import_profile(
+        &self,
+        name: Option<String>,
+        export: ProfileExport,
+        ctx: ClientContext,
+    ) -> anyhow::Result<Vec<String>> {
+        if export.version > PROFILE_EXPORT_VERSION {
+            bail!(
+                "Profile version {} is not supported, the latest supported version is {PROFILE_EXPORT_VERSION}",
+                export.version
+            );
+        }
+
+        let name = name.unwrap_or_else(|| export.name.clone());
+        let has_gpus = !export.gpus.is_empty();
+
+        let (profile, unmatched) = {
+            let controllers = self.gpu_controllers.read().await;
+            export.into_profile(controllers.keys().map(String::as_str))
+        };
+        if has_gpus && profile.gpus.is_empty() {
+            bail!("None of the GPUs from the profile are present in this system");
+        }
+        for model_id in &unmatched {
+            warn!("no GPU of model {model_id} found, skipping its settings from profile {name}");
+        }
+
+        self.create_profile(name, ProfileBase::Provided(profile), ctx)
+            .await?;
+        Ok(unmatched)
+    }
+
     pub async fn set_profile(
         &self,
         name: Option<Rc<str>>,
@@ -904,9 +1148,12 @@ impl<'a> Handler {
             self.set_current_profile(name).await?;
         }
 
-        let mut config = self.config.write().await;
-        config.auto_switch_profiles = auto_switch;
-        config.save(&self.config_last_saved)?;
+        {
+            let mut config = self.config.write().await;
+            config.auto_switch_profiles = auto_switch;
+            config.save(&self.config_last_saved)?;
+        }
+        events::emit(DaemonEvent::ProfilesUpdated);
 
         Ok(())
     }
@@ -930,10 +1177,14 @@ impl<'a> Handler {
         }
 
         self.cleanup().await;
-        self.config.write().await.current_profile = name;
+        self.config.write().await.current_profile.clone_from(&name);
 
         self.apply_current_config().await?;
 
+        events::emit(DaemonEvent::ProfileChanged {
+            name: name.as_deref().map(str::to_owned),
+        });
+
         if let Some(deactivated) = &deactivation_hook {
             run_hook_command(deactivated).await?;
         }
@@ -977,10 +1228,7 @@ impl<'a> Handler {
             config.save(&self.config_last_saved)?;
         }
 
-        let tx = self.profile_watcher_tx.borrow().clone();
-        if let Some(tx) = tx {
-            let _ = tx.send(ProfileWatcherCommand::Update).await;
-        }
+        self.profiles_updated().await;
 
         Ok(())
     }
@@ -1005,10 +1253,7 @@ impl<'a> Handler {
 
         self.config.write().await.save(&self.config_last_saved)?;
 
-        let tx = self.profile_watcher_tx.borrow().clone();
-        if let Some(tx) = tx {
-            let _ = tx.send(ProfileWatcherCommand::Update).await;
-        }
+        self.profiles_updated().await;
 
         Ok(())
     }
@@ -1030,10 +1275,7 @@ impl<'a> Handler {
             config.save(&self.config_last_saved)?;
         }
 
-        let tx = self.profile_watcher_tx.borrow().clone();
-        if let Some(tx) = tx {
-            let _ = tx.send(ProfileWatcherCommand::Update).await;
-        }
+        self.profiles_updated().await;
 
         Ok(())
     }
@@ -1067,12 +1309,18 @@ impl<'a> Handler {
             config.save(&self.config_last_saved)?;
         }
 
+        self.profiles_updated().await;
+
+        Ok(())
+    }
+
+    /// Notifies the profile watcher and clients about a change to the profile list
+    async fn profiles_updated(&self) {
         let tx = self.profile_watcher_tx.borrow().clone();
         if let Some(tx) = tx {
             let _ = tx.send(ProfileWatcherCommand::Update).await;
         }
-
-        Ok(())
+        events::emit(DaemonEvent::ProfilesUpdated);
     }
 
     pub async fn process_list(&self, id: &str) -> anyhow::Result<ProcessList> {
@@ -1089,16 +1337,149 @@ impl<'a> Handler {
             .await
     }
 
+    pub fn list_config_revisions(&self, id: Option<&str>) -> Vec<ConfigRevision> {
+        self.revisions.list(id)
+    }
+
+    pub async fn diff_config_revisions(
+        &self,
+        from: u64,
+        to: Option<u64>,
+    ) -> anyhow::Result<Vec<ConfigDiffEntry>> {
+        let from = self
+            .revisions
+            .get(from)
+            .with_context(|| format!("Revision {from} not found"))?;
+
+        let to_config = match to {
+            Some(to) => {
+                let to = self
+                    .revisions
+                    .get(to)
+                    .with_context(|| format!("Revision {to} not found"))?;
+                if to.gpu_id != from.gpu_id {
+                    bail!(
+                        "Revisions {} and {} are of different GPUs",
+                        from.revision,
+                        to.revision
+                    );
+                }
+                if to.profile != from.profile {
+                    bail!(
+                        "Revisions {} and {} are of different profiles",
+                        from.revision,
+                        to.revision
+                    );
+                }
+                to.config
+            }
+            // Compared to the current settings of the same profile, even if another profile is active
+            None => self
+                .config
+                .read()
+                .await
+                .profile_gpus(from.profile.as_deref())?
+                .get(&from.gpu_id)
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        diff_configs(&from.config, &to_config)
+    }
+
+    pub async fn rollback_config(&self, revision: u64) -> anyhow::Result<u64> {
+        let revision = self
+            .revisions
+            .get(revision)
+            .with_context(|| format!("Revision {revision} not found"))?;
+
+        let current_profile = self.config.read().await.current_profile.clone();
+        if revision.profile.as_deref() != current_profile.as_deref() {
+            bail!(
+                "Revision {} belongs to the '{}' profile, switch to it before rolling back",
+                revision.revision,
+                revision.profile.as_deref().unwrap_or("default"),
+            );
+        }
+
+        info!(
+            "rolling back settings of GPU {} to revision {}",
+            revision.gpu_id, revision.revision
+        );
+        self.edit_gpu_config(revision.gpu_id, |config| *config = revision.config)
+            .await
+    }
+
     pub fn evaluate_profile_rule(&self, rule: &ProfileRule) -> anyhow::Result<bool> {
         let profile_watcher_state_guard = self.profile_watcher_state.borrow();
         match profile_watcher_state_guard.as_ref() {
-            Some(state) => Ok(profiles::profile_rule_matches(state, rule)),
+            Some(state) => Ok(profiles::profile_rule_matches(
+                state,
+                rule,
+                profiles::LocalTime::now(),
+            )),
             None => Err(anyhow!(
                 "Automatic profile switching is not currently active"
             )),
         }
     }
 
+    /// Sets the window reported by a focus agent. The window is cleared when the connection of the agent is closed,
+    /// so an agent that was killed before reporting that it exits doesn't leave a stale window behind.
+    pub async fn set_focused_window(
+        &self,
+        window: Option<FocusedWindow>,
+        disconnect_notify: std::sync::Arc<tokio::sync::Notify>,
+    ) {
+        let is_new_connection = self
+            .focus_agent_connection
+            .borrow()
+            .as_ref()
+            .is_none_or(|connection| !std::sync::Arc::ptr_eq(connection, &disconnect_notify));
+
+        if is_new_connection {
+            *self.focus_agent_connection.borrow_mut() = Some(disconnect_notify.clone());
+
+            let handler = self.clone();
+            tokio::task::spawn_local(async move {
+                disconnect_notify.notified().await;
+
+                // Another agent might have reported a window since
+                let is_current = handler
+                    .focus_agent_connection
+                    .borrow()
+                    .as_ref()
+                    .is_some_and(|connection| {
+                        std::sync::Arc::ptr_eq(connection, &disconnect_notify)
+                    });
+                if is_current {
+                    debug!("focus agent disconnected, clearing the focused window");
+                    handler.focus_agent_connection.borrow_mut().take();
+                    handler.update_focused_window(None).await;
+                }
+            });
+        }
+
+        self.update_focused_window(window).await;
+    }
+
+    async fn update_focused_window(&self, window: Option<FocusedWindow>) {
+        if *self.focused_window.borrow() == window {
+            return;
+        }
+        debug!("focused window changed: {window:?}");
+
+        if let Some(state) = self.profile_watcher_state.borrow_mut().as_mut() {
+            state.focused_window.clone_from(&window);
+        }
+        *self.focused_window.borrow_mut() = window;
+
+        let tx = self.profile_watcher_tx.borrow().clone();
+        if let Some(tx) = tx {
+            let _ = tx.send(ProfileWatcherCommand::Update).await;
+        }
+    }
+
     pub async fn detach_gpu(&self, gpu_id: &str) -> anyhow::Result<()> {
         let _ = self.controller_by_id(gpu_id).await?;
 
@@ -1118,7 +1499,14 @@ impl<'a> Handler {
     }
 
     pub fn confirm_pending_config(&self, command: ConfirmCommand) -> anyhow::Result<()> {
-        if let Some(tx) = self
+        // The pending settings belong to the current step of the test, they must not be kept
+        if matches!(command, ConfirmCommand::Confirm)
+            && let Some(id) = self.stability_tests.active_id()
+        {
+            bail!("Cannot confirm settings while a stability test is running on GPU {id}");
+        }
+
+        if let Some((_, tx)) = self
             .confirm_config_tx
             .try_borrow_mut()
             .map_err(|err| anyhow!("{err}"))?
@@ -1131,6 +1519,253 @@ impl<'a> Handler {
         }
     }
 
+    /// Reverts the pending config change of a GPU and waits until the previous settings are applied again
+    pub(super) async fn revert_pending_config(&self, id: &str) {
+        let pending_id = self
+            .confirm_config_tx
+            .try_borrow()
+            .ok()
+            .and_then(|tx| tx.as_ref().map(|(pending_id, _)| pending_id.clone()));
+        if pending_id.as_deref() != Some(id) {
+            debug!("no pending config change of GPU {id} to revert");
+            return;
+        }
+
+        let mut events = events::subscribe();
+
+        if let Err(err) = self.confirm_pending_config(ConfirmCommand::Revert) {
+            // The confirmation timer has already reverted the settings
+            debug!("could not revert pending config: {err:#}");
+            return;
+        }
+
+        loop {
+            match events.recv().await {
+                Ok(DaemonEvent::ConfigReverted { id: reverted_id }) if reverted_id == id => break,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    pub async fn start_stability_test(
+        &self,
+        id: &str,
+        options: StabilityTestOptions,
+        ctx: ClientContext,
+    ) -> anyhow::Result<()> {
+        let driver = self
+            .controller_by_id(id)
+            .await?
+            .controller_info()
+            .driver
+            .clone();
+
+        if self
+            .confirm_config_tx
+            .try_borrow()
+            .map_err(|err| anyhow!("{err}"))?
+            .is_some()
+        {
+            bail!("There is an unconfirmed configuration change pending");
+        }
+
+        // The workload runs as the requesting user, which only matters when the daemon runs as root
+        let workload_uid = if Uid::effective().is_root() {
+            self.check_auth(
+                polkit_actions::STABILITY_TEST,
+                "User was not authorized to run stability tests",
+                ctx,
+            )
+            .await?;
+            Some(ctx.uid.context("No client UID available")?)
+        } else {
+            None
+        };
+
+        self.stability_tests
+            .start(self.clone(), id.to_owned(), driver, options, workload_uid)
+    }
+
+    pub fn stability_test_status(&self, id: &str) -> Option<StabilityTestStatus> {
+        self.stability_tests.status(id)
+    }
+
+    pub fn stop_stability_test(&self, id: &str) -> anyhow::Result<()> {
+        self.stability_tests.stop(id)
+    }
+
+    /// Updates the known good and unverified settings of every GPU
+    pub async fn observe_gpu_configs(&self) {
+        let has_pending_config = self
+            .confirm_config_tx
+            .try_borrow()
+            .is_ok_and(|tx| tx.is_some());
+
+        let ids: Vec<String> = self.gpu_controllers.read().await.keys().cloned().collect();
+        let mut config = self.config.write().await;
+        let known_good_time = Duration::from_secs(config.daemon.reset_recovery.known_good_time);
+        let Ok(gpus) = config.gpus().cloned() else {
+            return;
+        };
+
+        // Pending settings are only temporary and should never become known good
+        if has_pending_config {
+            let mut recovery = self.recovery.borrow_mut();
+            for id in &ids {
+                recovery.clear_observed(id);
+            }
+            return;
+        }
+
+        let mut changed = false;
+        for id in &ids {
+            let gpu_config = gpus.get(id).cloned().unwrap_or_default();
+            let is_known_good = self.recovery.borrow_mut().observe(
+                id,
+                &gpu_config,
+                Instant::now(),
+                known_good_time,
+            );
+
+            if is_known_good && config.last_known_good(id) != Some(&gpu_config) {
+                info!("settings of GPU {id} are now known good");
+                config.push_known_good(id, gpu_config);
+                changed = true;
+            }
+        }
+
+        if let Some(boot_id) = &self.boot_id {
+            match config.update_unverified(boot_id, &ids) {
+                Ok(unverified_changed) => changed |= unverified_changed,
+                Err(err) => error!("could not update unverified settings: {err:#}"),
+            }
+        }
+
+        if changed && let Err(err) = config.save(&self.config_last_saved) {
+            error!("could not save config: {err:#}");
+        }
+    }
+
+    pub async fn handle_gpu_reset(&self, pci_slot: &str) {
+        let id = self
+            .gpu_controllers
+            .read()
+            .await
+            .iter()
+            .find(|(_, controller)| controller.controller_info().pci_slot_name == pci_slot)
+            .map(|(id, _)| id.clone());
+        let Some(id) = id else {
+            debug!("got reset of unknown GPU {pci_slot}");
+            return;
+        };
+        warn!("GPU {id} was reset");
+
+        let settings = self.config.read().await.daemon.reset_recovery.clone();
+        if !settings.enabled {
+            return;
+        }
+
+        let resets = self
+            .recovery
+            .borrow_mut()
+            .record_reset(&id, Instant::now(), &settings);
+        if let Some(resets) = resets {
+            let reason = format!("{resets} GPU resets within {} seconds", settings.window);
+            if let Err(err) = self.recover_gpu_config(&id, reason).await {
+                error!("could not roll back settings of GPU {id}: {err:#}");
+            }
+        }
+    }
+
+    /// Replaces the settings of a GPU with the last known good ones, or with the defaults
+    async fn recover_gpu_config(&self, id: &str, reason: String) -> anyhow::Result<()> {
+        // The reset was most likely caused by the pending change
+        self.revert_pending_config(id).await;
+
+        let (recovered_config, restored_known_good) = {
+            let mut config = self.config.write().await;
+            let current_config = config.gpus()?.get(id).cloned().unwrap_or_default();
+
+            let known_good = config
+                .known_good_configs(config.current_profile.as_deref(), id)
+                .rev()
+                .find(|known_good| **known_good != current_config)
+                .cloned();
+            let restored_known_good = known_good.is_some();
+            let recovered_config = known_good.unwrap_or_default();
+
+            config
+                .gpus_mut()?
+                .insert(id.to_owned(), recovered_config.clone());
+            config.save(&self.config_last_saved)?;
+
+            self.revisions.record(
+                id,
+                config.current_profile.as_deref(),
+                &current_config,
+                &recovered_config,
+                current_change_source("reset_recovery"),
+                &self.config_last_saved,
+            );
+
+            (recovered_config, restored_known_good)
+        };
+
+        warn!(
+            "rolling back settings of GPU {id} to the {}: {reason}",
+            if restored_known_good {
+                "last known good settings"
+            } else {
+                "defaults"
+            }
+        );
+
+        self.recovery.borrow_mut().set_recovery(
+            id,
+            ConfigRecovery {
+                timestamp: jiff::Timestamp::now().as_millisecond(),
+                reason: reason.clone(),
+                restored_known_good,
+            },
+        );
+        events::emit(DaemonEvent::ConfigRecovered {
+            id: id.to_owned(),
+            reason,
+            restored_known_good,
+        });
+
+        let controller = self.controller_by_id(id).await?;
+        controller.reset_clocks()?;
+        controller
+            .apply_config(&recovered_config)
+            .await
+            .context("Could not apply recovered settings")
+    }
+
+    /// Settings don't need to be verified again after a clean shutdown
+    pub async fn clear_unverified_configs(&self) {
+        let mut config = self.config.write().await;
+        if config.unverified.take().is_some()
+            && let Err(err) = config.save(&self.config_last_saved)
+        {
+            error!("could not save config: {err:#}");
+        }
+    }
+
+    pub fn config_recovery(&self, id: &str) -> Option<ConfigRecovery> {
+        self.recovery.borrow().recovery(id).cloned()
+    }
+
+    /// Names of the currently held profiles, with one entry per hold
+    pub fn held_profiles(&self) -> Vec<Rc<str>> {
+        self.profile_holds
+            .borrow()
+            .iter()
+            .map(|(_, name, _)| name.clone())
+            .collect()
+    }
+
     pub async fn hold_profile(
         &self,
         name: String,
@@ -1241,12 +1876,15 @@ impl<'a> Handler {
     pub async fn reset_config(&self) {
         self.cleanup().await;
 
-        let mut config = self.config.write().await;
-        config.clear();
+        {
+            let mut config = self.config.write().await;
+            config.clear();
 
-        if let Err(err) = config.save(&self.config_last_saved) {
-            error!("could not save config: {err:#}");
+            if let Err(err) = config.save(&self.config_last_saved) {
+                error!("could not save config: {err:#}");
+            }
         }
+        events::emit(DaemonEvent::ConfigReloaded);
     }
 
     pub async fn cleanup(&self) {
@@ -1537,6 +2175,16 @@ fn add_path_to_archive(
     Ok(())
 }
 
+/// Source of the config change made by the request that is currently being handled
+fn current_change_source(fallback_request: &str) -> ConfigChangeSource {
+    CHANGE_SOURCE
+        .try_with(RequestSource::resolve)
+        .unwrap_or_else(|_| ConfigChangeSource {
+            request: fallback_request.to_owned(),
+            ..Default::default()
+        })
+}
+
 fn drm_base_path() -> PathBuf {
     match env::var("_LACT_DRM_SYSFS_PATH") {
         Ok(custom_path) => PathBuf::from(custom_path),
@@ -0,0 +1,257 @@
+use super::{handler::Handler, stats::MIN_SAMPLE_INTERVAL};
+use crate::config;
+use anyhow::{Context, bail};
+use lact_schema::history::{StatType, StatsHistorySample};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+/// How often the history is written to disk, when persistence is enabled
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// How often new GPUs are checked for
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+type HistoryMap = HashMap<String, VecDeque<StatsHistorySample>>;
+
+/// Recent stats samples of every GPU, kept by the daemon so that clients can show them right after connecting
+#[derive(Clone, Default)]
+pub struct StatsHistoryStore {
+    /// `None` when the history is disabled
+    inner: Rc<RefCell<Option<HistoryState>>>,
+}
+
+struct HistoryState {
+    config: config::StatsHistory,
+    samples: HistoryMap,
+    /// GPUs with a running recording task
+    recorded_gpus: HashSet<String>,
+}
+
+impl StatsHistoryStore {
+    pub fn setup(&self, handler: Handler, config: config::StatsHistory) {
+        let mut samples = HistoryMap::new();
+
+        if let Some(path) = &config.path {
+            match load_history(path) {
+                Ok(Some(loaded)) => samples = loaded,
+                Ok(None) => (),
+                Err(err) => warn!("could not load stats history: {err:#}"),
+            }
+        }
+
+        info!(
+            "recording stats history every {}ms for {} seconds",
+            config.interval_ms, config.duration
+        );
+
+        let interval = Duration::from_millis(config.interval_ms).max(MIN_SAMPLE_INTERVAL);
+        let should_save = config.path.is_some();
+        *self.inner.borrow_mut() = Some(HistoryState {
+            config,
+            samples,
+            recorded_gpus: HashSet::new(),
+        });
+
+        let store = self.clone();
+        tokio::task::spawn_local(async move {
+            let mut last_saved = Instant::now();
+
+            loop {
+                // GPUs can be added later, and samplers stop when a GPU can't be read anymore
+                for device in handler.list_devices().await {
+                    if store.start_recording(&device.id) {
+                        tokio::task::spawn_local(store.clone().record_gpu(
+                            handler.clone(),
+                            device.id,
+                            interval,
+                        ));
+                    }
+                }
+
+                if should_save && last_saved.elapsed() >= SAVE_INTERVAL {
+                    store.save();
+                    last_saved = Instant::now();
+                }
+
+                sleep(DEVICE_CHECK_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Returns `false` if the GPU is already being recorded
+    fn start_recording(&self, id: &str) -> bool {
+        self.inner
+            .borrow_mut()
+            .as_mut()
+            .is_some_and(|state| state.recorded_gpus.insert(id.to_owned()))
+    }
+
+    /// Records the samples of the shared stats sampler of the GPU, so that it is not queried separately for the history
+    async fn record_gpu(self, handler: Handler, id: String, interval: Duration) {
+        match handler.subscribe_stats(&id, interval).await {
+            Ok(mut subscription) => {
+                let mut last_sample: Option<Instant> = None;
+
+                while subscription.rx.changed().await.is_ok() {
+                    let Some(stats) = subscription.rx.borrow_and_update().clone() else {
+                        continue;
+                    };
+
+                    // The sampler runs at the shortest interval of all subscribers
+                    if last_sample.is_some_and(|last_sample| last_sample.elapsed() < interval) {
+                        continue;
+                    }
+                    last_sample = Some(Instant::now());
+
+                    let timestamp = jiff::Timestamp::now().as_millisecond();
+                    self.push(
+                        id.clone(),
+                        StatsHistorySample::from_stats(timestamp, &stats),
+                    );
+                }
+            }
+            Err(err) => debug!("could not subscribe to stats for history: {err:#}"),
+        }
+
+        if let Some(state) = self.inner.borrow_mut().as_mut() {
+            state.recorded_gpus.remove(&id);
+        }
+    }
+
+    fn push(&self, id: String, sample: StatsHistorySample) {
+        let mut guard = self.inner.borrow_mut();
+        let Some(state) = guard.as_mut() else {
+            return;
+        };
+
+        let cutoff = sample.timestamp - max_age_ms(&state.config);
+        state.samples.entry(id).or_default().push_back(sample);
+        trim_history(&mut state.samples, cutoff);
+    }
+
+    /// Returns the samples of a GPU taken after `since`, limited to the given stats (all if empty)
+    pub fn query(
+        &self,
+        id: &str,
+        since: Option<i64>,
+        stats: &[StatType],
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        let guard = self.inner.borrow();
+        let Some(state) = guard.as_ref() else {
+            bail!("Stats history is not enabled in the daemon configuration");
+        };
+
+        let Some(samples) = state.samples.get(id) else {
+            return Ok(vec![]);
+        };
+
+        let since = since.unwrap_or(i64::MIN);
+        let start = samples.partition_point(|sample| sample.timestamp < since);
+
+        Ok(samples
+            .range(start..)
+            .map(|sample| {
+                let mut sample = sample.clone();
+                if !stats.is_empty() {
+                    sample
+                        .values
+                        .retain(|(stat_type, _)| stats.contains(stat_type));
+                }
+                sample
+            })
+            .collect())
+    }
+
+    /// Writes the history to disk if persistence is enabled
+    pub fn save(&self) {
+        let guard = self.inner.borrow();
+        if let Some(state) = guard.as_ref()
+            && let Some(path) = &state.config.path
+        {
+            match save_history(path, &state.samples) {
+                Ok(()) => debug!("saved stats history to {}", path.display()),
+                Err(err) => error!("could not save stats history: {err:#}"),
+            }
+        }
+    }
+}
+
+fn max_age_ms(config: &config::StatsHistory) -> i64 {
+    i64::try_from(config.duration)
+        .unwrap_or(i64::MAX)
+        .saturating_mul(1000)
+}
+
+fn trim_history(samples: &mut HistoryMap, cutoff: i64) {
+    for gpu_samples in samples.values_mut() {
+        while gpu_samples
+            .front()
+            .is_some_and(|sample| sample.timestamp < cutoff)
+        {
+            gpu_samples.pop_front();
+        }
+    }
+    samples.retain(|_, gpu_samples| !gpu_samples.is_empty());
+}
+
+fn load_history(path: &Path) -> anyhow::Result<Option<HistoryMap>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read(path).context("Could not read file")?;
+    let samples = serde_json::from_slice(&contents).context("Could not parse file")?;
+    debug!("loaded stats history from {}", path.display());
+    Ok(Some(samples))
+}
+
+fn save_history(path: &Path, samples: &HistoryMap) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Could not create history directory")?;
+    }
+
+    // Write to a temporary file first, so that an interrupted write doesn't corrupt the history
+    let temp_path = path.with_extension("tmp");
+    let contents = serde_json::to_vec(samples)?;
+    fs::write(&temp_path, contents).context("Could not write file")?;
+    fs::rename(&temp_path, path).context("Could not replace history file")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryMap, trim_history};
+    use lact_schema::history::{StatType, StatsHistorySample};
+
+    fn sample(timestamp: i64) -> StatsHistorySample {
+        StatsHistorySample {
+            timestamp,
+            values: vec![(StatType::GpuUsage, 50.0)],
+            throttling: vec![],
+        }
+    }
+
+    #[test]
+    fn trim_old_samples() {
+        let mut samples = HistoryMap::from([
+            ("gpu1".to_owned(), (0..10).map(sample).collect()),
+            ("gpu2".to_owned(), [sample(1), sample(2)].into()),
+        ]);
+
+        trim_history(&mut samples, 5);
+
+        let remaining: Vec<i64> = samples["gpu1"]
+            .iter()
+            .map(|sample| sample.timestamp)
+            .collect();
+        assert_eq!(vec![5, 6, 7, 8, 9], remaining);
+        assert!(!samples.contains_key("gpu2"));
+    }
+}
@@ -1,102 +1,405 @@
+pub mod prometheus;
+mod protobuf;
 mod schema;
 
 use crate::{
-    config,
+    config::{self, MetricsCompression, MetricsEncoding},
     server::{handler::Handler, metrics::schema::NumberValue},
 };
+use anyhow::Context;
 use indexmap::IndexMap;
 use jiff::Zoned;
-use lact_schema::{DeviceStats, clean_gpu_name};
+use lact_schema::{DeviceStats, FanControlMode, clean_gpu_name};
+use libflate::gzip;
 use schema::{
     Attribute, Gauge, GaugeDataPoint, Metric, MetricsPayload, Resource, ResourceMetric, Scope,
     ScopeMetric, Value,
 };
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    io::Write,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 use tokio::time::sleep;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+use ureq::http::StatusCode;
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+/// Buffered samples are uploaded in multiple requests after an outage, to keep the request size bounded
+const MAX_UPLOAD_BATCH_SIZE: usize = 120;
 
 pub fn setup(handler: Handler, config: config::Metrics) {
     info!(
-        "exporting metrics to {} every {} seconds",
-        config.collector_address, config.interval
+        "exporting metrics to {} every {} seconds, sampled every {} seconds",
+        config.collector_address,
+        config.interval,
+        config.sample_interval()
     );
 
-    let interval = Duration::from_secs(config.interval);
+    let sample_interval = Duration::from_secs(config.sample_interval().max(1));
 
     tokio::task::spawn_local(async move {
+        let mut exporter = Exporter::new(config);
+        let mut throttle_reasons = ThrottleReasons::default();
+
+        loop {
+            sleep(sample_interval).await;
+
+            match get_sample(&handler).await {
+                Ok(mut sample) => {
+                    throttle_reasons.update(&mut sample);
+                    exporter.push_sample(BufferedSample {
+                        timestamp: Zoned::now().timestamp().as_nanosecond().to_string(),
+                        sample,
+                    });
+                }
+                Err(err) => error!("could not fetch metrics: {err:#}"),
+            }
+
+            if Instant::now() >= exporter.next_upload {
+                exporter.upload().await;
+            }
+        }
+    });
+}
+
+struct BufferedSample {
+    timestamp: String,
+    sample: MetricsSample,
+}
+
+/// Buffers samples until they can be uploaded, so that collector outages don't create gaps in the data
+struct Exporter {
+    config: config::Metrics,
+    client: ureq::Agent,
+    buffer: VecDeque<BufferedSample>,
+    dropped_samples: usize,
+    failed_attempts: u32,
+    next_upload: Instant,
+}
+
+impl Exporter {
+    fn new(config: config::Metrics) -> Self {
         let client = ureq::config::Config::builder()
             .http_status_as_error(false)
+            .timeout_global(Some(UPLOAD_TIMEOUT))
             .build()
             .new_agent();
 
-        loop {
-            sleep(interval).await;
+        Self {
+            next_upload: Instant::now() + Duration::from_secs(config.interval),
+            config,
+            client,
+            buffer: VecDeque::new(),
+            dropped_samples: 0,
+            failed_attempts: 0,
+        }
+    }
 
-            match get_stats(&handler).await {
-                Ok(devices) => {
-                    debug!("collecting metrics for {} devices", devices.len());
-                    let mut metrics = Vec::with_capacity(10);
-                    let timestamp = Zoned::now().timestamp().as_nanosecond().to_string();
+    fn push_sample(&mut self, sample: BufferedSample) {
+        if self.buffer.len() >= self.config.buffer_size.max(1) {
+            if self.dropped_samples == 0 {
+                warn!(
+                    "metrics buffer is full ({} samples), discarding the oldest samples",
+                    self.buffer.len()
+                );
+            }
+            self.buffer.pop_front();
+            self.dropped_samples += 1;
+        }
+        self.buffer.push_back(sample);
+    }
 
-                    for (gpu_id, (gpu_name, stats)) in &devices {
-                        collect_metrics(gpu_id, gpu_name, stats, &mut metrics, &timestamp);
-                    }
+    async fn upload(&mut self) {
+        let buffered_samples = self.buffer.len();
+        let mut batch_size = MAX_UPLOAD_BATCH_SIZE;
+
+        while !self.buffer.is_empty() {
+            let batch_len = batch_size.min(self.buffer.len());
+
+            let body = match self.encode_body(batch_len) {
+                Ok(body) => body,
+                Err(err) => {
+                    error!("could not encode metrics, discarding {batch_len} samples: {err:#}");
+                    self.buffer.drain(..batch_len);
+                    continue;
+                }
+            };
+
+            match self.send(body).await {
+                Ok((status, _)) if status.is_success() => {
+                    debug!("{batch_len} metric samples were uploaded");
+                    self.buffer.drain(..batch_len);
+                }
+                // The collector limits the request size, so the batch is split up instead
+                Ok((status, _)) if status == StatusCode::PAYLOAD_TOO_LARGE && batch_len > 1 => {
+                    batch_size = batch_len / 2;
+                    debug!(
+                        "metrics collector rejected {batch_len} samples as too large, retrying with batches of {batch_size}"
+                    );
+                }
+                // Retrying a rejected payload would never succeed
+                Ok((status, body))
+                    if status.is_client_error()
+                        && status != StatusCode::REQUEST_TIMEOUT
+                        && status != StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    error!(
+                        "metrics collector rejected {batch_len} samples with status code {status}, discarding them: {body}"
+                    );
+                    self.buffer.drain(..batch_len);
+                }
+                Ok((status, body)) => {
+                    self.upload_failed(&format!("status code {status}: {body}"));
+                    return;
+                }
+                Err(err) => {
+                    self.upload_failed(&err.to_string());
+                    return;
+                }
+            }
+        }
+
+        if self.failed_attempts > 0 || self.dropped_samples > 0 {
+            info!(
+                "metrics collector is reachable again, sent {buffered_samples} buffered samples ({} samples were discarded)",
+                self.dropped_samples
+            );
+        }
+        self.dropped_samples = 0;
+        self.failed_attempts = 0;
+        self.schedule_upload(Duration::from_secs(self.config.interval));
+    }
 
-                    let metric_count = metrics.len();
-
-                    let request = MetricsPayload {
-                        resource_metrics: vec![ResourceMetric {
-                            resource: Resource {
-                                attributes: vec![Attribute {
-                                    key: "service.name",
-                                    value: Value::String("LACT"),
-                                }],
-                            },
-                            scope_metrics: vec![ScopeMetric {
-                                scope: Scope {
-                                    name: "LACT".to_owned(),
-                                    version: env!("CARGO_PKG_VERSION").to_owned(),
-                                    attributes: vec![],
-                                },
-                                metrics,
-                            }],
-                        }],
-                    };
-
-                    let url = config.collector_address.clone();
-                    let client = client.clone();
-                    let payload =
-                        serde_json::to_string(&request).expect("Could not serialize request body");
-
-                    let response = tokio::task::spawn_blocking(move || {
-                        client
-                            .post(url)
-                            .content_type("application/json")
-                            .send(payload)
-                    })
-                    .await
-                    .unwrap();
-
-                    match response {
-                        Ok(response) => {
-                            if response.status().is_success() {
-                                debug!("{metric_count} metrics were uploaded");
-                            } else {
-                                let status = response.status();
-                                let body = response
-                                    .into_body()
-                                    .read_to_string()
-                                    .unwrap_or_else(|_| "<Invalid string>".to_owned());
-                                debug!("could not submit metrics, status code {status}: {body}");
-                            }
-                        }
-                        Err(err) => error!("could not submit metrics: {err}"),
+    async fn send(&self, body: Vec<u8>) -> Result<(StatusCode, String), ureq::Error> {
+        let client = self.client.clone();
+        let url = self.config.collector_address.clone();
+        let headers = self.config.headers.clone();
+        let compression = self.config.compression;
+        let content_type = match self.config.encoding {
+            MetricsEncoding::Json => "application/json",
+            MetricsEncoding::Protobuf => "application/x-protobuf",
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let mut request = client.post(url).content_type(content_type);
+            if compression == MetricsCompression::Gzip {
+                request = request.header("Content-Encoding", "gzip");
+            }
+            for (name, value) in &headers {
+                request = request.header(name, value);
+            }
+
+            let response = request.send(body)?;
+            let status = response.status();
+            let body = if status.is_success() {
+                String::new()
+            } else {
+                response
+                    .into_body()
+                    .read_to_string()
+                    .unwrap_or_else(|_| "<Invalid string>".to_owned())
+            };
+            Ok((status, body))
+        })
+        .await
+        .unwrap()
+    }
+
+    fn upload_failed(&mut self, err: &str) {
+        self.failed_attempts += 1;
+        let delay = retry_delay(
+            Duration::from_secs(self.config.interval),
+            self.failed_attempts,
+            Duration::from_secs(self.config.max_retry_delay),
+        );
+
+        if self.failed_attempts == 1 {
+            warn!(
+                "could not upload metrics to {}: {err}, buffering samples and retrying in {}s",
+                self.config.collector_address,
+                delay.as_secs()
+            );
+        } else {
+            debug!(
+                "metrics upload attempt {} failed: {err}, {} samples buffered, retrying in {}s",
+                self.failed_attempts,
+                self.buffer.len(),
+                delay.as_secs()
+            );
+        }
+
+        self.schedule_upload(delay);
+    }
+
+    fn schedule_upload(&mut self, delay: Duration) {
+        self.next_upload = Instant::now() + delay;
+    }
+
+    /// Encodes the oldest `sample_count` buffered samples
+    fn encode_body(&self, sample_count: usize) -> anyhow::Result<Vec<u8>> {
+        // Samples are batched by merging the data points of the same metric
+        let mut metrics: IndexMap<&'static str, Metric> = IndexMap::new();
+        let mut sample_metrics = Vec::with_capacity(10);
+
+        for buffered in self.buffer.iter().take(sample_count) {
+            collect_metrics(&buffered.sample, &mut sample_metrics, &buffered.timestamp);
+
+            for metric in sample_metrics.drain(..) {
+                match metrics.get_mut(metric.name) {
+                    Some(existing) => existing.gauge.data_points.extend(metric.gauge.data_points),
+                    None => {
+                        metrics.insert(metric.name, metric);
                     }
                 }
-                Err(err) => error!("could not fetch metrics: {err:#}"),
             }
         }
-    });
+
+        let payload = MetricsPayload {
+            resource_metrics: vec![ResourceMetric {
+                resource: Resource {
+                    attributes: vec![Attribute {
+                        key: "service.name",
+                        value: Value::String("LACT"),
+                    }],
+                },
+                scope_metrics: vec![ScopeMetric {
+                    scope: Scope {
+                        name: "LACT".to_owned(),
+                        version: env!("CARGO_PKG_VERSION").to_owned(),
+                        attributes: vec![],
+                    },
+                    metrics: metrics.into_values().collect(),
+                }],
+            }],
+        };
+
+        let body = match self.config.encoding {
+            MetricsEncoding::Json => {
+                serde_json::to_vec(&payload).context("Could not serialize request body")?
+            }
+            MetricsEncoding::Protobuf => protobuf::encode_payload(&payload),
+        };
+
+        match self.config.compression {
+            MetricsCompression::None => Ok(body),
+            MetricsCompression::Gzip => {
+                let mut encoder =
+                    gzip::Encoder::new(Vec::new()).context("Could not create GZIP encoder")?;
+                encoder.write_all(&body)?;
+                encoder
+                    .finish()
+                    .into_result()
+                    .context("Could not compress request body")
+            }
+        }
+    }
+}
+
+/// Exponential backoff starting at the upload interval
+fn retry_delay(interval: Duration, failed_attempts: u32, max_delay: Duration) -> Duration {
+    let factor = 2_u32.saturating_pow(failed_attempts.saturating_sub(1));
+    interval.saturating_mul(factor).min(max_delay)
+}
+
+/// Owned snapshot of everything that gets exported, so that it can be buffered
+struct MetricsSample {
+    profiles: ProfilesSample,
+    devices: IndexMap<String, DeviceSample>,
+}
+
+struct ProfilesSample {
+    current_profile: Option<String>,
+    auto_switch: bool,
+    /// Amount of holds per profile
+    held_profiles: IndexMap<String, usize>,
+}
+
+struct DeviceSample {
+    name: String,
+    stats: DeviceStats,
+    /// Includes previously seen reasons which are no longer active
+    throttle_reasons: BTreeMap<String, bool>,
+    /// Memory used by all processes with the given name.
+    /// Processes are aggregated by name, as a label per PID would create a new series for every process
+    process_memory: BTreeMap<String, u64>,
+}
+
+/// Throttle reasons are only reported while they are active,
+/// so the ones that were seen before are remembered to report them as inactive afterwards
+#[derive(Default)]
+struct ThrottleReasons(HashMap<String, BTreeSet<String>>);
+
+impl ThrottleReasons {
+    fn update(&mut self, sample: &mut MetricsSample) {
+        for (gpu_id, device) in &mut sample.devices {
+            let Some(active_reasons) = &device.stats.throttle_info else {
+                continue;
+            };
+
+            let known_reasons = self.0.entry(gpu_id.clone()).or_default();
+            known_reasons.extend(active_reasons.keys().cloned());
+
+            device.throttle_reasons = known_reasons
+                .iter()
+                .map(|reason| (reason.clone(), active_reasons.contains_key(reason)))
+                .collect();
+        }
+    }
+}
+
+fn collect_metrics<'a>(
+    sample: &'a MetricsSample,
+    metrics: &mut Vec<Metric<'a>>,
+    timestamp: &'a str,
+) {
+    collect_profile_metrics(&sample.profiles, metrics, timestamp);
+
+    for (gpu_id, device) in &sample.devices {
+        collect_device_metrics(gpu_id, device, metrics, timestamp);
+    }
+}
+
+fn collect_profile_metrics<'a>(
+    profiles: &'a ProfilesSample,
+    metrics: &mut Vec<Metric<'a>>,
+    timestamp: &'a str,
+) {
+    metrics.push(make_metric(
+        "lact_profile_info",
+        1_i64.into(),
+        "",
+        "Currently active profile (empty for the default profile)",
+        timestamp,
+        vec![Attribute {
+            key: "profile",
+            value: Value::String(profiles.current_profile.as_deref().unwrap_or_default()),
+        }],
+    ));
+
+    metrics.push(make_metric(
+        "lact_profile_auto_switch",
+        i64::from(profiles.auto_switch).into(),
+        "",
+        "Whether automatic profile switching is enabled",
+        timestamp,
+        vec![],
+    ));
+
+    for (name, holds) in &profiles.held_profiles {
+        metrics.push(make_metric(
+            "lact_profile_held",
+            i64::try_from(*holds).unwrap_or(i64::MAX).into(),
+            "",
+            "Amount of active holds on a profile",
+            timestamp,
+            vec![Attribute {
+                key: "profile",
+                value: Value::String(name),
+            }],
+        ));
+    }
 }
 
 #[allow(
@@ -104,13 +407,15 @@ pub fn setup(handler: Handler, config: config::Metrics) {
     clippy::cast_precision_loss,
     clippy::too_many_lines
 )]
-fn collect_metrics<'a>(
+fn collect_device_metrics<'a>(
     gpu_id: &'a str,
-    gpu_name: &'a str,
-    stats: &'a DeviceStats,
+    device: &'a DeviceSample,
     metrics: &mut Vec<Metric<'a>>,
     timestamp: &'a str,
 ) {
+    let gpu_name = device.name.as_str();
+    let stats = &device.stats;
+
     let base_attrs = vec![
         Attribute {
             key: "gpu_id",
@@ -303,6 +608,85 @@ fn collect_metrics<'a>(
             base_attrs.clone(),
         ));
     }
+
+    let fan_mode = if stats.fan.control_enabled {
+        match stats.fan.control_mode.unwrap_or_default() {
+            FanControlMode::Static => "static",
+            FanControlMode::Curve => "curve",
+            FanControlMode::Pid => "pid",
+        }
+    } else {
+        "auto"
+    };
+    let mut fan_mode_attrs = base_attrs.clone();
+    fan_mode_attrs.push(Attribute {
+        key: "mode",
+        value: Value::String(fan_mode),
+    });
+    metrics.push(make_metric(
+        "lact_gpu_fan_control_mode",
+        1_i64.into(),
+        "",
+        "Current fan control mode",
+        timestamp,
+        fan_mode_attrs,
+    ));
+
+    if let Some(target_pwm) = stats.fan.pwm_target {
+        metrics.push(make_metric(
+            "lact_gpu_fan_pwm_target",
+            i64::from(target_pwm).into(),
+            "",
+            "Fan PWM (0-255) last applied by the daemon's fan control",
+            timestamp,
+            base_attrs.clone(),
+        ));
+    }
+
+    if let Some(throttle_info) = &stats.throttle_info {
+        metrics.push(make_metric(
+            "lact_gpu_throttled",
+            i64::from(!throttle_info.is_empty()).into(),
+            "",
+            "Whether the GPU is currently being throttled",
+            timestamp,
+            base_attrs.clone(),
+        ));
+    }
+
+    for (reason, active) in &device.throttle_reasons {
+        let mut attrs = base_attrs.clone();
+        attrs.push(Attribute {
+            key: "reason",
+            value: Value::String(reason),
+        });
+
+        metrics.push(make_metric(
+            "lact_gpu_throttle_reason",
+            i64::from(*active).into(),
+            "",
+            "Whether the GPU is being throttled for the given reason",
+            timestamp,
+            attrs,
+        ));
+    }
+
+    for (name, memory_used) in &device.process_memory {
+        let mut attrs = base_attrs.clone();
+        attrs.push(Attribute {
+            key: "process_name",
+            value: Value::String(name),
+        });
+
+        metrics.push(make_metric(
+            "lact_gpu_process_memory_used",
+            (*memory_used as i64).into(),
+            "By",
+            "GPU memory used by processes with the given name",
+            timestamp,
+            attrs,
+        ));
+    }
 }
 
 fn make_metric<'a>(
@@ -327,7 +711,7 @@ fn make_metric<'a>(
     }
 }
 
-async fn get_stats(handler: &Handler) -> anyhow::Result<IndexMap<String, (String, DeviceStats)>> {
+async fn get_sample(handler: &Handler) -> anyhow::Result<MetricsSample> {
     let mut devices = IndexMap::new();
 
     let device_list = handler.list_devices().await;
@@ -335,16 +719,106 @@ async fn get_stats(handler: &Handler) -> anyhow::Result<IndexMap<String, (String
     for device in device_list {
         let stats = handler.get_gpu_stats(&device.id).await?;
 
+        // Not all GPUs support listing processes
+        let mut process_memory = BTreeMap::new();
+        if let Ok(list) = handler.process_list(&device.id).await {
+            for process in list.processes.into_values() {
+                *process_memory.entry(process.name).or_default() += process.memory_used;
+            }
+        }
+
         devices.insert(
             device.id,
-            (
-                device
+            DeviceSample {
+                name: device
                     .name
                     .map_or_else(String::new, |name| clean_gpu_name(&name).to_owned()),
                 stats,
+                throttle_reasons: BTreeMap::new(),
+                process_memory,
+            },
+        );
+    }
+
+    let mut held_profiles = IndexMap::<String, usize>::new();
+    for name in handler.held_profiles() {
+        *held_profiles.entry(name.to_string()).or_default() += 1;
+    }
+
+    let profiles = {
+        let config = handler.config.read().await;
+        ProfilesSample {
+            current_profile: config.current_profile.as_ref().map(Rc::to_string),
+            auto_switch: config.auto_switch_profiles,
+            held_profiles,
+        }
+    };
+
+    Ok(MetricsSample { profiles, devices })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceSample, MetricsSample, ProfilesSample, ThrottleReasons, retry_delay};
+    use indexmap::IndexMap;
+    use lact_schema::DeviceStats;
+    use std::{collections::BTreeMap, time::Duration};
+
+    fn throttle_sample(active_reasons: &[&str]) -> MetricsSample {
+        let stats = DeviceStats {
+            throttle_info: Some(
+                active_reasons
+                    .iter()
+                    .map(|reason| ((*reason).to_owned(), vec![]))
+                    .collect(),
             ),
+            ..Default::default()
+        };
+
+        MetricsSample {
+            profiles: ProfilesSample {
+                current_profile: None,
+                auto_switch: false,
+                held_profiles: IndexMap::new(),
+            },
+            devices: IndexMap::from([(
+                "gpu".to_owned(),
+                DeviceSample {
+                    name: String::new(),
+                    stats,
+                    throttle_reasons: BTreeMap::new(),
+                    process_memory: BTreeMap::new(),
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn throttle_reasons_remembered() {
+        let mut throttle_reasons = ThrottleReasons::default();
+
+        let mut sample = throttle_sample(&["PPT", "Thermal"]);
+        throttle_reasons.update(&mut sample);
+
+        let mut sample = throttle_sample(&["Thermal"]);
+        throttle_reasons.update(&mut sample);
+
+        assert_eq!(
+            BTreeMap::from([("PPT".to_owned(), false), ("Thermal".to_owned(), true)]),
+            sample.devices["gpu"].throttle_reasons
         );
     }
 
-    Ok(devices)
+    #[test]
+    fn retry_delay_backoff() {
+        let interval = Duration::from_secs(30);
+        let max_delay = Duration::from_secs(300);
+
+        let delays: Vec<u64> = (1..=6)
+            .map(|attempt| retry_delay(interval, attempt, max_delay).as_secs())
+            .collect();
+        assert_eq!(vec![30, 60, 120, 240, 300, 300], delays);
+
+        assert_eq!(max_delay, retry_delay(interval, u32::MAX, max_delay));
+    }
 }
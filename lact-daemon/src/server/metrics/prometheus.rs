@@ -0,0 +1,253 @@
+use super::{
+    ThrottleReasons, collect_metrics, get_sample,
+    schema::{Metric, NumberValue, Value},
+};
+use crate::{config, server::handler::Handler};
+use anyhow::Context;
+use indexmap::IndexMap;
+use std::{cell::RefCell, fmt::Write, rc::Rc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+use tracing::{debug, error, info};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Scrapes are only served from the cache, which is refreshed in the background,
+/// so they never wait on the hardware and concurrent scrapes don't query it multiple times
+#[derive(Default)]
+struct SampleCache {
+    /// Rendered metrics, or the error from the last refresh
+    body: RefCell<Option<Result<Rc<str>, Rc<str>>>>,
+    throttle_reasons: RefCell<ThrottleReasons>,
+}
+
+pub async fn setup(handler: Handler, config: config::Prometheus) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&config.listen_address)
+        .await
+        .with_context(|| {
+            format!(
+                "Could not bind Prometheus endpoint to {}",
+                config.listen_address
+            )
+        })?;
+    info!(
+        "serving Prometheus metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+
+    let refresh_interval = Duration::from_secs(config.cache_duration).max(MIN_REFRESH_INTERVAL);
+    let cache = Rc::new(SampleCache::default());
+
+    tokio::task::spawn_local({
+        let cache = cache.clone();
+        async move {
+            loop {
+                cache.refresh(&handler).await;
+                sleep(refresh_interval).await;
+            }
+        }
+    });
+
+    tokio::task::spawn_local(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let cache = cache.clone();
+
+                    tokio::task::spawn_local(async move {
+                        let result =
+                            timeout(REQUEST_TIMEOUT, handle_connection(stream, &cache)).await;
+
+                        match result {
+                            Ok(Ok(())) => (),
+                            Ok(Err(err)) => debug!("could not serve metrics request: {err:#}"),
+                            Err(_) => debug!("metrics request timed out"),
+                        }
+                    });
+                }
+                Err(err) => error!("failed to handle metrics connection: {err}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(stream: TcpStream, cache: &SampleCache) -> anyhow::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+
+    // Headers are not used, but they need to be read before responding
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? != 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_ascii_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path == "/metrics" || path.starts_with("/metrics?") => {
+            let body = cache.body.borrow().clone();
+            match body {
+                Some(Ok(body)) => ("200 OK", body),
+                Some(Err(err)) => ("500 Internal Server Error", err),
+                None => (
+                    "503 Service Unavailable",
+                    Rc::from("Metrics have not been collected yet\n"),
+                ),
+            }
+        }
+        (Some("GET"), _) => ("404 Not Found", Rc::from("Not found\n")),
+        _ => ("405 Method Not Allowed", Rc::from("Method not allowed\n")),
+    };
+
+    let header = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
+
+impl SampleCache {
+    async fn refresh(&self, handler: &Handler) {
+        let body = match get_sample(handler).await {
+            Ok(mut sample) => {
+                debug!("collecting metrics for {} devices", sample.devices.len());
+                self.throttle_reasons.borrow_mut().update(&mut sample);
+
+                let mut metrics = Vec::with_capacity(10);
+                // The exposition format doesn't need timestamps, the scrape time is used instead
+                collect_metrics(&sample, &mut metrics, "");
+                Ok(render_metrics(&metrics).into())
+            }
+            Err(err) => {
+                error!("could not collect metrics: {err:#}");
+                Err(format!("{err:#}\n").into())
+            }
+        };
+        *self.body.borrow_mut() = Some(body);
+    }
+}
+
+/// Renders metrics in the Prometheus text exposition format
+fn render_metrics(metrics: &[Metric]) -> String {
+    // All samples of a metric have to be grouped together
+    let mut families: IndexMap<&str, Vec<&Metric>> = IndexMap::new();
+    for metric in metrics {
+        families.entry(metric.name).or_default().push(metric);
+    }
+
+    let mut output = String::new();
+
+    for (name, family) in families {
+        let _ = writeln!(output, "# HELP {name} {}", family[0].description);
+        let _ = writeln!(output, "# TYPE {name} gauge");
+
+        for data_point in family.iter().flat_map(|metric| &metric.gauge.data_points) {
+            output.push_str(name);
+
+            if !data_point.attributes.is_empty() {
+                output.push('{');
+                for (i, attribute) in data_point.attributes.iter().enumerate() {
+                    if i > 0 {
+                        output.push(',');
+                    }
+                    let Value::String(value) = &attribute.value;
+                    let _ = write!(output, "{}=\"{}\"", attribute.key, escape_label(value));
+                }
+                output.push('}');
+            }
+
+            let _ = match data_point.value {
+                NumberValue::Int(value) => writeln!(output, " {value}"),
+                NumberValue::Float(value) if value.is_infinite() => {
+                    writeln!(output, " {}", if value > 0.0 { "+Inf" } else { "-Inf" })
+                }
+                NumberValue::Float(value) => writeln!(output, " {value}"),
+            };
+        }
+    }
+
+    output
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_metrics;
+    use crate::server::metrics::{
+        make_metric,
+        schema::{Attribute, Value},
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn render_grouped_metrics() {
+        let attrs = |sensor| {
+            vec![
+                Attribute {
+                    key: "gpu_name",
+                    value: Value::String("RX \"6800\""),
+                },
+                Attribute {
+                    key: "sensor",
+                    value: Value::String(sensor),
+                },
+            ]
+        };
+
+        let metrics = vec![
+            make_metric(
+                "lact_gpu_temperature",
+                45.0_f64.into(),
+                "Cel",
+                "Current temperature",
+                "",
+                attrs("edge"),
+            ),
+            make_metric(
+                "lact_gpu_usage",
+                12_i64.into(),
+                "%",
+                "Current GPU usage",
+                "",
+                vec![],
+            ),
+            make_metric(
+                "lact_gpu_temperature",
+                51.5_f64.into(),
+                "Cel",
+                "Current temperature",
+                "",
+                attrs("junction"),
+            ),
+        ];
+
+        let expected = "\
+# HELP lact_gpu_temperature Current temperature
+# TYPE lact_gpu_temperature gauge
+lact_gpu_temperature{gpu_name=\"RX \\\"6800\\\"\",sensor=\"edge\"} 45
+lact_gpu_temperature{gpu_name=\"RX \\\"6800\\\"\",sensor=\"junction\"} 51.5
+# HELP lact_gpu_usage Current GPU usage
+# TYPE lact_gpu_usage gauge
+lact_gpu_usage 12
+";
+        assert_eq!(expected, render_metrics(&metrics));
+    }
+}
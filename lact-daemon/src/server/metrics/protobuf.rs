@@ -0,0 +1,176 @@
+//! Minimal protobuf encoding of the OTLP metrics payload.
+//! Field numbers are from the `opentelemetry/proto/metrics/v1/metrics.proto` definitions.
+use super::schema::{
+    Attribute, Gauge, GaugeDataPoint, Metric, MetricsPayload, NumberValue, Resource,
+    ResourceMetric, Scope, ScopeMetric, Value,
+};
+
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+
+pub fn encode_payload(payload: &MetricsPayload) -> Vec<u8> {
+    let mut buf = Vec::new();
+    payload.encode(&mut buf);
+    buf
+}
+
+trait Message {
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+impl Message for MetricsPayload<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for resource_metric in &self.resource_metrics {
+            write_message(buf, 1, resource_metric);
+        }
+    }
+}
+
+impl Message for ResourceMetric<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_message(buf, 1, &self.resource);
+        for scope_metric in &self.scope_metrics {
+            write_message(buf, 2, scope_metric);
+        }
+    }
+}
+
+impl Message for Resource<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for attribute in &self.attributes {
+            write_message(buf, 1, attribute);
+        }
+    }
+}
+
+impl Message for ScopeMetric<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_message(buf, 1, &self.scope);
+        for metric in &self.metrics {
+            write_message(buf, 2, metric);
+        }
+    }
+}
+
+impl Message for Scope<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_bytes(buf, 1, self.name.as_bytes());
+        write_bytes(buf, 2, self.version.as_bytes());
+        for attribute in &self.attributes {
+            write_message(buf, 3, attribute);
+        }
+    }
+}
+
+impl Message for Metric<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_bytes(buf, 1, self.name.as_bytes());
+        write_bytes(buf, 2, self.description.as_bytes());
+        write_bytes(buf, 3, self.unit.as_bytes());
+        write_message(buf, 5, &self.gauge);
+    }
+}
+
+impl Message for Gauge<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for data_point in &self.data_points {
+            write_message(buf, 1, data_point);
+        }
+    }
+}
+
+impl Message for GaugeDataPoint<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        if let Ok(time) = self.time_unix_nano.parse::<u64>() {
+            write_fixed64(buf, 3, time.to_le_bytes());
+        }
+
+        match self.value {
+            NumberValue::Float(value) => write_fixed64(buf, 4, value.to_le_bytes()),
+            NumberValue::Int(value) => write_fixed64(buf, 6, value.to_le_bytes()),
+        }
+
+        for attribute in &self.attributes {
+            write_message(buf, 7, attribute);
+        }
+    }
+}
+
+impl Message for Attribute<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_bytes(buf, 1, self.key.as_bytes());
+        write_message(buf, 2, &self.value);
+    }
+}
+
+impl Message for Value<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Value::String(value) => write_bytes(buf, 1, value.as_bytes()),
+        }
+    }
+}
+
+fn write_message(buf: &mut Vec<u8>, field: u64, message: &impl Message) {
+    let mut inner = Vec::new();
+    message.encode(&mut inner);
+    write_bytes(buf, field, &inner);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u64, value: &[u8]) {
+    write_varint(buf, (field << 3) | WIRE_LEN);
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+fn write_fixed64(buf: &mut Vec<u8>, field: u64, value: [u8; 8]) {
+    write_varint(buf, (field << 3) | WIRE_FIXED64);
+    buf.extend_from_slice(&value);
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, write_varint};
+    use crate::server::metrics::schema::{Attribute, GaugeDataPoint, NumberValue, Value};
+
+    #[test]
+    fn encode_varint() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 1);
+        write_varint(&mut buf, 300);
+        assert_eq!(vec![0x01, 0xAC, 0x02], buf);
+    }
+
+    #[test]
+    fn encode_data_point() {
+        let data_point = GaugeDataPoint {
+            value: NumberValue::Int(5),
+            time_unix_nano: "1",
+            attributes: vec![Attribute {
+                key: "a",
+                value: Value::String("b"),
+            }],
+        };
+
+        let mut buf = Vec::new();
+        data_point.encode(&mut buf);
+
+        assert_eq!(
+            vec![
+                0x19, 1, 0, 0, 0, 0, 0, 0, 0, // time_unix_nano
+                0x31, 5, 0, 0, 0, 0, 0, 0, 0, // as_int
+                0x3A, 8, 0x0A, 1, b'a', 0x12, 3, 0x0A, 1, b'b', // attributes
+            ],
+            buf
+        );
+    }
+}
@@ -1,10 +1,17 @@
 mod gamemode;
+mod power_source;
 mod process;
 
-use crate::server::{handler::Handler, profiles::gamemode::GameModeConnector};
-use lact_schema::{ProfileRule, ProfileWatcherState};
+use crate::server::{
+    handler::Handler, profiles::gamemode::GameModeConnector, stats::StatsSubscription,
+};
+use lact_schema::{
+    PowerSourceState, ProfileRule, ProfileWatcherState, ScheduleTime, Weekday,
+    history::StatsHistorySample,
+};
 use libcopes::PEvent;
 use std::{
+    collections::HashMap,
     rc::Rc,
     time::{Duration, Instant},
 };
@@ -19,11 +26,35 @@ static PROFILE_WATCHER_LOCK: Mutex<()> = Mutex::const_new(());
 
 const PROFILE_WATCHER_MIN_DELAY_MS: u64 = 50;
 const PROFILE_WATCHER_MAX_DELAY_MS: u64 = 500;
+/// How often GPU stats are checked while there are GPU stat rules
+const GPU_STATS_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Debug)]
 enum ProfileWatcherEvent {
     Process(PEvent),
     Gamemode(PEvent),
+    PowerSource(PowerSourceState),
+}
+
+/// Local time used for evaluating schedule rules
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LocalTime {
+    pub weekday: Weekday,
+    pub time: ScheduleTime,
+}
+
+impl LocalTime {
+    pub fn now() -> Self {
+        let now = jiff::Zoned::now();
+        Self {
+            weekday: Weekday::from_monday_one_offset(now.weekday().to_monday_one_offset())
+                .expect("Weekday offset is always valid"),
+            time: ScheduleTime {
+                hour: now.hour().unsigned_abs(),
+                minute: now.minute().unsigned_abs(),
+            },
+        }
+    }
 }
 
 pub enum ProfileWatcherCommand {
@@ -43,10 +74,15 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
     let mut state = ProfileWatcherState::default();
     process::load_full_process_list(&mut state);
     info!("loaded {} processes", state.process_list.len());
+    state.power_source = power_source::read_state();
+    state
+        .focused_window
+        .clone_from(&handler.focused_window.borrow());
 
     let (event_tx, mut event_rx) = mpsc::channel(128);
 
     process::start_listener(event_tx.clone());
+    power_source::start_listener(event_tx.clone());
 
     let gamemode_stop_notify = Rc::new(Notify::new());
     let mut gamemode_task = None;
@@ -98,9 +134,36 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
     update_profile(&handler).await;
 
     let mut should_reload = false;
+    let mut last_stats_sample = Instant::now();
+    let mut stats_subscriptions = HashMap::new();
 
     loop {
+        let periodic_rules = get_periodic_rules(&handler).await;
+        if periodic_rules.stats_duration.is_none() {
+            // Lets the shared samplers slow down or stop
+            stats_subscriptions.clear();
+        }
+        let schedule_delay = periodic_rules.schedule.then(time_until_next_minute);
+        // Counted from the last sample, so that frequent events don't keep delaying it
+        let stats_delay = periodic_rules
+            .stats_duration
+            .map(|_| GPU_STATS_INTERVAL.saturating_sub(last_stats_sample.elapsed()));
+
         select! {
+            // Schedule boundaries are always at the start of a minute
+            () = sleep(schedule_delay.unwrap_or_default()), if schedule_delay.is_some() => {
+                update_profile(&handler).await;
+            }
+            () = sleep(stats_delay.unwrap_or_default()), if stats_delay.is_some() => {
+                last_stats_sample = Instant::now();
+                sample_gpu_stats(
+                    &handler,
+                    &mut stats_subscriptions,
+                    periodic_rules.stats_duration.unwrap_or_default(),
+                )
+                .await;
+                update_profile(&handler).await;
+            }
             Some(cmd) = command_rx.recv() => {
                 match cmd {
                     ProfileWatcherCommand::Stop => break,
@@ -198,6 +261,10 @@ fn handle_profile_event(event: &ProfileWatcherEvent, handler: &Handler, should_r
         ProfileWatcherEvent::Gamemode(PEvent::Exit(pid)) => {
             state.gamemode_games.shift_remove(pid.as_ref());
         }
+        ProfileWatcherEvent::PowerSource(power_source) => {
+            info!("power source changed: {power_source:?}");
+            state.power_source = power_source;
+        }
     }
 }
 
@@ -212,7 +279,7 @@ async fn update_profile(handler: &Handler) {
         let state_guard = handler.profile_watcher_state.borrow();
         if let Some(state) = state_guard.as_ref() {
             let started_at = Instant::now();
-            let new_profile = evaluate_current_profile(state, profile_rules);
+            let new_profile = evaluate_current_profile(state, profile_rules, LocalTime::now());
             trace!("evaluated profile rules in {:?}", started_at.elapsed());
             new_profile.cloned()
         } else {
@@ -233,13 +300,123 @@ async fn update_profile(handler: &Handler) {
     }
 }
 
+/// Rules that have to be re-evaluated periodically instead of on events
+#[derive(Default)]
+struct PeriodicRules {
+    schedule: bool,
+    /// Longest duration of the GPU stat rules, `None` if there are none
+    stats_duration: Option<Duration>,
+}
+
+impl PeriodicRules {
+    fn add(&mut self, rule: &ProfileRule) {
+        match rule {
+            ProfileRule::Schedule(_) => self.schedule = true,
+            ProfileRule::GpuStat(stat_rule) => {
+                let duration = Duration::from_secs(stat_rule.duration);
+                self.stats_duration = Some(self.stats_duration.unwrap_or_default().max(duration));
+            }
+            ProfileRule::And(rules) | ProfileRule::Or(rules) => {
+                for rule in rules {
+                    self.add(rule);
+                }
+            }
+            ProfileRule::Process(_)
+            | ProfileRule::Gamemode(_)
+            | ProfileRule::PowerSource { .. }
+            | ProfileRule::FocusedWindow(_) => (),
+        }
+    }
+}
+
+async fn get_periodic_rules(handler: &Handler) -> PeriodicRules {
+    let config = handler.config.read().await;
+    let mut periodic_rules = PeriodicRules::default();
+    for rule in config
+        .profiles
+        .values()
+        .filter_map(|profile| profile.rule.as_ref())
+    {
+        periodic_rules.add(rule);
+    }
+    periodic_rules
+}
+
+/// Takes the latest samples from the shared stats samplers, which are also used by stats clients and the history
+async fn sample_gpu_stats(
+    handler: &Handler,
+    subscriptions: &mut HashMap<String, StatsSubscription>,
+    rules_duration: Duration,
+) {
+    let timestamp = jiff::Timestamp::now().as_millisecond();
+
+    // The sampler of a GPU stops if its stats can't be read, and is started again on the next check
+    subscriptions.retain(|_, subscription| subscription.rx.has_changed().is_ok());
+    for device in handler.list_devices().await {
+        if !subscriptions.contains_key(&device.id) {
+            match handler
+                .subscribe_stats(&device.id, GPU_STATS_INTERVAL)
+                .await
+            {
+                Ok(subscription) => {
+                    subscriptions.insert(device.id, subscription);
+                }
+                Err(err) => debug!("could not subscribe to stats for profile rules: {err:#}"),
+            }
+        }
+    }
+
+    let samples: Vec<_> = subscriptions
+        .iter_mut()
+        .filter_map(|(id, subscription)| {
+            // Only new samples are used, the sampler might not have produced one since the last check
+            if !subscription.rx.has_changed().unwrap_or(false) {
+                return None;
+            }
+            let stats = subscription.rx.borrow_and_update().clone()?;
+            Some((
+                id.clone(),
+                StatsHistorySample::from_stats(timestamp, &stats),
+            ))
+        })
+        .collect();
+
+    let mut state_guard = handler.profile_watcher_state.borrow_mut();
+    let Some(state) = state_guard.as_mut() else {
+        return;
+    };
+
+    // Samples are kept for twice the rule duration, so that a rule can both activate and deactivate within the window
+    let max_age = rules_duration * 2 + GPU_STATS_INTERVAL;
+    let cutoff = timestamp - i64::try_from(max_age.as_millis()).unwrap_or(i64::MAX);
+
+    for (id, sample) in samples {
+        let gpu_samples = state.gpu_stats.entry(id).or_default();
+        gpu_samples.push_back(sample);
+        while gpu_samples
+            .front()
+            .is_some_and(|sample| sample.timestamp < cutoff)
+        {
+            gpu_samples.pop_front();
+        }
+    }
+}
+
+fn time_until_next_minute() -> Duration {
+    let now = jiff::Zoned::now();
+    let elapsed = Duration::from_secs(now.second().unsigned_abs().into())
+        + Duration::from_nanos(now.subsec_nanosecond().unsigned_abs().into());
+    Duration::from_secs(60).saturating_sub(elapsed)
+}
+
 /// Returns the new active profile
 fn evaluate_current_profile<'a>(
     state: &ProfileWatcherState,
     profile_rules: impl Iterator<Item = (&'a Rc<str>, &'a ProfileRule)>,
+    now: LocalTime,
 ) -> Option<&'a Rc<str>> {
     for (profile_name, rule) in profile_rules {
-        if profile_rule_matches(state, rule) {
+        if profile_rule_matches(state, rule, now) {
             return Some(profile_name);
         }
     }
@@ -248,52 +425,91 @@ fn evaluate_current_profile<'a>(
 }
 
 #[inline]
-pub(crate) fn profile_rule_matches(state: &ProfileWatcherState, rule: &ProfileRule) -> bool {
+pub(crate) fn profile_rule_matches(
+    state: &ProfileWatcherState,
+    rule: &ProfileRule,
+    now: LocalTime,
+) -> bool {
     match rule {
         ProfileRule::Process(process_rule) => {
-            if let Some(pids) = state.process_names_map.get(&process_rule.name) {
-                match &process_rule.args {
-                    Some(args_filter) => {
-                        for pid in pids {
-                            if let Some(process_info) = state.process_list.get(pid) {
-                                if process_info.cmdline.contains(args_filter) {
-                                    return true;
-                                }
-                            } else {
-                                error!("process {pid} not found in process map");
+            if process_rule.has_exact_name() {
+                if let Some(pids) = state.process_names_map.get(&process_rule.name) {
+                    for pid in pids {
+                        if let Some(process_info) = state.process_list.get(pid) {
+                            if process_rule.matches(process_info) {
+                                return true;
                             }
+                        } else {
+                            error!("process {pid} not found in process map");
                         }
                     }
-                    None => return true,
                 }
+            } else if state
+                .process_list
+                .values()
+                .any(|process_info| process_rule.matches(process_info))
+            {
+                return true;
             }
         }
         ProfileRule::Gamemode(None) => return !state.gamemode_games.is_empty(),
         ProfileRule::Gamemode(Some(gamemode_rule)) => {
-            if let Some(pids) = state.process_names_map.get(&gamemode_rule.name) {
-                for pid in pids {
-                    if state.gamemode_games.contains(pid) {
-                        match &gamemode_rule.args {
-                            Some(args_filter) => {
-                                if let Some(process_info) = state.process_list.get(pid) {
-                                    if process_info.cmdline.contains(args_filter) {
-                                        return true;
-                                    }
-                                } else {
-                                    error!("process {pid} not found in process map");
-                                }
-                            }
-                            None => return true,
-                        }
+            for pid in &state.gamemode_games {
+                if let Some(process_info) = state.process_list.get(pid) {
+                    if gamemode_rule.matches(process_info) {
+                        return true;
                     }
+                } else {
+                    error!("process {pid} not found in process map");
                 }
             }
         }
+        ProfileRule::Schedule(schedule_rule) => {
+            return schedule_rule.is_active(now.weekday, now.time);
+        }
+        ProfileRule::PowerSource {
+            on_battery,
+            battery_below,
+            battery_above,
+        } => {
+            let power_source = &state.power_source;
+            let battery_percent = power_source.battery_percent;
+
+            return power_source.on_battery == *on_battery
+                && battery_below.is_none_or(|threshold| {
+                    battery_percent.is_some_and(|value| value < threshold)
+                })
+                && battery_above.is_none_or(|threshold| {
+                    battery_percent.is_some_and(|value| value > threshold)
+                });
+        }
+        ProfileRule::GpuStat(stat_rule) => {
+            return state
+                .gpu_stats
+                .iter()
+                .filter(|(id, _)| stat_rule.gpu_id.as_ref().is_none_or(|gpu_id| gpu_id == *id))
+                .any(|(_, samples)| stat_rule.is_active(samples));
+        }
+        ProfileRule::FocusedWindow(window_rule) => {
+            return state.focused_window.as_ref().is_some_and(|window| {
+                let process_name = window
+                    .pid
+                    .and_then(|pid| state.process_list.get(&pid))
+                    .map(|info| info.name.as_ref());
+                window_rule.matches(window, process_name)
+            });
+        }
         ProfileRule::And(rules) => {
-            return !rules.is_empty() && rules.iter().all(|rule| profile_rule_matches(state, rule));
+            return !rules.is_empty()
+                && rules
+                    .iter()
+                    .all(|rule| profile_rule_matches(state, rule, now));
         }
         ProfileRule::Or(rules) => {
-            return !rules.is_empty() && rules.iter().any(|rule| profile_rule_matches(state, rule));
+            return !rules.is_empty()
+                && rules
+                    .iter()
+                    .any(|rule| profile_rule_matches(state, rule, now));
         }
     }
     false
@@ -301,19 +517,26 @@ pub(crate) fn profile_rule_matches(state: &ProfileWatcherState, rule: &ProfileRu
 
 #[cfg(test)]
 mod tests {
-    use super::evaluate_current_profile;
-    use lact_schema::{ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState};
+    use super::{LocalTime, evaluate_current_profile};
+    use lact_schema::{
+        GpuStatProfileRule, PowerSourceState, ProcessMatchMode, ProcessProfileRule,
+        ProfileProcessInfo, ProfileRule, ProfileRuleStat, ProfileWatcherState, ScheduleProfileRule,
+        ScheduleTime, Weekday,
+        history::{StatType, StatsHistorySample},
+    };
     use pretty_assertions::assert_eq;
     use std::rc::Rc;
 
     #[test]
     fn evaluate_basic_profile() {
+        let now = LocalTime::now();
         let mut state = ProfileWatcherState::default();
         state.push_process(
             1,
             ProfileProcessInfo {
                 name: "game1".into(),
                 cmdline: "".into(),
+                ..Default::default()
             },
         );
 
@@ -322,21 +545,25 @@ mod tests {
                 "1".into(),
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game1".into(),
-                    args: None,
+                    ..Default::default()
                 }),
             ),
             (
                 "2".into(),
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game2".into(),
-                    args: None,
+                    ..Default::default()
                 }),
             ),
         ];
 
         assert_eq!(
             Some(&Rc::from("1")),
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &state,
+                profile_rules.iter().map(|(key, rule)| (key, rule)),
+                now
+            )
         );
 
         state.push_process(
@@ -344,11 +571,16 @@ mod tests {
             ProfileProcessInfo {
                 name: "game2".into(),
                 cmdline: "".into(),
+                ..Default::default()
             },
         );
         assert_eq!(
             Some(&Rc::from("2")),
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &state,
+                profile_rules.iter().map(|(key, rule)| (key, rule)),
+                now
+            )
         );
 
         state.push_process(
@@ -356,54 +588,327 @@ mod tests {
             ProfileProcessInfo {
                 name: "game3".into(),
                 cmdline: "".into(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            None,
+            evaluate_current_profile(
+                &state,
+                profile_rules.iter().map(|(key, rule)| (key, rule)),
+                now
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_pattern_profile() {
+        let now = LocalTime::now();
+        let mut state = ProfileWatcherState::default();
+        state.push_process(
+            1,
+            ProfileProcessInfo {
+                name: "steam".into(),
+                cmdline: "/home/user/.steam/steam/ubuntu12_32/steam -silent".into(),
+                uid: Some(1000),
+                ..Default::default()
+            },
+        );
+
+        let mut glob_rule = ProcessProfileRule {
+            name: "game-*".into(),
+            match_mode: ProcessMatchMode::Glob,
+            ..Default::default()
+        };
+        glob_rule.compile().unwrap();
+        let mut regex_rule = ProcessProfileRule {
+            match_mode: ProcessMatchMode::Regex,
+            cgroup: Some(r"^app-steam-app1091500-\d+\.scope$".to_owned()),
+            ..Default::default()
+        };
+        regex_rule.compile().unwrap();
+
+        let profile_rules = [
+            ("glob".into(), ProfileRule::Process(glob_rule)),
+            ("regex".into(), ProfileRule::Gamemode(Some(regex_rule))),
+        ];
+        let evaluate = |state: &ProfileWatcherState| {
+            evaluate_current_profile(
+                state,
+                profile_rules.iter().map(|(key, rule)| (key, rule)),
+                now,
+            )
+            .cloned()
+        };
+
+        assert_eq!(None, evaluate(&state));
+
+        state.push_process(
+            2,
+            ProfileProcessInfo {
+                name: "Cyberpunk2077.exe".into(),
+                cmdline: "Z:\\games\\Cyberpunk2077.exe".into(),
+                cgroup: Some(
+                    "/user.slice/user-1000.slice/user@1000.service/app.slice/app-steam-app1091500-4242.scope"
+                        .into(),
+                ),
+                ..Default::default()
+            },
+        );
+        assert_eq!(None, evaluate(&state));
+        state.gamemode_games.insert(2);
+        assert_eq!(Some(Rc::from("regex")), evaluate(&state));
+
+        state.push_process(
+            3,
+            ProfileProcessInfo {
+                name: "game-abc".into(),
+                cmdline: "game-abc".into(),
+                ..Default::default()
             },
         );
+        assert_eq!(Some(Rc::from("glob")), evaluate(&state));
+    }
+
+    #[test]
+    fn evaluate_schedule_profile() {
+        let state = ProfileWatcherState::default();
+        let profile_rules = [
+            (
+                "night".into(),
+                ProfileRule::Schedule(ScheduleProfileRule {
+                    start: ScheduleTime::new(22, 0).unwrap(),
+                    end: ScheduleTime::new(7, 0).unwrap(),
+                    weekdays: vec![],
+                }),
+            ),
+            (
+                "office".into(),
+                ProfileRule::And(vec![
+                    ProfileRule::Schedule(ScheduleProfileRule {
+                        start: ScheduleTime::new(9, 0).unwrap(),
+                        end: ScheduleTime::new(17, 0).unwrap(),
+                        weekdays: vec![Weekday::Monday, Weekday::Friday],
+                    }),
+                    ProfileRule::Process(ProcessProfileRule {
+                        name: "game1".into(),
+                        ..Default::default()
+                    }),
+                ]),
+            ),
+        ];
+
+        let evaluate = |weekday, hour| {
+            let now = LocalTime {
+                weekday,
+                time: ScheduleTime::new(hour, 30).unwrap(),
+            };
+            evaluate_current_profile(
+                &state,
+                profile_rules.iter().map(|(key, rule)| (key, rule)),
+                now,
+            )
+            .cloned()
+        };
+
+        assert_eq!(Some(Rc::from("night")), evaluate(Weekday::Tuesday, 23));
+        assert_eq!(Some(Rc::from("night")), evaluate(Weekday::Wednesday, 6));
+        assert_eq!(None, evaluate(Weekday::Monday, 12));
+    }
+
+    #[test]
+    fn evaluate_power_source_profile() {
+        let now = LocalTime::now();
+        let profile_rules = [
+            (
+                "low-battery".into(),
+                ProfileRule::PowerSource {
+                    on_battery: true,
+                    battery_below: Some(20),
+                    battery_above: None,
+                },
+            ),
+            (
+                "battery".into(),
+                ProfileRule::PowerSource {
+                    on_battery: true,
+                    battery_below: None,
+                    battery_above: None,
+                },
+            ),
+        ];
+
+        let evaluate = |power_source| {
+            let state = ProfileWatcherState {
+                power_source,
+                ..Default::default()
+            };
+            evaluate_current_profile(
+                &state,
+                profile_rules.iter().map(|(key, rule)| (key, rule)),
+                now,
+            )
+            .cloned()
+        };
+
         assert_eq!(
             None,
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate(PowerSourceState {
+                on_battery: false,
+                battery_percent: Some(10),
+            })
+        );
+        assert_eq!(
+            Some(Rc::from("low-battery")),
+            evaluate(PowerSourceState {
+                on_battery: true,
+                battery_percent: Some(10),
+            })
+        );
+        assert_eq!(
+            Some(Rc::from("battery")),
+            evaluate(PowerSourceState {
+                on_battery: true,
+                battery_percent: Some(20),
+            })
         );
     }
+
+    #[test]
+    fn evaluate_gpu_stat_profile() {
+        let now = LocalTime::now();
+        let profile_rules = [(
+            "load".into(),
+            ProfileRule::GpuStat(GpuStatProfileRule {
+                gpu_id: Some("gpu-2".to_owned()),
+                stat: ProfileRuleStat::BusyPercent,
+                above: Some(80.0),
+                duration: 10,
+                ..Default::default()
+            }),
+        )];
+
+        let mut state = ProfileWatcherState::default();
+        for (id, value) in [("gpu-1", 90.0), ("gpu-2", 50.0)] {
+            let samples = (0..=10_000)
+                .step_by(2_000)
+                .map(|timestamp| StatsHistorySample {
+                    timestamp,
+                    values: vec![(StatType::GpuUsage, value)],
+                    throttling: vec![],
+                })
+                .collect();
+            state.gpu_stats.insert(id.to_owned(), samples);
+        }
+
+        let evaluate = |state: &ProfileWatcherState| {
+            evaluate_current_profile(
+                state,
+                profile_rules.iter().map(|(key, rule)| (key, rule)),
+                now,
+            )
+            .cloned()
+        };
+
+        assert_eq!(None, evaluate(&state));
+
+        for sample in state.gpu_stats.get_mut("gpu-2").unwrap() {
+            sample.values = vec![(StatType::GpuUsage, 95.0)];
+        }
+        assert_eq!(Some(Rc::from("load")), evaluate(&state));
+    }
 }
 
 #[cfg(feature = "bench")]
 mod benches {
-    use super::evaluate_current_profile;
+    use super::{LocalTime, evaluate_current_profile};
     use divan::Bencher;
-    use lact_schema::{ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState};
-    use std::hint::black_box;
+    use lact_schema::{
+        ProcessMatchMode, ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState,
+    };
+    use std::{hint::black_box, rc::Rc};
 
-    #[divan::bench(sample_size = 1000, min_time = 2)]
-    fn evaluate_profiles(bencher: Bencher) {
+    fn process_state() -> ProfileWatcherState {
         let mut state = ProfileWatcherState::default();
 
         for pid in 1..2000 {
             let name = format!("process-{pid}").into();
             let cmdline = format!("{name} arg1 arg2 --arg3").into();
-            state.push_process(pid, ProfileProcessInfo { name, cmdline });
+            state.push_process(
+                pid,
+                ProfileProcessInfo {
+                    name,
+                    cmdline,
+                    exe: Some(format!("/usr/bin/process-{pid}").into()),
+                    cwd: Some("/home/user".into()),
+                    uid: Some(1000),
+                    cgroup: Some(format!("/user.slice/user-1000.slice/session-{pid}.scope").into()),
+                },
+            );
         }
 
-        let profile_rules = [
-            (
-                "1".into(),
-                ProfileRule::Process(ProcessProfileRule {
-                    name: "game-abc".into(),
-                    args: None,
-                }),
-            ),
-            (
-                "2".into(),
-                ProfileRule::Process(ProcessProfileRule {
-                    name: "game-1034".into(),
-                    args: None,
-                }),
-            ),
-        ];
+        state
+    }
+
+    fn bench_rules(bencher: Bencher, profile_rules: Vec<(Rc<str>, ProfileRule)>) {
+        let state = process_state();
 
         bencher.bench_local(move || {
             evaluate_current_profile(
                 black_box(&state),
                 black_box(profile_rules.iter().map(|(key, rule)| (key, rule))),
+                black_box(LocalTime::now()),
             );
         });
     }
+
+    #[divan::bench(sample_size = 1000, min_time = 2)]
+    fn evaluate_profiles(bencher: Bencher) {
+        bench_rules(
+            bencher,
+            vec![
+                (
+                    "1".into(),
+                    ProfileRule::Process(ProcessProfileRule {
+                        name: "game-abc".into(),
+                        ..Default::default()
+                    }),
+                ),
+                (
+                    "2".into(),
+                    ProfileRule::Process(ProcessProfileRule {
+                        name: "game-1034".into(),
+                        ..Default::default()
+                    }),
+                ),
+            ],
+        );
+    }
+
+    #[divan::bench(sample_size = 1000, min_time = 2)]
+    fn evaluate_pattern_profiles(bencher: Bencher) {
+        let mut glob_rule = ProcessProfileRule {
+            name: "game-*".into(),
+            args: Some("* --fullscreen*".to_owned()),
+            match_mode: ProcessMatchMode::Glob,
+            ..Default::default()
+        };
+        glob_rule.compile().unwrap();
+
+        let mut regex_rule = ProcessProfileRule {
+            match_mode: ProcessMatchMode::Regex,
+            exe: Some(r"^/usr/bin/process-\d+$".to_owned()),
+            cgroup: Some(r"^app-steam-app\d+-\d+\.scope$".to_owned()),
+            ..Default::default()
+        };
+        regex_rule.compile().unwrap();
+
+        bench_rules(
+            bencher,
+            vec![
+                ("1".into(), ProfileRule::Process(glob_rule)),
+                ("2".into(), ProfileRule::Process(regex_rule)),
+            ],
+        );
+    }
 }
@@ -0,0 +1,195 @@
+use super::ProfileWatcherEvent;
+use anyhow::Context;
+use lact_schema::PowerSourceState;
+use nix::{
+    errno::Errno,
+    sys::{
+        socket::{
+            AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType, bind, recv,
+            setsockopt, socket, sockopt::ReceiveTimeout,
+        },
+        time::{TimeVal, TimeValLike},
+    },
+};
+use std::{fs, os::fd::AsRawFd, path::Path};
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+/// Not every battery reports charge changes with uevents, so the state is also checked periodically
+const POLL_INTERVAL_SECS: i64 = 60;
+
+pub fn read_state() -> PowerSourceState {
+    read_state_from(Path::new(POWER_SUPPLY_PATH))
+}
+
+fn read_state_from(base_path: &Path) -> PowerSourceState {
+    let mut adapters_online = None;
+    let mut discharging = false;
+    let mut capacities = Vec::new();
+
+    for entry in fs::read_dir(base_path).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let read = |name: &str| {
+            fs::read_to_string(path.join(name))
+                .ok()
+                .map(|value| value.trim().to_owned())
+        };
+
+        // Peripherals such as wireless mice report their own batteries
+        if read("scope").as_deref() == Some("Device") {
+            continue;
+        }
+
+        match read("type").as_deref() {
+            Some("Mains" | "USB") => {
+                let online = read("online").as_deref() == Some("1");
+                adapters_online = Some(adapters_online.unwrap_or(false) || online);
+            }
+            Some("Battery") => {
+                if let Some(capacity) = read("capacity").and_then(|value| value.parse::<u32>().ok())
+                {
+                    capacities.push(capacity);
+                }
+                discharging |= read("status").as_deref() == Some("Discharging");
+            }
+            _ => (),
+        }
+    }
+
+    let battery_percent = u32::try_from(capacities.len())
+        .ok()
+        .filter(|count| *count > 0)
+        .and_then(|count| u8::try_from(capacities.iter().sum::<u32>() / count).ok());
+
+    // Systems without a reported adapter are on battery when it's discharging
+    let on_battery =
+        battery_percent.is_some() && adapters_online.map_or(discharging, |online| !online);
+
+    PowerSourceState {
+        on_battery,
+        battery_percent,
+    }
+}
+
+pub fn start_listener(event_tx: mpsc::Sender<ProfileWatcherEvent>) {
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = listen(&event_tx) {
+            error!("power source listener error: {err:#}");
+        }
+    });
+}
+
+fn listen(event_tx: &mpsc::Sender<ProfileWatcherEvent>) -> anyhow::Result<()> {
+    let socket = socket(
+        AddressFamily::Netlink,
+        SockType::Raw,
+        SockFlag::empty(),
+        SockProtocol::NetlinkKObjectUEvent,
+    )
+    .context("Could not setup netlink socket")?;
+
+    // Port id 0 lets the kernel pick a free one, as the process id is used by the drm event listener
+    bind(socket.as_raw_fd(), &NetlinkAddr::new(0, 1)).context("Could not bind netlink socket")?;
+    setsockopt(
+        &socket,
+        ReceiveTimeout,
+        &TimeVal::seconds(POLL_INTERVAL_SECS),
+    )
+    .context("Could not set socket timeout")?;
+
+    let mut last_state = read_state();
+    let mut buf = vec![0; 8192];
+
+    loop {
+        if event_tx.is_closed() {
+            debug!("profile watcher channel closed, exiting power source listener");
+            return Ok(());
+        }
+
+        match recv(socket.as_raw_fd(), &mut buf, MsgFlags::empty()) {
+            Ok(len) => {
+                let is_power_supply_event = buf[..len]
+                    .split(|c| *c == b'\0')
+                    .any(|line| line == b"SUBSYSTEM=power_supply");
+                if !is_power_supply_event {
+                    continue;
+                }
+            }
+            Err(Errno::EAGAIN) => (),
+            Err(err) => return Err(err).context("Could not read netlink message"),
+        }
+
+        let state = read_state();
+        if state != last_state {
+            debug!("power source changed: {state:?}");
+            last_state = state;
+
+            if event_tx
+                .blocking_send(ProfileWatcherEvent::PowerSource(state))
+                .is_err()
+            {
+                debug!("profile watcher channel closed, exiting power source listener");
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_state_from;
+    use lact_schema::PowerSourceState;
+    use std::{fs, path::Path};
+
+    fn add_supply(base_path: &Path, name: &str, attributes: &[(&str, &str)]) {
+        let path = base_path.join(name);
+        fs::create_dir(&path).unwrap();
+        for (attribute, value) in attributes {
+            fs::write(path.join(attribute), format!("{value}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn laptop_power_source() {
+        let dir = tempfile::tempdir().unwrap();
+        add_supply(dir.path(), "AC", &[("type", "Mains"), ("online", "0")]);
+        add_supply(
+            dir.path(),
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("capacity", "42"),
+                ("status", "Discharging"),
+            ],
+        );
+        add_supply(
+            dir.path(),
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "5")],
+        );
+
+        assert_eq!(
+            PowerSourceState {
+                on_battery: true,
+                battery_percent: Some(42),
+            },
+            read_state_from(dir.path())
+        );
+
+        fs::write(dir.path().join("AC/online"), "1\n").unwrap();
+        assert!(!read_state_from(dir.path()).on_battery);
+    }
+
+    #[test]
+    fn desktop_power_source() {
+        let dir = tempfile::tempdir().unwrap();
+        add_supply(
+            dir.path(),
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "5")],
+        );
+
+        assert_eq!(PowerSourceState::default(), read_state_from(dir.path()));
+    }
+}
@@ -1,7 +1,7 @@
 use super::ProfileWatcherEvent;
 use lact_schema::{ProfileProcessInfo, ProfileWatcherState};
 use libcopes::{PID, ProcessEventsConnector};
-use std::{ffi::OsString, fs};
+use std::{ffi::OsString, fs, os::unix::fs::MetadataExt, path::PathBuf};
 use tokio::sync::mpsc;
 use tracing::{debug, error};
 
@@ -81,11 +81,55 @@ pub fn get_pid_info(pid: PID) -> std::io::Result<ProfileProcessInfo> {
         .to_string()
         .into();
 
+    let process_path = PathBuf::from(pid);
+    let read_link = |name: &str| {
+        fs::read_link(process_path.join(name))
+            .ok()
+            .map(|path| path.to_string_lossy().into())
+    };
+
     Ok(ProfileProcessInfo {
         name,
         cmdline: cmdline
             .to_string()
             .trim_matches(|c| c == '[' || c == ']')
             .into(),
+        exe: read_link("exe"),
+        cwd: read_link("cwd"),
+        uid: process_path.metadata().ok().as_ref().map(MetadataExt::uid),
+        cgroup: fs::read_to_string(process_path.join("cgroup"))
+            .ok()
+            .as_deref()
+            .and_then(parse_cgroup)
+            .map(Into::into),
     })
 }
+
+/// Returns the unified hierarchy path from `/proc/<pid>/cgroup`
+fn parse_cgroup(contents: &str) -> Option<&str> {
+    contents.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_cgroup;
+
+    #[test]
+    fn parse_process_cgroup() {
+        assert_eq!(
+            Some(
+                "/user.slice/user-1000.slice/user@1000.service/app.slice/app-steam-app1091500-4242.scope"
+            ),
+            parse_cgroup(
+                "0::/user.slice/user-1000.slice/user@1000.service/app.slice/app-steam-app1091500-4242.scope\n"
+            )
+        );
+        assert_eq!(
+            Some("/system.slice/lactd.service"),
+            parse_cgroup(
+                "12:pids:/system.slice/lactd.service\n1:name=systemd:/system.slice/lactd.service\n0::/system.slice/lactd.service\n"
+            )
+        );
+        assert_eq!(None, parse_cgroup("1:name=systemd:/\n"));
+    }
+}
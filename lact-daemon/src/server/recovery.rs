@@ -0,0 +1,151 @@
+//! Tracking of GPU resets and of settings that are known to be stable, used to roll back settings that cause reset loops
+use crate::config::ResetRecovery;
+use lact_schema::{ConfigRecovery, config::GpuConfig};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Resets reported within this period are counted once, as a single reset shows up both in the kernel log and as a uevent
+const RESET_DEBOUNCE: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct RecoveryState {
+    resets: HashMap<String, Vec<Instant>>,
+    /// The settings of every GPU and since when they have been used without a reset
+    observed: HashMap<String, (GpuConfig, Instant)>,
+    recoveries: HashMap<String, ConfigRecovery>,
+}
+
+impl RecoveryState {
+    /// Records the settings currently used by a GPU. Returns whether they have been used without a reset for at least `known_good_time`.
+    pub fn observe(
+        &mut self,
+        id: &str,
+        config: &GpuConfig,
+        now: Instant,
+        known_good_time: Duration,
+    ) -> bool {
+        match self.observed.get(id) {
+            Some((observed_config, since)) if observed_config == config => {
+                now.duration_since(*since) >= known_good_time
+            }
+            _ => {
+                self.observed.insert(id.to_owned(), (config.clone(), now));
+                false
+            }
+        }
+    }
+
+    /// Forgets the currently used settings, e.g. when they are only applied temporarily
+    pub fn clear_observed(&mut self, id: &str) {
+        self.observed.remove(id);
+    }
+
+    /// Records a reset of the GPU. Returns the amount of resets within the window if it reached the limit.
+    pub fn record_reset(
+        &mut self,
+        id: &str,
+        now: Instant,
+        settings: &ResetRecovery,
+    ) -> Option<u32> {
+        let window = Duration::from_secs(settings.window);
+        let resets = self.resets.entry(id.to_owned()).or_default();
+
+        if resets
+            .last()
+            .is_some_and(|last| now.duration_since(*last) < RESET_DEBOUNCE)
+        {
+            return None;
+        }
+
+        resets.retain(|reset| now.duration_since(*reset) < window);
+        resets.push(now);
+
+        // Settings that were in use during a reset have to start over to become known good
+        if let Some((_, since)) = self.observed.get_mut(id) {
+            *since = now;
+        }
+
+        let count = u32::try_from(resets.len()).unwrap_or(u32::MAX);
+        if count >= settings.max_resets {
+            resets.clear();
+            Some(count)
+        } else {
+            None
+        }
+    }
+
+    pub fn recovery(&self, id: &str) -> Option<&ConfigRecovery> {
+        self.recoveries.get(id)
+    }
+
+    pub fn set_recovery(&mut self, id: &str, recovery: ConfigRecovery) {
+        self.recoveries.insert(id.to_owned(), recovery);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecoveryState;
+    use crate::config::ResetRecovery;
+    use lact_schema::config::GpuConfig;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn repeated_resets() {
+        let settings = ResetRecovery::default();
+        let mut state = RecoveryState::default();
+        let start = Instant::now();
+
+        assert_eq!(None, state.record_reset("gpu", start, &settings));
+        // Same reset reported by a different source
+        assert_eq!(
+            None,
+            state.record_reset("gpu", start + Duration::from_secs(1), &settings)
+        );
+        assert_eq!(None, state.record_reset("other-gpu", start, &settings));
+        assert_eq!(
+            Some(2),
+            state.record_reset("gpu", start + Duration::from_secs(120), &settings)
+        );
+
+        // Resets outside of the window are not counted
+        assert_eq!(
+            None,
+            state.record_reset("gpu", start + Duration::from_secs(200), &settings)
+        );
+        assert_eq!(
+            None,
+            state.record_reset("gpu", start + Duration::from_secs(1000), &settings)
+        );
+    }
+
+    #[test]
+    fn known_good_time() {
+        let settings = ResetRecovery::default();
+        let known_good_time = Duration::from_secs(settings.known_good_time);
+        let mut state = RecoveryState::default();
+        let start = Instant::now();
+
+        let stable = GpuConfig::default();
+        let unstable = GpuConfig {
+            power_cap: Some(400.0),
+            ..Default::default()
+        };
+
+        assert!(!state.observe("gpu", &stable, start, known_good_time));
+        assert!(!state.observe("gpu", &stable, start + known_good_time / 2, known_good_time));
+        assert!(state.observe("gpu", &stable, start + known_good_time, known_good_time));
+
+        let unstable_start = start + known_good_time * 2;
+        assert!(!state.observe("gpu", &unstable, unstable_start, known_good_time));
+        state.record_reset("gpu", unstable_start + known_good_time / 2, &settings);
+        assert!(!state.observe(
+            "gpu",
+            &unstable,
+            unstable_start + known_good_time,
+            known_good_time
+        ));
+    }
+}
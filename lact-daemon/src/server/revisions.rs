@@ -0,0 +1,227 @@
+//! Bounded history of GPU settings, saved next to the config file
+use crate::config;
+use anyhow::Context;
+use lact_schema::{
+    config::GpuConfig,
+    revisions::{ConfigChangeSource, ConfigRevision},
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fs,
+    rc::Rc,
+    time::Instant,
+};
+use tracing::{debug, error, warn};
+
+const FILE_NAME: &str = "config_history.yaml";
+/// Maximum amount of revisions kept for every GPU in every profile
+const MAX_REVISIONS: usize = 50;
+
+#[derive(Clone, Default)]
+pub struct ConfigRevisions {
+    revisions: Rc<RefCell<VecDeque<ConfigRevision>>>,
+}
+
+impl ConfigRevisions {
+    pub fn load() -> Self {
+        let revisions = match load_revisions() {
+            Ok(revisions) => revisions,
+            Err(err) => {
+                warn!("could not load config history: {err:#}");
+                VecDeque::new()
+            }
+        };
+
+        Self {
+            revisions: Rc::new(RefCell::new(revisions)),
+        }
+    }
+
+    pub fn list(&self, gpu_id: Option<&str>) -> Vec<ConfigRevision> {
+        self.revisions
+            .borrow()
+            .iter()
+            .filter(|revision| gpu_id.is_none_or(|id| revision.gpu_id == id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, revision: u64) -> Option<ConfigRevision> {
+        self.revisions
+            .borrow()
+            .iter()
+            .find(|item| item.revision == revision)
+            .cloned()
+    }
+
+    /// Records new settings of a GPU in the given profile (`None` for the default profile).
+    /// The previous settings are recorded as well if there is no revision for the GPU in this profile yet,
+    /// so that the first change can also be rolled back.
+    pub fn record(
+        &self,
+        gpu_id: &str,
+        profile: Option<&str>,
+        previous_config: &GpuConfig,
+        config: &GpuConfig,
+        source: ConfigChangeSource,
+        config_last_saved: &Cell<Instant>,
+    ) {
+        {
+            let mut revisions = self.revisions.borrow_mut();
+            if !revisions
+                .iter()
+                .any(|revision| is_same_history(revision, gpu_id, profile))
+            {
+                push_revision(
+                    &mut revisions,
+                    gpu_id,
+                    profile,
+                    previous_config,
+                    ConfigChangeSource {
+                        request: "initial".to_owned(),
+                        ..Default::default()
+                    },
+                );
+            }
+            push_revision(&mut revisions, gpu_id, profile, config, source);
+        }
+
+        match self.save() {
+            Ok(()) => config_last_saved.set(Instant::now()),
+            Err(err) => error!("could not save config history: {err:#}"),
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = config::get_path(FILE_NAME);
+        debug!("saving config history to {path:?}");
+
+        #[cfg(not(test))]
+        {
+            let contents = serde_norway::to_string(&*self.revisions.borrow())?;
+            fs::write(path, contents).context("Could not write config history")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_same_history(revision: &ConfigRevision, gpu_id: &str, profile: Option<&str>) -> bool {
+    revision.gpu_id == gpu_id && revision.profile.as_deref() == profile
+}
+
+fn push_revision(
+    revisions: &mut VecDeque<ConfigRevision>,
+    gpu_id: &str,
+    profile: Option<&str>,
+    config: &GpuConfig,
+    source: ConfigChangeSource,
+) {
+    let revision = revisions.back().map_or(1, |last| last.revision + 1);
+    revisions.push_back(ConfigRevision {
+        revision,
+        timestamp: jiff::Timestamp::now().as_millisecond(),
+        gpu_id: gpu_id.to_owned(),
+        profile: profile.map(str::to_owned),
+        config: config.clone(),
+        source,
+    });
+
+    // Changes of one GPU should not evict the history of another one
+    let history_len = revisions
+        .iter()
+        .filter(|revision| is_same_history(revision, gpu_id, profile))
+        .count();
+    if history_len > MAX_REVISIONS
+        && let Some(oldest) = revisions
+            .iter()
+            .position(|revision| is_same_history(revision, gpu_id, profile))
+    {
+        revisions.remove(oldest);
+    }
+}
+
+fn load_revisions() -> anyhow::Result<VecDeque<ConfigRevision>> {
+    if cfg!(test) {
+        return Ok(VecDeque::new());
+    }
+
+    let path = config::get_path(FILE_NAME);
+    if !path.exists() {
+        return Ok(VecDeque::new());
+    }
+
+    let contents = fs::read_to_string(&path).context("Could not read file")?;
+    let revisions = serde_norway::from_str(&contents).context("Could not parse file")?;
+    debug!("loaded config history from {}", path.display());
+    Ok(revisions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigRevisions, MAX_REVISIONS};
+    use lact_schema::{config::GpuConfig, revisions::ConfigChangeSource};
+    use std::{cell::Cell, time::Instant};
+
+    #[test]
+    fn record_revisions() {
+        let revisions = ConfigRevisions::default();
+        let last_saved = Cell::new(Instant::now());
+        let source = ConfigChangeSource {
+            client: Some("lact".to_owned()),
+            uid: Some(1000),
+            request: "set_gpu_config".to_owned(),
+        };
+
+        let first = GpuConfig::default();
+        let second = GpuConfig {
+            power_cap: Some(250.0),
+            ..Default::default()
+        };
+        revisions.record("gpu-1", None, &first, &second, source.clone(), &last_saved);
+        revisions.record("gpu-2", None, &first, &second, source.clone(), &last_saved);
+        revisions.record("gpu-1", None, &second, &first, source.clone(), &last_saved);
+
+        let gpu_revisions = revisions.list(Some("gpu-1"));
+        assert_eq!(
+            vec![1, 2, 5],
+            gpu_revisions
+                .iter()
+                .map(|revision| revision.revision)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("initial", gpu_revisions[0].source.request);
+        assert_eq!(
+            Some(second),
+            revisions.get(2).map(|revision| revision.config)
+        );
+        assert_eq!(5, revisions.list(None).len());
+
+        // Every profile has its own initial revision
+        revisions.record(
+            "gpu-1",
+            Some("gaming"),
+            &first,
+            &first,
+            source.clone(),
+            &last_saved,
+        );
+        let profile_revisions: Vec<_> = revisions
+            .list(Some("gpu-1"))
+            .into_iter()
+            .filter(|revision| revision.profile.as_deref() == Some("gaming"))
+            .collect();
+        assert_eq!(2, profile_revisions.len());
+        assert_eq!("initial", profile_revisions[0].source.request);
+
+        for _ in 0..MAX_REVISIONS {
+            revisions.record("gpu-1", None, &first, &first, source.clone(), &last_saved);
+        }
+        assert_eq!(None, revisions.get(1));
+        // The history of other GPUs and profiles is kept
+        assert_eq!(MAX_REVISIONS + 2 + 2, revisions.list(None).len());
+        assert_eq!(2, revisions.list(Some("gpu-2")).len());
+        assert!(revisions.get(6).is_some());
+    }
+}
@@ -0,0 +1,435 @@
+//! Stability testing of clocks settings: steps a setting while running a workload and watching for driver errors
+use super::{access::user_groups, events, handler::Handler};
+use crate::system::{KernelLog, parse_kmsg_record};
+use anyhow::{Context, bail};
+use lact_schema::{
+    DaemonEvent,
+    stability::{StabilityTestOptions, StabilityTestState, StabilityTestStatus, StabilityTestStep},
+};
+use nix::{
+    sys::signal::{Signal, killpg},
+    unistd::{Gid, Pid, Uid, User, setgid, setgroups, setuid},
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    process::{Child, Command},
+    select,
+    sync::Notify,
+    time::sleep,
+};
+use tracing::{debug, error, info, warn};
+
+const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+/// Time on top of the step duration after which the tested settings get reverted by the config confirmation timer,
+/// in case the test itself gets stuck
+const STEP_CONFIRM_MARGIN: u64 = 30;
+
+#[derive(Clone, Default)]
+pub struct StabilityTests {
+    statuses: Rc<RefCell<HashMap<String, StabilityTestStatus>>>,
+    /// GPU id and stop notification of the running test. Only one test can run at a time,
+    /// as the settings of every step are a pending config change.
+    active: Rc<RefCell<Option<(String, Rc<Notify>)>>>,
+}
+
+impl StabilityTests {
+    pub fn start(
+        &self,
+        handler: Handler,
+        id: String,
+        driver: String,
+        options: StabilityTestOptions,
+        workload_uid: Option<u32>,
+    ) -> anyhow::Result<()> {
+        if options.workload_command.trim().is_empty() {
+            bail!("No workload command specified");
+        }
+        let workload_user = workload_uid.map(WorkloadUser::lookup).transpose()?;
+        if options.steps == 0 || options.step_duration == 0 {
+            bail!("The test needs at least one step with a non-zero duration");
+        }
+
+        let mut active = self.active.borrow_mut();
+        if let Some((active_id, _)) = active.as_ref() {
+            bail!("A stability test is already running on GPU {active_id}");
+        }
+
+        info!(
+            "starting stability test of {:?} with workload '{}'",
+            options.target, options.workload_command
+        );
+
+        self.statuses.borrow_mut().insert(
+            id.clone(),
+            StabilityTestStatus {
+                options: options.clone(),
+                state: StabilityTestState::Running,
+                current_value: None,
+                last_stable_value: None,
+                steps: vec![],
+            },
+        );
+
+        let stop = Rc::new(Notify::new());
+        *active = Some((id.clone(), stop.clone()));
+
+        let tests = self.clone();
+        tokio::task::spawn_local(async move {
+            let state = tests
+                .run(
+                    &handler,
+                    &id,
+                    &driver,
+                    &options,
+                    workload_user.as_ref(),
+                    &stop,
+                )
+                .await
+                .unwrap_or_else(|err| {
+                    error!("stability test failed: {err:#}");
+                    StabilityTestState::Error
+                });
+
+            let last_stable_value = tests.update(&id, |status| {
+                status.state = state;
+                status.current_value = None;
+                status.last_stable_value
+            });
+            *tests.active.borrow_mut() = None;
+
+            info!("stability test finished, last stable value: {last_stable_value:?}");
+            events::emit(DaemonEvent::StabilityTestFinished {
+                id,
+                state,
+                last_stable_value,
+            });
+        });
+
+        Ok(())
+    }
+
+    /// GPU id of the running test
+    pub fn active_id(&self) -> Option<String> {
+        self.active.borrow().as_ref().map(|(id, _)| id.clone())
+    }
+
+    pub fn status(&self, id: &str) -> Option<StabilityTestStatus> {
+        self.statuses.borrow().get(id).cloned()
+    }
+
+    pub fn stop(&self, id: &str) -> anyhow::Result<()> {
+        match self.active.borrow().as_ref() {
+            Some((active_id, stop)) if active_id == id => {
+                stop.notify_one();
+                Ok(())
+            }
+            _ => bail!("No stability test is running on this GPU"),
+        }
+    }
+
+    fn update<T>(&self, id: &str, f: impl FnOnce(&mut StabilityTestStatus) -> T) -> T {
+        let mut statuses = self.statuses.borrow_mut();
+        let status = statuses
+            .get_mut(id)
+            .expect("Status is inserted before the test starts");
+        f(status)
+    }
+
+    async fn run(
+        &self,
+        handler: &Handler,
+        id: &str,
+        driver: &str,
+        options: &StabilityTestOptions,
+        workload_user: Option<&WorkloadUser>,
+        stop: &Notify,
+    ) -> anyhow::Result<StabilityTestState> {
+        let mut driver_errors = DriverErrors::open(driver);
+
+        for value in options.values() {
+            self.update(id, |status| status.current_value = Some(value));
+            info!("stability test: testing value {value}");
+
+            let apply_timer = options.step_duration + STEP_CONFIRM_MARGIN;
+            let apply_result = handler
+                .edit_gpu_config_with_timer(
+                    id.to_owned(),
+                    |config| {
+                        options
+                            .target
+                            .apply(&mut config.clocks_configuration, value);
+                    },
+                    Some(apply_timer),
+                )
+                .await;
+
+            let step = match apply_result {
+                Ok(_) => {
+                    let result = run_step(
+                        handler,
+                        id,
+                        options,
+                        workload_user,
+                        value,
+                        &mut driver_errors,
+                        stop,
+                    )
+                    .await;
+                    // Reverting the pending change brings back the settings from before the test
+                    handler.revert_pending_config(id).await;
+
+                    match result? {
+                        Some(step) => step,
+                        None => return Ok(StabilityTestState::Stopped),
+                    }
+                }
+                // Settings that can't be applied are not stable either
+                Err(err) => StabilityTestStep {
+                    value,
+                    failure: Some(format!("Could not apply settings: {err:#}")),
+                    throttling: vec![],
+                },
+            };
+
+            let failed = step.failure.is_some();
+            if let Some(failure) = &step.failure {
+                warn!("stability test: value {value} failed: {failure}");
+            }
+
+            self.update(id, |status| {
+                if !failed {
+                    status.last_stable_value = Some(value);
+                }
+                status.steps.push(step);
+            });
+
+            if failed {
+                break;
+            }
+        }
+
+        Ok(StabilityTestState::Finished)
+    }
+}
+
+/// Runs the workload for the duration of a step. Returns `None` if the test was stopped.
+async fn run_step(
+    handler: &Handler,
+    id: &str,
+    options: &StabilityTestOptions,
+    workload_user: Option<&WorkloadUser>,
+    value: i32,
+    driver_errors: &mut DriverErrors<'_>,
+    stop: &Notify,
+) -> anyhow::Result<Option<StabilityTestStep>> {
+    let deadline = Instant::now() + Duration::from_secs(options.step_duration);
+    let mut workload = Workload::spawn(&options.workload_command, workload_user)?;
+    let mut throttling = BTreeSet::new();
+
+    let failure = loop {
+        select! {
+            result = workload.child.wait() => {
+                let status = result.context("Could not wait for the workload")?;
+                if !status.success() {
+                    break Some(format!("Workload exited with {status}"));
+                }
+                debug!("workload finished before the end of the step, restarting it");
+                workload = Workload::spawn(&options.workload_command, workload_user)?;
+            }
+            () = sleep(MONITOR_INTERVAL) => {
+                if let Some(message) = driver_errors.read_next() {
+                    break Some(format!("Driver error: {message}"));
+                }
+
+                match handler.get_gpu_stats(id).await {
+                    Ok(stats) => {
+                        throttling.extend(stats.throttle_info.into_iter().flatten().map(|(reason, _)| reason));
+                    }
+                    Err(err) => break Some(format!("Could not read GPU stats: {err:#}")),
+                }
+
+                if Instant::now() >= deadline {
+                    break None;
+                }
+            }
+            () = stop.notified() => return Ok(None),
+        }
+    };
+
+    Ok(Some(StabilityTestStep {
+        value,
+        failure,
+        throttling: throttling.into_iter().collect(),
+    }))
+}
+
+/// Workload command running in its own process group, which is terminated when dropped
+struct Workload {
+    child: Child,
+}
+
+impl Workload {
+    /// Runs the command as the given user, or as the daemon's user if not set
+    fn spawn(command: &str, user: Option<&WorkloadUser>) -> anyhow::Result<Self> {
+        let mut workload = Command::new("sh");
+        workload
+            .arg("-c")
+            .arg(command)
+            .process_group(0)
+            .kill_on_drop(true);
+
+        if let Some(user) = user {
+            workload
+                .env("HOME", &user.home)
+                .env("USER", &user.name)
+                .env("LOGNAME", &user.name);
+
+            let (uid, gid, groups) = (user.uid, user.gid, user.groups.clone());
+            // SAFETY: only makes syscalls, which are safe to use between fork and exec
+            unsafe {
+                workload.pre_exec(move || {
+                    setgroups(&groups)?;
+                    setgid(gid)?;
+                    setuid(uid)?;
+                    Ok(())
+                });
+            }
+        }
+
+        let child = workload.spawn().context("Could not start workload")?;
+        Ok(Self { child })
+    }
+}
+
+/// User that a workload runs as. The user info is resolved up front, as it can't be looked up after forking.
+struct WorkloadUser {
+    uid: Uid,
+    gid: Gid,
+    groups: Vec<Gid>,
+    name: String,
+    home: PathBuf,
+}
+
+impl WorkloadUser {
+    fn lookup(uid: u32) -> anyhow::Result<Self> {
+        let user = User::from_uid(Uid::from_raw(uid))
+            .context("Could not get user info")?
+            .with_context(|| format!("User {uid} not found"))?;
+        let groups = user_groups(&user);
+
+        Ok(Self {
+            uid: user.uid,
+            gid: user.gid,
+            groups,
+            name: user.name,
+            home: user.dir,
+        })
+    }
+}
+
+impl Drop for Workload {
+    fn drop(&mut self) {
+        if let Some(pid) = self.child.id().and_then(|pid| i32::try_from(pid).ok())
+            && let Err(err) = killpg(Pid::from_raw(pid), Signal::SIGTERM)
+        {
+            debug!("could not stop workload: {err}");
+        }
+    }
+}
+
+/// Watches the kernel log for errors of the GPU driver
+struct DriverErrors<'a> {
+    kernel_log: Option<KernelLog>,
+    driver: &'a str,
+}
+
+impl<'a> DriverErrors<'a> {
+    fn open(driver: &'a str) -> Self {
+        let kernel_log = KernelLog::open(true)
+            .inspect_err(|err| {
+                warn!("could not open kernel log, driver errors will not be detected: {err:#}");
+            })
+            .ok();
+        Self { kernel_log, driver }
+    }
+
+    /// Returns the first error logged since the last call
+    fn read_next(&mut self) -> Option<String> {
+        let kernel_log = self.kernel_log.as_mut()?;
+        read_driver_errors(kernel_log, self.driver)
+            .into_iter()
+            .next()
+    }
+}
+
+/// Reads the new driver errors from the kernel log
+fn read_driver_errors(kernel_log: &mut KernelLog, driver: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    let result = kernel_log.read_records(|record| {
+        if let Some(message) = driver_error_message(record, driver) {
+            errors.push(message.to_owned());
+        }
+    });
+    if let Err(err) = result {
+        warn!("could not read kernel log: {err}");
+    }
+    errors
+}
+
+/// Returns the message of a `/dev/kmsg` record if it's an error or a reset reported by the GPU driver
+fn driver_error_message<'a>(record: &'a str, driver: &str) -> Option<&'a str> {
+    let (level, message) = parse_kmsg_record(record)?;
+
+    let from_driver = message.contains(driver) || (driver == "nvidia" && message.contains("NVRM"));
+    let is_error =
+        level <= 3 || message.to_lowercase().contains("reset") || message.contains("Xid");
+
+    (from_driver && is_error).then_some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::driver_error_message;
+
+    #[test]
+    fn detect_driver_errors() {
+        let records = [
+            (
+                "3,1052,2384813431,-;amdgpu 0000:03:00.0: amdgpu: ring gfx_0.0.0 timeout, signaled seq=123, emitted seq=125\n",
+                Some(
+                    "amdgpu 0000:03:00.0: amdgpu: ring gfx_0.0.0 timeout, signaled seq=123, emitted seq=125",
+                ),
+            ),
+            (
+                "6,1053,2384813500,-;amdgpu 0000:03:00.0: amdgpu: GPU reset begin!\n",
+                Some("amdgpu 0000:03:00.0: amdgpu: GPU reset begin!"),
+            ),
+            (
+                "6,1054,2384813600,-;amdgpu 0000:03:00.0: amdgpu: SMU is initialized successfully!\n",
+                None,
+            ),
+            (
+                "3,1055,2384813700,-;usb 1-2: device descriptor read/64, error -71\n",
+                None,
+            ),
+        ];
+
+        for (record, expected) in records {
+            assert_eq!(expected, driver_error_message(record, "amdgpu"), "{record}");
+        }
+
+        assert_eq!(
+            Some("NVRM: Xid (PCI:0000:01:00): 79, pid=1234, GPU has fallen off the bus."),
+            driver_error_message(
+                "4,2000,1000,-;NVRM: Xid (PCI:0000:01:00): 79, pid=1234, GPU has fallen off the bus.\n",
+                "nvidia"
+            )
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use super::handler::Handler;
+use lact_schema::DeviceStats;
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+use tokio::{sync::watch, time::sleep};
+use tracing::{debug, warn};
+
+pub const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub type StatsSample = Rc<DeviceStats>;
+pub type StatsReceiver = watch::Receiver<Option<StatsSample>>;
+
+/// Shared stats sampling tasks, one per GPU.
+///
+/// Every subscriber of a GPU receives samples from the same task,
+/// so multiple clients watching stats don't result in the hardware being queried multiple times.
+#[derive(Clone, Default)]
+pub struct StatsSamplers {
+    samplers: Rc<RefCell<HashMap<String, Sampler>>>,
+}
+
+struct Sampler {
+    tx: Rc<watch::Sender<Option<StatsSample>>>,
+    intervals: Rc<RefCell<Vec<Duration>>>,
+}
+
+/// Receiver of a stats subscription. The requested interval stops affecting the sampler when this is dropped.
+pub struct StatsSubscription {
+    pub rx: StatsReceiver,
+    intervals: Rc<RefCell<Vec<Duration>>>,
+    interval: Duration,
+}
+
+impl Drop for StatsSubscription {
+    fn drop(&mut self) {
+        let mut intervals = self.intervals.borrow_mut();
+        if let Some(index) = intervals
+            .iter()
+            .position(|interval| *interval == self.interval)
+        {
+            intervals.swap_remove(index);
+        }
+    }
+}
+
+impl StatsSamplers {
+    /// Subscribes to the stats of the given GPU, starting a sampling task if there isn't one running already.
+    ///
+    /// The sampler runs at the shortest interval requested by its subscribers, and stops once all of them are gone.
+    pub fn subscribe(&self, handler: &Handler, id: &str, interval: Duration) -> StatsSubscription {
+        let interval = interval.max(MIN_SAMPLE_INTERVAL);
+        let mut samplers = self.samplers.borrow_mut();
+
+        if let Some(sampler) = samplers.get(id) {
+            sampler.intervals.borrow_mut().push(interval);
+            return StatsSubscription {
+                rx: sampler.tx.subscribe(),
+                intervals: sampler.intervals.clone(),
+                interval,
+            };
+        }
+
+        let (tx, rx) = watch::channel(None);
+        let tx = Rc::new(tx);
+        let intervals = Rc::new(RefCell::new(vec![interval]));
+
+        samplers.insert(
+            id.to_owned(),
+            Sampler {
+                tx: tx.clone(),
+                intervals: intervals.clone(),
+            },
+        );
+
+        tokio::task::spawn_local(run_sampler(
+            handler.clone(),
+            self.clone(),
+            id.to_owned(),
+            tx,
+            intervals.clone(),
+        ));
+
+        StatsSubscription {
+            rx,
+            intervals,
+            interval,
+        }
+    }
+}
+
+async fn run_sampler(
+    handler: Handler,
+    samplers: StatsSamplers,
+    id: String,
+    tx: Rc<watch::Sender<Option<StatsSample>>>,
+    intervals: Rc<RefCell<Vec<Duration>>>,
+) {
+    debug!("starting stats sampler for {id}");
+
+    while tx.receiver_count() > 0 {
+        match handler.get_gpu_stats(&id).await {
+            Ok(stats) => {
+                tx.send_replace(Some(Rc::new(stats)));
+            }
+            Err(err) => {
+                warn!("could not sample stats for {id}: {err:#}");
+                break;
+            }
+        }
+
+        let interval = intervals
+            .borrow()
+            .iter()
+            .min()
+            .copied()
+            .unwrap_or(MIN_SAMPLE_INTERVAL);
+        sleep(interval).await;
+    }
+
+    // Dropping the sender closes the channel for any remaining subscribers
+    samplers.samplers.borrow_mut().remove(&id);
+    debug!("stopped stats sampler for {id}");
+}
@@ -0,0 +1,230 @@
+use super::{ClientContext, TcpAuthState, handler::Handler};
+use crate::config::TcpTls;
+use anyhow::{Context, bail};
+use indexmap::IndexMap;
+use lact_schema::request::RequestClass;
+use ring::digest::{SHA256, digest};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+};
+use tracing::{info, warn};
+
+/// How long an address is blocked from authenticating after a failed attempt, to slow down guessing of tokens.
+/// Doubled with every consecutive failure, up to `MAX_FAILED_AUTH_DELAY`.
+const FAILED_AUTH_DELAY: Duration = Duration::from_secs(1);
+const MAX_FAILED_AUTH_DELAY: Duration = Duration::from_secs(300);
+
+/// Shared between all connections, so that opening more connections doesn't allow more attempts
+static AUTH_LIMITER: LazyLock<Mutex<AuthLimiter>> = LazyLock::new(Mutex::default);
+
+pub fn load_tls_acceptor(tls: &TcpTls) -> anyhow::Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(&tls.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| {
+            format!(
+                "Could not read TLS certificate from {}",
+                tls.cert_path.display()
+            )
+        })?;
+    let key = PrivateKeyDer::from_pem_file(&tls.key_path).with_context(|| {
+        format!(
+            "Could not read TLS private key from {}",
+            tls.key_path.display()
+        )
+    })?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Could not set up TLS protocol versions")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or private key")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+pub async fn authenticate(
+    handler: &Handler,
+    ctx: &mut ClientContext,
+    token: &str,
+) -> anyhow::Result<()> {
+    let Some(auth_state) = &mut ctx.tcp else {
+        bail!("Authentication is only used for TCP connections");
+    };
+
+    let peer = ctx.tcp_peer.context("Missing TCP client address")?;
+
+    let config = handler.config.read().await;
+    if config.daemon.tcp_tokens.is_empty() {
+        bail!("No TCP tokens are configured on the daemon");
+    }
+
+    // Checked without awaiting in between, so that parallel attempts see each other's failures
+    let client = {
+        let mut limiter = AUTH_LIMITER.lock().unwrap();
+        let now = Instant::now();
+        if let Some(remaining) = limiter.remaining_block(peer, now) {
+            bail!(
+                "Too many failed authentication attempts, try again in {} seconds",
+                remaining.as_secs() + 1
+            );
+        }
+
+        let client = find_token(&config.daemon.tcp_tokens, token).map(|name| {
+            let access = config
+                .daemon
+                .access
+                .tokens
+                .get(name)
+                .copied()
+                .unwrap_or(RequestClass::Admin);
+            (name.to_owned(), access)
+        });
+        match client {
+            Some(_) => limiter.record_success(peer),
+            None => limiter.record_failure(peer, now),
+        }
+        client
+    };
+    drop(config);
+
+    match client {
+        Some((name, access)) => {
+            info!("TCP client authenticated as '{name}' with {access} access");
+            *auth_state = TcpAuthState::Authenticated;
+            ctx.access = Some(access);
+            Ok(())
+        }
+        None => {
+            warn!("TCP client {peer} provided an invalid token");
+            bail!("Invalid token");
+        }
+    }
+}
+
+/// Consecutive failed authentication attempts, per client address
+#[derive(Default)]
+struct AuthLimiter {
+    failures: HashMap<IpAddr, FailedAuth>,
+}
+
+struct FailedAuth {
+    count: u32,
+    blocked_until: Instant,
+}
+
+impl AuthLimiter {
+    fn remaining_block(&self, peer: IpAddr, now: Instant) -> Option<Duration> {
+        self.failures
+            .get(&peer)
+            .map(|failure| failure.blocked_until.saturating_duration_since(now))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    fn record_failure(&mut self, peer: IpAddr, now: Instant) {
+        // Addresses that have not failed for a while start over
+        self.failures
+            .retain(|_, failure| now < failure.blocked_until + MAX_FAILED_AUTH_DELAY);
+
+        let failure = self.failures.entry(peer).or_insert(FailedAuth {
+            count: 0,
+            blocked_until: now,
+        });
+        let delay = FAILED_AUTH_DELAY
+            .saturating_mul(2_u32.saturating_pow(failure.count))
+            .min(MAX_FAILED_AUTH_DELAY);
+        failure.count = failure.count.saturating_add(1);
+        failure.blocked_until = now + delay;
+    }
+
+    fn record_success(&mut self, peer: IpAddr) {
+        self.failures.remove(&peer);
+    }
+}
+
+/// Returns the name of the client the token belongs to
+fn find_token<'a>(tokens: &'a IndexMap<String, String>, provided: &str) -> Option<&'a str> {
+    tokens
+        .iter()
+        .filter(|(_, token)| !token.is_empty())
+        .find(|(_, token)| tokens_match(token, provided))
+        .map(|(name, _)| name.as_str())
+}
+
+/// Compares fixed-length digests of the tokens in constant time,
+/// so that the time taken does not depend on the contents or the length of either token
+fn tokens_match(token: &str, provided: &str) -> bool {
+    let token = digest(&SHA256, token.as_bytes());
+    let provided = digest(&SHA256, provided.as_bytes());
+    token
+        .as_ref()
+        .iter()
+        .zip(provided.as_ref())
+        .fold(0, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthLimiter, FAILED_AUTH_DELAY, MAX_FAILED_AUTH_DELAY, find_token};
+    use indexmap::IndexMap;
+    use std::{net::IpAddr, time::Instant};
+
+    #[test]
+    fn find_matching_token() {
+        let tokens = IndexMap::from([
+            ("monitor".to_owned(), "abc123".to_owned()),
+            ("lab-admin".to_owned(), "def456".to_owned()),
+        ]);
+
+        assert_eq!(Some("lab-admin"), find_token(&tokens, "def456"));
+        assert_eq!(Some("monitor"), find_token(&tokens, "abc123"));
+        assert_eq!(None, find_token(&tokens, "abc12"));
+        assert_eq!(None, find_token(&tokens, ""));
+    }
+
+    #[test]
+    fn failed_auth_blocks_peer() {
+        let peer: IpAddr = "192.168.1.20".parse().unwrap();
+        let other_peer: IpAddr = "192.168.1.21".parse().unwrap();
+        let now = Instant::now();
+        let mut limiter = AuthLimiter::default();
+
+        assert_eq!(None, limiter.remaining_block(peer, now));
+
+        limiter.record_failure(peer, now);
+        assert_eq!(Some(FAILED_AUTH_DELAY), limiter.remaining_block(peer, now));
+        assert_eq!(None, limiter.remaining_block(other_peer, now));
+        assert_eq!(None, limiter.remaining_block(peer, now + FAILED_AUTH_DELAY));
+
+        // Consecutive failures double the delay
+        let now = now + FAILED_AUTH_DELAY;
+        limiter.record_failure(peer, now);
+        assert_eq!(
+            Some(FAILED_AUTH_DELAY * 2),
+            limiter.remaining_block(peer, now)
+        );
+
+        for _ in 0..20 {
+            limiter.record_failure(peer, now);
+        }
+        assert_eq!(
+            Some(MAX_FAILED_AUTH_DELAY),
+            limiter.remaining_block(peer, now)
+        );
+
+        limiter.record_success(peer);
+        assert_eq!(None, limiter.remaining_block(peer, now));
+    }
+}
@@ -5,6 +5,7 @@ use nix::{
 };
 use std::{
     env, fs,
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -100,8 +101,23 @@ pub async fn set_permissions(
         run_command("chown", &[&owner_arg, path])
             .await
             .context("Could not set socket permissions")?;
+
+        if daemon_config.access.has_local_rules() {
+            run_command("chmod", &["666", path])
+                .await
+                .context("Could not set socket permissions")?;
+        }
     } else {
         chown(socket_path, user, Some(group)).context("Could not set socket permissions")?;
+
+        if daemon_config.access.has_local_rules() {
+            fs::set_permissions(socket_path, fs::Permissions::from_mode(0o666))
+                .context("Could not set socket permissions")?;
+        }
+    }
+
+    if daemon_config.access.has_local_rules() {
+        info!("access policy has rules for local users, socket is accessible to everyone");
     }
 
     Ok(())
@@ -13,10 +13,13 @@ use nix::sys::{
 use os_release::OsRelease;
 use std::{
     env,
-    fs::{self, File, Permissions},
-    io::{self, Write},
+    fs::{self, File, OpenOptions, Permissions},
+    io::{self, Read, Seek, SeekFrom, Write},
     iter,
-    os::{fd::AsRawFd, unix::prelude::PermissionsExt},
+    os::{
+        fd::AsRawFd,
+        unix::{fs::OpenOptionsExt, prelude::PermissionsExt},
+    },
     path::{Path, PathBuf},
     process::{self, Output},
     sync::{
@@ -24,7 +27,10 @@ use std::{
         atomic::{AtomicBool, Ordering},
     },
 };
-use tokio::{process::Command, sync::Notify};
+use tokio::{
+    process::Command,
+    sync::{Notify, mpsc},
+};
 use tracing::{debug, error, info};
 
 static OC_TOGGLED: AtomicBool = AtomicBool::new(false);
@@ -33,6 +39,8 @@ const PP_OVERDRIVE_MASK: u64 = 0x4000;
 pub const PP_FEATURE_MASK_PATH: &str = "/sys/module/amdgpu/parameters/ppfeaturemask";
 pub const BASE_MODULE_CONF_PATH: &str = "/etc/modprobe.d/99-amdgpu-overdrive.conf";
 pub const DAEMON_VERSION: &str = env!("CARGO_PKG_VERSION");
+const KMSG_PATH: &str = "/dev/kmsg";
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
 
 pub static IS_FLATBOX: LazyLock<bool> =
     LazyLock::new(|| env::var("FLATBOX_ENV").as_deref() == Ok("1"));
@@ -270,7 +278,12 @@ pub async fn run_command(exec: &str, args: &[&str]) -> anyhow::Result<Output> {
     }
 }
 
-pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()> {
+/// Listens to drm uevents. `notify` is triggered on every drm event,
+/// and the PCI slot names of GPUs that reported a reset are sent to `reset_tx`.
+pub(crate) fn listen_netlink_kernel_event(
+    notify: &Notify,
+    reset_tx: &mpsc::UnboundedSender<String>,
+) -> anyhow::Result<()> {
     let socket = socket(
         AddressFamily::Netlink,
         SockType::Raw,
@@ -298,17 +311,12 @@ pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()>
         recv(socket.as_raw_fd(), &mut buf, MsgFlags::empty())
             .context("Could not read netlink message")?;
 
+        let mut lines = Vec::new();
         for raw_line in buf.split(|c| *c == b'\0') {
             match std::str::from_utf8(raw_line) {
                 Ok(line) => {
-                    if line.is_empty() {
-                        continue;
-                    }
-
-                    if let Some(subsystem) = line.strip_prefix("SUBSYSTEM=")
-                        && subsystem == "drm"
-                    {
-                        notify.notify_one();
+                    if !line.is_empty() {
+                        lines.push(line);
                     }
                 }
                 Err(_) => {
@@ -319,12 +327,113 @@ pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()>
                 }
             }
         }
+
+        if lines.contains(&"SUBSYSTEM=drm") {
+            notify.notify_one();
+
+            if let Some(slot) = wedged_gpu_slot(&lines) {
+                let _ = reset_tx.send(slot.to_owned());
+            }
+        }
+    }
+}
+
+/// Drivers report a reset (or a GPU that needs one) with a `WEDGED=<recovery method>` drm uevent
+fn wedged_gpu_slot<'a>(lines: &[&'a str]) -> Option<&'a str> {
+    if !lines.iter().any(|line| line.starts_with("WEDGED=")) {
+        return None;
+    }
+
+    let devpath = lines
+        .iter()
+        .find_map(|line| line.strip_prefix("DEVPATH="))?;
+    let (device_path, _) = devpath.split_once("/drm/")?;
+    device_path.rsplit('/').next()
+}
+
+/// Follows the kernel log and sends the PCI slot names of GPUs that the driver started resetting to `reset_tx`
+pub(crate) fn listen_kernel_log_resets(
+    reset_tx: &mpsc::UnboundedSender<String>,
+) -> anyhow::Result<()> {
+    let mut kernel_log = KernelLog::open(false)?;
+    loop {
+        kernel_log
+            .read_records(|record| {
+                if let Some(slot) = kernel_log_reset_slot(record) {
+                    let _ = reset_tx.send(slot.to_owned());
+                }
+            })
+            .context("Could not read kernel log")?;
+    }
+}
+
+/// Reader of the kernel log records that are logged after it was opened
+pub(crate) struct KernelLog {
+    file: File,
+}
+
+impl KernelLog {
+    /// A non-blocking reader only reads the records that are already available,
+    /// a blocking one keeps waiting for new records
+    pub(crate) fn open(nonblocking: bool) -> anyhow::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        if nonblocking {
+            options.custom_flags(nix::libc::O_NONBLOCK);
+        }
+
+        let mut file = options
+            .open(KMSG_PATH)
+            .with_context(|| format!("Could not open {KMSG_PATH}"))?;
+        // Only messages logged after this point are relevant
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self { file })
+    }
+
+    /// Passes every new record to `f`, until there are no more records available
+    pub(crate) fn read_records(&mut self, mut f: impl FnMut(&str)) -> io::Result<()> {
+        let mut buf = vec![0; 8192];
+
+        // Every read returns a single record
+        loop {
+            match self.file.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(len) => f(&String::from_utf8_lossy(&buf[..len])),
+                // Records were overwritten before they could be read
+                Err(err) if err.raw_os_error() == Some(nix::libc::EPIPE) => (),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
     }
 }
 
+pub(crate) fn boot_id() -> anyhow::Result<String> {
+    let boot_id = fs::read_to_string(BOOT_ID_PATH).context("Could not read boot id")?;
+    Ok(boot_id.trim().to_owned())
+}
+
+/// Parses a `/dev/kmsg` record into its log level and the first line of the message
+pub(crate) fn parse_kmsg_record(record: &str) -> Option<(u32, &str)> {
+    let (header, message) = record.split_once(';')?;
+    let level = header.split(',').next()?.parse::<u32>().ok()? & 7;
+    let message = message.lines().next()?;
+    Some((level, message))
+}
+
+/// Matches amdgpu reset messages such as `amdgpu 0000:03:00.0: amdgpu: GPU reset begin!`
+fn kernel_log_reset_slot(record: &str) -> Option<&str> {
+    let (_, message) = parse_kmsg_record(record)?;
+    let (device, text) = message.split_once(": ")?;
+    let slot = device.strip_prefix("amdgpu ")?;
+    text.contains("GPU reset begin").then_some(slot)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::detect_amdgpu_configurator;
+    use super::{
+        detect_amdgpu_configurator, kernel_log_reset_slot, parse_kmsg_record, wedged_gpu_slot,
+    };
     use lact_schema::{AmdgpuParamsConfigurator, InitramfsType};
     use os_release::OsRelease;
 
@@ -368,4 +477,47 @@ UBUNTU_CODENAME=jammy
             detect_amdgpu_configurator(&os_release).await.unwrap()
         );
     }
+
+    #[test]
+    fn wedged_uevent() {
+        let lines = [
+            "change@/devices/pci0000:00/0000:00:03.1/0000:09:00.0/0000:0a:00.0/0000:0b:00.0/drm/card1",
+            "ACTION=change",
+            "DEVPATH=/devices/pci0000:00/0000:00:03.1/0000:09:00.0/0000:0a:00.0/0000:0b:00.0/drm/card1",
+            "SUBSYSTEM=drm",
+            "WEDGED=none",
+            "DEVNAME=dri/card1",
+        ];
+        assert_eq!(Some("0000:0b:00.0"), wedged_gpu_slot(&lines));
+
+        let hotplug = [
+            "ACTION=change",
+            "DEVPATH=/devices/pci0000:00/0000:00:03.1/0000:0b:00.0/drm/card1",
+            "SUBSYSTEM=drm",
+            "HOTPLUG=1",
+        ];
+        assert_eq!(None, wedged_gpu_slot(&hotplug));
+    }
+
+    #[test]
+    fn kernel_log_reset() {
+        assert_eq!(
+            Some("0000:03:00.0"),
+            kernel_log_reset_slot(
+                "6,1053,2384813500,-;amdgpu 0000:03:00.0: amdgpu: GPU reset begin!\n"
+            )
+        );
+        assert_eq!(
+            None,
+            kernel_log_reset_slot(
+                "3,1052,2384813431,-;amdgpu 0000:03:00.0: amdgpu: ring gfx_0.0.0 timeout, signaled seq=123, emitted seq=125\n"
+            )
+        );
+        assert_eq!(
+            Some((3, "usb 1-2: device descriptor read/64, error -71")),
+            parse_kmsg_record(
+                "3,1055,2384813700,-;usb 1-2: device descriptor read/64, error -71\n"
+            )
+        );
+    }
 }
@@ -51,6 +51,7 @@ throttling = Throttling
 auto-page = Automatic
 curve-page = Curve
 static-page = Static
+pid-page = Target Temperature
 target-temp = Target temperature (°C)
 acoustic-limit = Acoustic Limit (RPM)
 acoustic-target = Acoustic Target (RPM)
@@ -319,6 +320,38 @@ profile-rule-gamemode-tab = Gamemode is active
 profile-rule-process-name = Process Name:
 profile-rule-args-contain = Arguments Contain:
 profile-rule-specific-process = With a specific process:
+profile-rule-match-mode = Match Using:
+profile-rule-match-exact = Exact name
+profile-rule-match-glob = Wildcards (* and ?)
+profile-rule-match-regex = Regular expression
+profile-rule-schedule-tab = Time of day
+profile-rule-schedule-start = Start (HH:MM):
+profile-rule-schedule-end = End (HH:MM):
+profile-rule-schedule-weekdays = Only on these days (every day if none are selected):
+profile-rule-power-source-tab = Power source
+profile-rule-on-battery = Running on battery:
+profile-rule-battery-below = Battery below (%):
+profile-rule-battery-above = Battery above (%):
+profile-rule-gpu-stat-tab = GPU load or temperature
+profile-rule-gpu-stat = Value:
+profile-rule-gpu-stat-sensor = Temperature sensor:
+profile-rule-gpu-stat-above = Above:
+profile-rule-gpu-stat-below = Below:
+profile-rule-gpu-stat-duration = For at least (seconds):
+profile-rule-gpu-stat-busy = GPU usage (%)
+profile-rule-gpu-stat-temperature = Temperature (°C)
+profile-rule-gpu-stat-power = Power draw (W)
+profile-rule-gpu-stat-vram = VRAM used (MiB)
+profile-rule-focused-window-tab = A window is focused
+profile-rule-window-app-id = App ID or WM Class:
+profile-rule-focused-window-info = Requires the focus agent to run in the desktop session (`lact cli focus-agent`)
+weekday-monday = Mon
+weekday-tuesday = Tue
+weekday-wednesday = Wed
+weekday-thursday = Thu
+weekday-friday = Fri
+weekday-saturday = Sat
+weekday-sunday = Sun
 
 theme = Theme
 theme-auto = Automatic
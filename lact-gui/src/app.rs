@@ -44,11 +44,12 @@ use gtk::{
     glib::{self, ControlFlow, clone},
 };
 use i18n_embed_fl::fl;
-use lact_client::{ConnectionStatusMsg, DaemonClient};
+use lact_client::{ConnectionStatusMsg, DaemonClient, TcpConnectOptions, TcpTlsOptions};
 use lact_schema::{
     DeviceApiInfo, DeviceFlag, DeviceListEntry, DeviceStats, DeviceType, SystemInfo,
     args::GuiArgs,
     config::{GpuConfig, Profile},
+    export::ProfileExport,
     request::{ConfirmCommand, ProfileBase, SetClocksCommand},
 };
 use msg::AppMsg;
@@ -87,6 +88,8 @@ use utils::styles;
 pub(crate) static APP_BROKER: MessageBroker<AppMsg> = MessageBroker::new();
 
 const PROCESS_POLL_INTERVAL_MS: u64 = 1500;
+/// Matches the longest period that can be shown in the graphs window
+const STATS_HISTORY_PERIOD_MS: i64 = 3600 * 1000;
 const NVIDIA_RECOMMENDED_MIN_VERSION: u32 = 560;
 const CONTENT_MAXIMUM_WIDTH: i32 = 1200;
 const DEFAULT_WINDOW_WIDTH: i32 = 1100;
@@ -381,7 +384,16 @@ impl AsyncComponent for AppModel {
         let (daemon_client, daemon_config_shown) = match args.tcp_address {
             Some(remote_addr) => {
                 info!("establishing connection to {remote_addr}");
-                match DaemonClient::connect_tcp(&remote_addr).await {
+                let tls = (args.tcp_tls || args.tcp_tls_ca.is_some()).then(|| TcpTlsOptions {
+                    ca_path: args.tcp_tls_ca,
+                    server_name: None,
+                });
+                let options = TcpConnectOptions {
+                    token: args.tcp_token,
+                    tls,
+                };
+
+                match DaemonClient::connect_tcp(&remote_addr, options).await {
                     Ok(conn) => (conn, true),
                     Err(err) => {
                         sender.input(AppMsg::Error(
@@ -750,34 +762,30 @@ impl AppModel {
                 });
             }
             AppMsg::ExportProfile(name) => {
-                if let Some(profile) = self.daemon_client.get_profile(name.clone()).await? {
-                    let settings = SaveDialogSettings {
-                        create_folders: true,
-                        is_modal: true,
-                        ..Default::default()
-                    };
-                    let diag = SaveDialog::launch(settings);
-                    diag.emit(SaveDialogMsg::SaveAs(format!(
-                        "LACT-profile-{}.json",
-                        name.as_deref().unwrap_or("default")
-                    )));
-
-                    let stream = diag.into_stream();
-
-                    sender.oneshot_command(async move {
-                        if let Some(SaveDialogResponse::Accept(path)) = stream.recv_one().await {
-                            let contents = serde_json::to_string(&profile)
-                                .expect("Could not serialize profile");
-
-                            if let Err(err) =
-                                fs::write(path, contents).context("Could not export profile")
-                            {
-                                return Some(CommandOutput::Error(err));
-                            }
-                        }
-                        None
-                    });
-                }
+                let export = self.daemon_client.export_profile(name.clone()).await?;
+                let settings = SaveDialogSettings {
+                    create_folders: true,
+                    is_modal: true,
+                    ..Default::default()
+                };
+                let diag = SaveDialog::launch(settings);
+                diag.emit(SaveDialogMsg::SaveAs(format!(
+                    "LACT-profile-{}.json",
+                    name.as_deref().unwrap_or("default")
+                )));
+
+                let stream = diag.into_stream();
+
+                sender.oneshot_command(async move {
+                    if let Some(SaveDialogResponse::Accept(path)) = stream.recv_one().await
+                        && let Err(err) = export.to_json().and_then(|contents| {
+                            fs::write(path, contents).context("Could not export profile")
+                        })
+                    {
+                        return Some(CommandOutput::Error(err));
+                    }
+                    None
+                });
             }
             AppMsg::Stats(stats) => {
                 let update = PageUpdate::Stats(stats.clone());
@@ -792,6 +800,7 @@ impl AppModel {
                 self.graphs_window.emit(GraphsWindowMsg::Stats {
                     stats,
                     selected_gpu_id: None,
+                    history: vec![],
                 });
             }
             AppMsg::ApplyChanges => {
@@ -946,16 +955,32 @@ impl AppModel {
                     .unwrap_or("Imported profile");
 
                 let contents = fs::read_to_string(&path).context("Could not read selected file")?;
-                let profile = serde_json::from_str::<Profile>(&contents)
-                    .context("Could not parse profile")?;
                 let profile_name = file_name
                     .trim_start_matches("LACT-profile-")
                     .trim_end_matches(".json");
 
-                self.daemon_client
-                    .create_profile(profile_name.to_owned(), ProfileBase::Provided(profile))
-                    .await
-                    .context("Could not import profile")?;
+                let value: serde_json::Value =
+                    serde_json::from_str(&contents).context("Could not parse profile")?;
+
+                // Files exported by older versions contain the profile as it is stored in the config
+                if value.get("version").is_none() {
+                    let profile = serde_json::from_value::<Profile>(value)
+                        .context("Could not parse profile")?;
+                    self.daemon_client
+                        .create_profile(profile_name.to_owned(), ProfileBase::Provided(profile))
+                        .await
+                        .context("Could not import profile")?;
+                } else {
+                    let export = ProfileExport::from_json(&contents)?;
+                    let unmatched = self
+                        .daemon_client
+                        .import_profile(Some(profile_name.to_owned()), export)
+                        .await
+                        .context("Could not import profile")?;
+                    for model_id in unmatched {
+                        warn!("no GPU of model {model_id} found, its settings were not imported");
+                    }
+                }
 
                 sender.input(AppMsg::ReloadProfiles { state_sender: None });
             }
@@ -1088,9 +1113,19 @@ impl AppModel {
 
         let stats = self.update_gpu_data(gpu_id.clone(), sender).await?;
 
+        // The history is optional, older daemons or ones without it enabled return an error
+        let history_since = jiff::Timestamp::now().as_millisecond() - STATS_HISTORY_PERIOD_MS;
+        let history = self
+            .daemon_client
+            .get_stats_history(&gpu_id, Some(history_since), vec![])
+            .await
+            .inspect_err(|err| debug!("could not fetch stats history: {err:#}"))
+            .unwrap_or_default();
+
         self.graphs_window.emit(GraphsWindowMsg::Stats {
             stats,
             selected_gpu_id: Some(gpu_id),
+            history,
         });
 
         self.ui_sensitive.set_value(true);
@@ -8,7 +8,7 @@ use anyhow::Context;
 use gtk::{glib, prelude::*};
 use i18n_embed_fl::fl;
 use jiff::Zoned;
-use lact_schema::DeviceStats;
+use lact_schema::{DeviceStats, history::StatsHistorySample};
 use plot_component::{PlotComponent, PlotComponentConfig, PlotComponentMsg};
 use relm4::{
     ComponentController, ComponentParts, ComponentSender, RelmWidgetExt,
@@ -42,6 +42,8 @@ pub enum GraphsWindowMsg {
         stats: Arc<DeviceStats>,
         /// Fill for initial message
         selected_gpu_id: Option<String>,
+        /// Samples from the daemon's stats history, only used with the initial message
+        history: Vec<StatsHistorySample>,
     },
     VramClockRatio(f64),
     NotifyEditing,
@@ -205,9 +207,15 @@ impl relm4::Component for GraphsWindow {
             GraphsWindowMsg::Stats {
                 stats,
                 selected_gpu_id,
+                history,
             } => {
                 if let Some(selected_gpu_id) = selected_gpu_id {
-                    self.stats_data.write().unwrap().clear();
+                    let mut data = self.stats_data.write().unwrap();
+                    data.clear();
+                    for sample in history {
+                        data.push_sample(sample, self.vram_clock_ratio);
+                    }
+                    drop(data);
 
                     let config = CONFIG.read();
                     let plots_config = config
@@ -28,7 +28,7 @@ use gtk::{
 use i18n_embed_fl::fl;
 use lact_schema::{
     DeviceFlag, FanControlMode,
-    config::{FanControlSettings, FanCurve, GpuConfig},
+    config::{FanControlSettings, FanCurve, FanPidSettings, GpuConfig},
     default_fan_curve,
 };
 use relm4::{
@@ -42,6 +42,7 @@ use std::{cell::Cell, rc::Rc};
 const AUTO_PAGE: &str = "automatic";
 const CURVE_PAGE: &str = "curve";
 const STATIC_PAGE: &str = "static";
+const PID_PAGE: &str = "pid";
 
 pub struct ThermalsPage {
     stats_section: relm4::Controller<GpuStatsSection>,
@@ -57,6 +58,7 @@ pub struct ThermalsPage {
     option_change_signals: Vec<(glib::Object, SignalHandlerId)>,
 
     static_speed_adj: Adjustment,
+    pid_target_temp_adj: Adjustment,
 }
 
 #[derive(Clone, Default)]
@@ -350,6 +352,34 @@ impl relm4::Component for ThermalsPage {
                         },
                     },
 
+                    add_titled[Some(PID_PAGE), &fl!(I18N, "pid-page")] = &gtk::Box {
+                        set_valign: gtk::Align::Start,
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 5,
+
+                        #[template]
+                        #[name = "pid_target_temp_row"]
+                        FanSettingRow {
+                            #[template_child]
+                            label {
+                                set_label: &fl!(I18N, "target-temp"),
+                            },
+
+                            #[template_child]
+                            scale {
+                                set_adjustment: &model.pid_target_temp_adj,
+                                connect_value_changed => move |_| {
+                                    APP_BROKER.send(AppMsg::SettingsChanged);
+                                } @ pid_target_temp_changed_signal,
+                            },
+
+                            #[template_child]
+                            spinbutton {
+                                set_adjustment: &model.pid_target_temp_adj,
+                            },
+                        },
+                    },
+
                     add_binding: (&model.selected_mode, "visible-child-name"),
                     connect_visible_child_name_notify => ThermalsPageMsg::FanModeSelected @ mode_selected_signal,
                 }
@@ -426,6 +456,7 @@ impl relm4::Component for ThermalsPage {
             has_pmfw: false,
             has_auto_threshold: false,
             static_speed_adj: Adjustment::new(50.0, 0.0, 100.0, 1.0, 5.0, 0.0),
+            pid_target_temp_adj: Adjustment::new(80.0, 30.0, 110.0, 1.0, 5.0, 0.0),
             selected_mode: StringBinding::new(AUTO_PAGE),
         };
 
@@ -466,6 +497,7 @@ impl relm4::Component for ThermalsPage {
                             Some(mode) if stats.fan.control_enabled => match mode {
                                 FanControlMode::Static => STATIC_PAGE,
                                 FanControlMode::Curve => CURVE_PAGE,
+                                FanControlMode::Pid => PID_PAGE,
                             },
                             _ => AUTO_PAGE,
                         };
@@ -502,6 +534,24 @@ impl relm4::Component for ThermalsPage {
                             .scale
                             .unblock_signal(&widgets.static_speed_changed_signal);
 
+                        widgets
+                            .pid_target_temp_row
+                            .scale
+                            .block_signal(&widgets.pid_target_temp_changed_signal);
+                        self.pid_target_temp_adj.set_value(
+                            stats
+                                .fan
+                                .pid
+                                .clone()
+                                .unwrap_or_default()
+                                .target_temperature
+                                .into(),
+                        );
+                        widgets
+                            .pid_target_temp_row
+                            .scale
+                            .unblock_signal(&widgets.pid_target_temp_changed_signal);
+
                         let temperature_range = stats
                             .fan
                             .temperature_range
@@ -512,6 +562,7 @@ impl relm4::Component for ThermalsPage {
                             curve: stats.fan.curve.clone().unwrap_or_else(default_fan_curve),
                             hw_based: self.has_pmfw,
                             current_temperatures: stats.temps.clone(),
+                            system_sensors: stats.fan.system_sensors.clone(),
                             temperature_key: stats.fan.temperature_key.clone(),
                             spindown_delay: stats.fan.spindown_delay_ms,
                             change_threshold: stats.fan.change_threshold,
@@ -616,6 +667,13 @@ impl ThermalsPage {
                     fan_settings.mode = FanControlMode::Static;
                     fan_settings.static_speed = self.static_speed_adj.value() as f32 / 100.0;
                 }
+                PID_PAGE => {
+                    fan_settings.mode = FanControlMode::Pid;
+                    fan_settings
+                        .pid
+                        .get_or_insert_with(FanPidSettings::default)
+                        .target_temperature = self.pid_target_temp_adj.value() as f32;
+                }
                 _ => unreachable!("Invalid fan control page selected"),
             }
         }
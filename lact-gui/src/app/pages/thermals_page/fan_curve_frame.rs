@@ -95,6 +95,8 @@ pub(super) struct CurveSetupMsg {
     pub curve: FanCurveMap,
     pub hw_based: bool,
     pub current_temperatures: IndexMap<String, TemperatureEntry>,
+    /// System hwmon sensors which can be used instead of the GPU's own sensors
+    pub system_sensors: Vec<String>,
     pub temperature_key: Option<String>,
     pub speed_range: RangeInclusive<f32>,
     pub temperature_range: RangeInclusive<f32>,
@@ -405,6 +407,7 @@ impl relm4::Component for FanCurveFrame {
                     .map(|(key, _)| key)
                     .collect::<Vec<_>>();
                 temp_keys.sort();
+                temp_keys.extend(msg.system_sensors);
 
                 let selected_idx = msg
                     .temperature_key
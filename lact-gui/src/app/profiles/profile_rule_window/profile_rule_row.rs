@@ -4,15 +4,21 @@ use gtk::{
     glib::GString,
     pango,
     prelude::{
-        BoxExt, ButtonExt, CheckButtonExt, EditableExt, EntryBufferExt, EntryBufferExtManual,
-        EntryExt, GridExt, OrientableExt, PopoverExt, SelectionModelExt, WidgetExt,
+        AdjustmentExt, BoxExt, ButtonExt, CheckButtonExt, EditableExt, EntryBufferExt,
+        EntryBufferExtManual, EntryExt, GridExt, OrientableExt, PopoverExt, SelectionModelExt,
+        WidgetExt,
     },
 };
 use i18n_embed_fl::fl;
-use lact_schema::{ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState};
+use lact_schema::{
+    FocusedWindowProfileRule, GpuStatProfileRule, ProcessMatchMode, ProcessProfileRule,
+    ProfileProcessInfo, ProfileRule, ProfileRuleStat, ProfileWatcherState, ScheduleProfileRule,
+    Weekday,
+};
 use relm4::{
     RelmObjectExt, RelmWidgetExt,
     binding::{BoolBinding, StringBinding},
+    css,
     typed_view::list::{RelmListItem, TypedListView},
     view,
 };
@@ -21,6 +27,25 @@ use tracing::debug;
 
 const PROCESS_PAGE: &str = "process";
 const GAMEMODE_PAGE: &str = "gamemode";
+const SCHEDULE_PAGE: &str = "schedule";
+const POWER_SOURCE_PAGE: &str = "power_source";
+const GPU_STAT_PAGE: &str = "gpu_stat";
+const FOCUSED_WINDOW_PAGE: &str = "focused_window";
+
+/// Order of the match modes in the dropdown
+const MATCH_MODES: [ProcessMatchMode; 3] = [
+    ProcessMatchMode::Exact,
+    ProcessMatchMode::Glob,
+    ProcessMatchMode::Regex,
+];
+
+/// Order of the stats in the dropdown
+const GPU_STATS: [ProfileRuleStat; 4] = [
+    ProfileRuleStat::BusyPercent,
+    ProfileRuleStat::Temperature,
+    ProfileRuleStat::Power,
+    ProfileRuleStat::VramUsed,
+];
 
 pub struct ProfileRuleRow {
     process_listview: TypedListView<ProcessListItem, gtk::SingleSelection>,
@@ -33,6 +58,34 @@ pub struct ProfileRuleRow {
 
     process_name_buffer: gtk::EntryBuffer,
     args_buffer: gtk::EntryBuffer,
+    match_mode_dropdown: gtk::DropDown,
+    /// Executable, working directory, user and cgroup filters are not editable in the GUI,
+    /// but kept when editing a rule from the config
+    process_filters: ProcessProfileRule,
+
+    schedule_start_buffer: gtk::EntryBuffer,
+    schedule_end_buffer: gtk::EntryBuffer,
+    weekdays_box: gtk::Box,
+    weekday_buttons: Vec<(Weekday, gtk::CheckButton)>,
+
+    on_battery: BoolBinding,
+    filter_battery_below: BoolBinding,
+    filter_battery_above: BoolBinding,
+    battery_below_adjustment: gtk::Adjustment,
+    battery_above_adjustment: gtk::Adjustment,
+
+    /// Not editable in the GUI, but kept when editing a rule from the config
+    stat_gpu_id: Option<String>,
+    stat_dropdown: gtk::DropDown,
+    stat_sensor_buffer: gtk::EntryBuffer,
+    filter_stat_above: BoolBinding,
+    filter_stat_below: BoolBinding,
+    stat_above_adjustment: gtk::Adjustment,
+    stat_below_adjustment: gtk::Adjustment,
+    stat_duration_adjustment: gtk::Adjustment,
+
+    window_app_id_buffer: gtk::EntryBuffer,
+    window_process_name_buffer: gtk::EntryBuffer,
 }
 
 #[derive(Debug)]
@@ -150,6 +203,13 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
                                             set_sensitive: false,
                                             add_binding: (&self.filter_by_args, "sensitive"),
                                         },
+
+                                        attach[0, 2, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-match-mode"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach: (&self.match_mode_dropdown, 2, 2, 2, 1),
                                     },
 
                                     add_titled[Some(GAMEMODE_PAGE), &fl!(I18N, "profile-rule-gamemode-tab")] = &gtk::Grid {
@@ -192,6 +252,195 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
                                         },
                                     },
 
+                                    add_titled[Some(SCHEDULE_PAGE), &fl!(I18N, "profile-rule-schedule-tab")] = &gtk::Box {
+                                        set_orientation: gtk::Orientation::Vertical,
+                                        set_spacing: 5,
+
+                                        gtk::Grid {
+                                            set_row_spacing: 5,
+                                            set_column_spacing: 10,
+
+                                            attach[0, 0, 1, 1] = &gtk::Label {
+                                                set_label: &fl!(I18N, "profile-rule-schedule-start"),
+                                                set_halign: gtk::Align::Start,
+                                            },
+
+                                            attach[1, 0, 1, 1] = &gtk::Entry {
+                                                set_buffer: &self.schedule_start_buffer,
+                                                set_hexpand: true,
+                                                set_placeholder_text: Some("22:00"),
+                                            },
+
+                                            attach[0, 1, 1, 1] = &gtk::Label {
+                                                set_label: &fl!(I18N, "profile-rule-schedule-end"),
+                                                set_halign: gtk::Align::Start,
+                                            },
+
+                                            attach[1, 1, 1, 1] = &gtk::Entry {
+                                                set_buffer: &self.schedule_end_buffer,
+                                                set_hexpand: true,
+                                                set_placeholder_text: Some("07:00"),
+                                            },
+                                        },
+
+                                        gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-schedule-weekdays"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        append: &self.weekdays_box,
+                                    },
+
+                                    add_titled[Some(POWER_SOURCE_PAGE), &fl!(I18N, "profile-rule-power-source-tab")] = &gtk::Grid {
+                                        set_row_spacing: 5,
+                                        set_column_spacing: 10,
+
+                                        attach[0, 0, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-on-battery"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach[1, 0, 1, 1] = &gtk::CheckButton {
+                                            add_binding: (&self.on_battery, "active"),
+                                        },
+
+                                        attach[0, 1, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-battery-below"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach[1, 1, 1, 1] = &gtk::CheckButton {
+                                            add_binding: (&self.filter_battery_below, "active"),
+                                        },
+
+                                        attach[2, 1, 1, 1] = &gtk::SpinButton {
+                                            set_adjustment: &self.battery_below_adjustment,
+                                            set_hexpand: true,
+                                            set_sensitive: false,
+                                            add_binding: (&self.filter_battery_below, "sensitive"),
+                                        },
+
+                                        attach[0, 2, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-battery-above"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach[1, 2, 1, 1] = &gtk::CheckButton {
+                                            add_binding: (&self.filter_battery_above, "active"),
+                                        },
+
+                                        attach[2, 2, 1, 1] = &gtk::SpinButton {
+                                            set_adjustment: &self.battery_above_adjustment,
+                                            set_hexpand: true,
+                                            set_sensitive: false,
+                                            add_binding: (&self.filter_battery_above, "sensitive"),
+                                        },
+                                    },
+
+                                    add_titled[Some(GPU_STAT_PAGE), &fl!(I18N, "profile-rule-gpu-stat-tab")] = &gtk::Grid {
+                                        set_row_spacing: 5,
+                                        set_column_spacing: 10,
+
+                                        attach[0, 0, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-gpu-stat"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach: (&self.stat_dropdown, 2, 0, 1, 1),
+
+                                        attach[0, 1, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-gpu-stat-sensor"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach[2, 1, 1, 1] = &gtk::Entry {
+                                            set_buffer: &self.stat_sensor_buffer,
+                                            set_hexpand: true,
+                                            set_placeholder_text: Some("junction"),
+                                        },
+
+                                        attach[0, 2, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-gpu-stat-above"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach[1, 2, 1, 1] = &gtk::CheckButton {
+                                            add_binding: (&self.filter_stat_above, "active"),
+                                        },
+
+                                        attach[2, 2, 1, 1] = &gtk::SpinButton {
+                                            set_adjustment: &self.stat_above_adjustment,
+                                            set_hexpand: true,
+                                            set_sensitive: false,
+                                            add_binding: (&self.filter_stat_above, "sensitive"),
+                                        },
+
+                                        attach[0, 3, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-gpu-stat-below"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach[1, 3, 1, 1] = &gtk::CheckButton {
+                                            add_binding: (&self.filter_stat_below, "active"),
+                                        },
+
+                                        attach[2, 3, 1, 1] = &gtk::SpinButton {
+                                            set_adjustment: &self.stat_below_adjustment,
+                                            set_hexpand: true,
+                                            set_sensitive: false,
+                                            add_binding: (&self.filter_stat_below, "sensitive"),
+                                        },
+
+                                        attach[0, 4, 1, 1] = &gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-gpu-stat-duration"),
+                                            set_halign: gtk::Align::Start,
+                                        },
+
+                                        attach[2, 4, 1, 1] = &gtk::SpinButton {
+                                            set_adjustment: &self.stat_duration_adjustment,
+                                            set_hexpand: true,
+                                        },
+                                    },
+
+                                    add_titled[Some(FOCUSED_WINDOW_PAGE), &fl!(I18N, "profile-rule-focused-window-tab")] = &gtk::Box {
+                                        set_orientation: gtk::Orientation::Vertical,
+                                        set_spacing: 5,
+
+                                        gtk::Grid {
+                                            set_row_spacing: 5,
+                                            set_column_spacing: 10,
+
+                                            attach[0, 0, 1, 1] = &gtk::Label {
+                                                set_label: &fl!(I18N, "profile-rule-window-app-id"),
+                                                set_halign: gtk::Align::Start,
+                                            },
+
+                                            attach[1, 0, 1, 1] = &gtk::Entry {
+                                                set_buffer: &self.window_app_id_buffer,
+                                                set_hexpand: true,
+                                                set_placeholder_text: Some("steam_app_1091500"),
+                                            },
+
+                                            attach[0, 1, 1, 1] = &gtk::Label {
+                                                set_label: &fl!(I18N, "profile-rule-process-name"),
+                                                set_halign: gtk::Align::Start,
+                                            },
+
+                                            attach[1, 1, 1, 1] = &gtk::Entry {
+                                                set_buffer: &self.window_process_name_buffer,
+                                                set_hexpand: true,
+                                                set_placeholder_text: Some("Cyberpunk2077.exe"),
+                                            },
+                                        },
+
+                                        gtk::Label {
+                                            set_label: &fl!(I18N, "profile-rule-focused-window-info"),
+                                            set_halign: gtk::Align::Start,
+                                            set_wrap: true,
+                                            add_css_class: css::DIM_LABEL,
+                                        },
+                                    },
+
                                     add_binding: (&self.selected_page, "visible-child-name"),
                                 },
 
@@ -226,24 +475,166 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
     ) -> Self {
         let process_name_buffer = gtk::EntryBuffer::default();
         let args_buffer = gtk::EntryBuffer::default();
+        let schedule_start_buffer = gtk::EntryBuffer::default();
+        let schedule_end_buffer = gtk::EntryBuffer::default();
+        let stat_sensor_buffer = gtk::EntryBuffer::default();
+        let window_app_id_buffer = gtk::EntryBuffer::default();
+        let window_process_name_buffer = gtk::EntryBuffer::default();
+
+        for buffer in [
+            &process_name_buffer,
+            &args_buffer,
+            &schedule_start_buffer,
+            &schedule_end_buffer,
+            &stat_sensor_buffer,
+            &window_app_id_buffer,
+            &window_process_name_buffer,
+        ] {
+            buffer.connect_text_notify({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
 
-        process_name_buffer.connect_text_notify({
+        let process_filters = match &rule {
+            ProfileRule::Process(rule) | ProfileRule::Gamemode(Some(rule)) => rule.clone(),
+            _ => ProcessProfileRule::default(),
+        };
+        process_name_buffer.set_text(process_filters.name.as_ref());
+        args_buffer.set_text(process_filters.args.as_deref().unwrap_or_default());
+
+        let match_mode_labels: Vec<String> =
+            MATCH_MODES.into_iter().map(match_mode_label).collect();
+        let match_mode_labels: Vec<&str> = match_mode_labels.iter().map(String::as_str).collect();
+        let match_mode_dropdown = gtk::DropDown::from_strings(&match_mode_labels);
+        let match_mode_index = MATCH_MODES
+            .iter()
+            .position(|mode| *mode == process_filters.match_mode)
+            .unwrap_or_default();
+        match_mode_dropdown.set_selected(match_mode_index as u32);
+        match_mode_dropdown.connect_selected_notify({
             let sender = sender.clone();
             move |_| {
                 sender.input(ProfileRuleRowMsg::Changed);
             }
         });
-        args_buffer.connect_text_notify({
+
+        let schedule_rule = match &rule {
+            ProfileRule::Schedule(schedule_rule) => Some(schedule_rule),
+            _ => None,
+        };
+        if let Some(schedule_rule) = schedule_rule {
+            schedule_start_buffer.set_text(schedule_rule.start.to_string());
+            schedule_end_buffer.set_text(schedule_rule.end.to_string());
+        }
+
+        let weekdays_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+        let weekday_buttons: Vec<_> = Weekday::ALL
+            .into_iter()
+            .map(|weekday| {
+                let button = gtk::CheckButton::with_label(&weekday_label(weekday));
+                button.set_active(
+                    schedule_rule
+                        .is_some_and(|schedule_rule| schedule_rule.weekdays.contains(&weekday)),
+                );
+                button.connect_toggled({
+                    let sender = sender.clone();
+                    move |_| {
+                        sender.input(ProfileRuleRowMsg::Changed);
+                    }
+                });
+                weekdays_box.append(&button);
+                (weekday, button)
+            })
+            .collect();
+
+        let (on_battery, battery_below, battery_above) = match &rule {
+            ProfileRule::PowerSource {
+                on_battery,
+                battery_below,
+                battery_above,
+            } => (*on_battery, *battery_below, *battery_above),
+            _ => (true, None, None),
+        };
+
+        let battery_below_adjustment = gtk::Adjustment::new(
+            f64::from(battery_below.unwrap_or(20)),
+            0.0,
+            100.0,
+            1.0,
+            10.0,
+            0.0,
+        );
+        let battery_above_adjustment = gtk::Adjustment::new(
+            f64::from(battery_above.unwrap_or(80)),
+            0.0,
+            100.0,
+            1.0,
+            10.0,
+            0.0,
+        );
+        if let ProfileRule::FocusedWindow(window_rule) = &rule {
+            window_app_id_buffer.set_text(window_rule.app_id.as_deref().unwrap_or_default());
+            window_process_name_buffer
+                .set_text(window_rule.process_name.as_deref().unwrap_or_default());
+        }
+
+        let stat_rule = match &rule {
+            ProfileRule::GpuStat(stat_rule) => stat_rule.clone(),
+            _ => GpuStatProfileRule::default(),
+        };
+        stat_sensor_buffer.set_text(stat_rule.sensor.as_deref().unwrap_or_default());
+
+        let stat_labels: Vec<String> = GPU_STATS.into_iter().map(stat_label).collect();
+        let stat_labels: Vec<&str> = stat_labels.iter().map(String::as_str).collect();
+        let stat_dropdown = gtk::DropDown::from_strings(&stat_labels);
+        let stat_index = GPU_STATS
+            .iter()
+            .position(|stat| *stat == stat_rule.stat)
+            .unwrap_or_default();
+        stat_dropdown.set_selected(stat_index as u32);
+        stat_dropdown.connect_selected_notify({
             let sender = sender.clone();
             move |_| {
                 sender.input(ProfileRuleRowMsg::Changed);
             }
         });
 
-        if let ProfileRule::Process(rule) | ProfileRule::Gamemode(Some(rule)) = &rule {
-            process_name_buffer.set_text(rule.name.as_ref());
-            args_buffer.set_text(rule.args.as_deref().unwrap_or_default());
-        };
+        let stat_above_adjustment = gtk::Adjustment::new(
+            stat_rule.above.unwrap_or(80.0),
+            0.0,
+            100_000.0,
+            1.0,
+            10.0,
+            0.0,
+        );
+        let stat_below_adjustment = gtk::Adjustment::new(
+            stat_rule.below.unwrap_or(20.0),
+            0.0,
+            100_000.0,
+            1.0,
+            10.0,
+            0.0,
+        );
+        let stat_duration_adjustment =
+            gtk::Adjustment::new(stat_rule.duration as f64, 0.0, 3600.0, 1.0, 10.0, 0.0);
+
+        for adjustment in [
+            &battery_below_adjustment,
+            &battery_above_adjustment,
+            &stat_above_adjustment,
+            &stat_below_adjustment,
+            &stat_duration_adjustment,
+        ] {
+            adjustment.connect_value_changed({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
 
         let mut process_listview = TypedListView::<ProcessListItem, gtk::SingleSelection>::new();
         process_listview.selection_model.set_autoselect(false);
@@ -251,14 +642,31 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
         let initial_page = match &rule {
             ProfileRule::Process(_) => PROCESS_PAGE,
             ProfileRule::Gamemode(_) => GAMEMODE_PAGE,
+            ProfileRule::Schedule(_) => SCHEDULE_PAGE,
+            ProfileRule::PowerSource { .. } => POWER_SOURCE_PAGE,
+            ProfileRule::GpuStat(_) => GPU_STAT_PAGE,
+            ProfileRule::FocusedWindow(_) => FOCUSED_WINDOW_PAGE,
             _ => PROCESS_PAGE, // Fallback
         };
         let selected_page = StringBinding::new(initial_page);
 
         let filter_by_args = BoolBinding::new(args_buffer.length() > 0);
         let gamemode_filter_by_process = BoolBinding::new(process_name_buffer.length() > 0);
-
-        for bool_bind in [&filter_by_args, &gamemode_filter_by_process] {
+        let on_battery = BoolBinding::new(on_battery);
+        let filter_battery_below = BoolBinding::new(battery_below.is_some());
+        let filter_battery_above = BoolBinding::new(battery_above.is_some());
+        let filter_stat_above = BoolBinding::new(stat_rule.above.is_some());
+        let filter_stat_below = BoolBinding::new(stat_rule.below.is_some());
+
+        for bool_bind in [
+            &filter_by_args,
+            &gamemode_filter_by_process,
+            &on_battery,
+            &filter_battery_below,
+            &filter_battery_above,
+            &filter_stat_above,
+            &filter_stat_below,
+        ] {
             bool_bind.connect_value_notify({
                 let sender = sender.clone();
                 move |_| {
@@ -292,7 +700,28 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
             process_search_filter,
             filter_by_args,
             args_buffer,
+            match_mode_dropdown,
+            process_filters,
             process_listview,
+            schedule_start_buffer,
+            schedule_end_buffer,
+            weekdays_box,
+            weekday_buttons,
+            on_battery,
+            filter_battery_below,
+            filter_battery_above,
+            battery_below_adjustment,
+            battery_above_adjustment,
+            stat_gpu_id: stat_rule.gpu_id,
+            stat_dropdown,
+            stat_sensor_buffer,
+            filter_stat_above,
+            filter_stat_below,
+            stat_above_adjustment,
+            stat_below_adjustment,
+            stat_duration_adjustment,
+            window_app_id_buffer,
+            window_process_name_buffer,
         }
     }
 
@@ -360,6 +789,19 @@ impl ProfileRuleRow {
     pub fn get_configured_rule(&self) -> ProfileRule {
         let process_name = self.process_name_buffer.text();
         let process_args = self.args_buffer.text();
+        let process_rule = |args| ProcessProfileRule {
+            name: process_name.as_str().into(),
+            args,
+            match_mode: MATCH_MODES
+                .get(self.match_mode_dropdown.selected() as usize)
+                .copied()
+                .unwrap_or_default(),
+            exe: self.process_filters.exe.clone(),
+            cwd: self.process_filters.cwd.clone(),
+            uid: self.process_filters.uid,
+            cgroup: self.process_filters.cgroup.clone(),
+            ..Default::default()
+        };
 
         match self.selected_page.value().as_str() {
             PROCESS_PAGE => {
@@ -368,10 +810,7 @@ impl ProfileRuleRow {
                 } else {
                     None
                 };
-                ProfileRule::Process(ProcessProfileRule {
-                    name: process_name.as_str().into(),
-                    args,
-                })
+                ProfileRule::Process(process_rule(args))
             }
             GAMEMODE_PAGE => {
                 let args = if self.filter_by_args.value() {
@@ -382,13 +821,66 @@ impl ProfileRuleRow {
                 let rule = if !self.gamemode_filter_by_process.value() && args.is_none() {
                     None
                 } else {
-                    Some(ProcessProfileRule {
-                        name: process_name.as_str().into(),
-                        args,
-                    })
+                    Some(process_rule(args))
                 };
                 ProfileRule::Gamemode(rule)
             }
+            SCHEDULE_PAGE => ProfileRule::Schedule(ScheduleProfileRule {
+                // Invalid times fall back to midnight, which is visible in the rule description
+                start: self
+                    .schedule_start_buffer
+                    .text()
+                    .parse()
+                    .unwrap_or_default(),
+                end: self.schedule_end_buffer.text().parse().unwrap_or_default(),
+                weekdays: self
+                    .weekday_buttons
+                    .iter()
+                    .filter(|(_, button)| button.is_active())
+                    .map(|(weekday, _)| *weekday)
+                    .collect(),
+            }),
+            POWER_SOURCE_PAGE => ProfileRule::PowerSource {
+                on_battery: self.on_battery.value(),
+                battery_below: self
+                    .filter_battery_below
+                    .value()
+                    .then(|| self.battery_below_adjustment.value() as u8),
+                battery_above: self
+                    .filter_battery_above
+                    .value()
+                    .then(|| self.battery_above_adjustment.value() as u8),
+            },
+            GPU_STAT_PAGE => {
+                let stat = GPU_STATS
+                    .get(self.stat_dropdown.selected() as usize)
+                    .copied()
+                    .unwrap_or_default();
+                let sensor = self.stat_sensor_buffer.text();
+                ProfileRule::GpuStat(GpuStatProfileRule {
+                    gpu_id: self.stat_gpu_id.clone(),
+                    stat,
+                    sensor: (stat == ProfileRuleStat::Temperature && !sensor.is_empty())
+                        .then(|| sensor.to_string()),
+                    above: self
+                        .filter_stat_above
+                        .value()
+                        .then(|| self.stat_above_adjustment.value()),
+                    below: self
+                        .filter_stat_below
+                        .value()
+                        .then(|| self.stat_below_adjustment.value()),
+                    duration: self.stat_duration_adjustment.value() as u64,
+                })
+            }
+            FOCUSED_WINDOW_PAGE => {
+                let app_id = self.window_app_id_buffer.text();
+                let process_name = self.window_process_name_buffer.text();
+                ProfileRule::FocusedWindow(FocusedWindowProfileRule {
+                    app_id: (!app_id.is_empty()).then(|| app_id.to_string()),
+                    process_name: (!process_name.is_empty()).then(|| process_name.as_str().into()),
+                })
+            }
             _ => unreachable!(),
         }
     }
@@ -401,12 +893,20 @@ fn format_rule(rule: &ProfileRule) -> String {
         ProfileRule::Process(process_rule) => {
             if !process_rule.name.is_empty() {
                 write!(text, "Process <b>{}</b> is running", process_rule.name).unwrap();
+            } else if process_rule.args.is_some()
+                || process_rule.exe.is_some()
+                || process_rule.cwd.is_some()
+                || process_rule.uid.is_some()
+                || process_rule.cgroup.is_some()
+            {
+                write!(text, "Process is running").unwrap();
             } else {
                 write!(text, "Process is running <b>(unconfigured)</b>").unwrap();
             }
             if let Some(args) = &process_rule.args {
                 write!(text, " with args <b>{args}</b>").unwrap();
             }
+            format_process_filters(&mut text, process_rule);
         }
         ProfileRule::Gamemode(process_rule) => {
             write!(text, "Gamemode is active").unwrap();
@@ -415,6 +915,73 @@ fn format_rule(rule: &ProfileRule) -> String {
                 if let Some(args) = &process_rule.args {
                     write!(text, " and args <b>{args}</b>").unwrap();
                 }
+                format_process_filters(&mut text, process_rule);
+            }
+        }
+        ProfileRule::Schedule(schedule_rule) => {
+            write!(
+                text,
+                "Time is between <b>{}</b> and <b>{}</b>",
+                schedule_rule.start, schedule_rule.end
+            )
+            .unwrap();
+            if !schedule_rule.weekdays.is_empty() {
+                let weekdays: Vec<String> = schedule_rule
+                    .weekdays
+                    .iter()
+                    .map(|weekday| weekday_label(*weekday))
+                    .collect();
+                write!(text, " starting on <b>{}</b>", weekdays.join(", ")).unwrap();
+            }
+        }
+        ProfileRule::PowerSource {
+            on_battery,
+            battery_below,
+            battery_above,
+        } => {
+            if *on_battery {
+                write!(text, "System is running <b>on battery</b>").unwrap();
+            } else {
+                write!(text, "System is running <b>on AC power</b>").unwrap();
+            }
+            if let Some(percent) = battery_below {
+                write!(text, " with battery below <b>{percent}%</b>").unwrap();
+            }
+            if let Some(percent) = battery_above {
+                write!(text, " with battery above <b>{percent}%</b>").unwrap();
+            }
+        }
+        ProfileRule::GpuStat(stat_rule) => {
+            write!(text, "<b>{}</b>", stat_label(stat_rule.stat)).unwrap();
+            if let Some(sensor) = &stat_rule.sensor {
+                write!(text, " ({sensor})").unwrap();
+            }
+            if let Some(gpu_id) = &stat_rule.gpu_id {
+                write!(text, " of GPU <b>{gpu_id}</b>").unwrap();
+            }
+            if let Some(above) = stat_rule.above {
+                write!(text, " is above <b>{above}</b>").unwrap();
+            }
+            if let Some(below) = stat_rule.below {
+                if stat_rule.above.is_some() {
+                    write!(text, " and").unwrap();
+                }
+                write!(text, " is below <b>{below}</b>").unwrap();
+            }
+            write!(text, " for <b>{}s</b>", stat_rule.duration).unwrap();
+        }
+        ProfileRule::FocusedWindow(window_rule) => {
+            match (&window_rule.app_id, &window_rule.process_name) {
+                (None, None) => write!(text, "Window is focused <b>(unconfigured)</b>").unwrap(),
+                (Some(app_id), None) => write!(text, "Window <b>{app_id}</b> is focused").unwrap(),
+                (None, Some(name)) => {
+                    write!(text, "Window of process <b>{name}</b> is focused").unwrap();
+                }
+                (Some(app_id), Some(name)) => write!(
+                    text,
+                    "Window <b>{app_id}</b> of process <b>{name}</b> is focused"
+                )
+                .unwrap(),
             }
         }
         ProfileRule::And(subrules) => {
@@ -440,6 +1007,55 @@ fn format_rule(rule: &ProfileRule) -> String {
     text
 }
 
+fn format_process_filters(text: &mut String, process_rule: &ProcessProfileRule) {
+    if let Some(exe) = &process_rule.exe {
+        write!(text, " from <b>{exe}</b>").unwrap();
+    }
+    if let Some(cwd) = &process_rule.cwd {
+        write!(text, " in <b>{cwd}</b>").unwrap();
+    }
+    if let Some(uid) = process_rule.uid {
+        write!(text, " as user <b>{uid}</b>").unwrap();
+    }
+    if let Some(cgroup) = &process_rule.cgroup {
+        write!(text, " in cgroup <b>{cgroup}</b>").unwrap();
+    }
+    match process_rule.match_mode {
+        ProcessMatchMode::Exact => (),
+        ProcessMatchMode::Glob => write!(text, " (glob)").unwrap(),
+        ProcessMatchMode::Regex => write!(text, " (regex)").unwrap(),
+    }
+}
+
+fn match_mode_label(mode: ProcessMatchMode) -> String {
+    match mode {
+        ProcessMatchMode::Exact => fl!(I18N, "profile-rule-match-exact"),
+        ProcessMatchMode::Glob => fl!(I18N, "profile-rule-match-glob"),
+        ProcessMatchMode::Regex => fl!(I18N, "profile-rule-match-regex"),
+    }
+}
+
+fn weekday_label(weekday: Weekday) -> String {
+    match weekday {
+        Weekday::Monday => fl!(I18N, "weekday-monday"),
+        Weekday::Tuesday => fl!(I18N, "weekday-tuesday"),
+        Weekday::Wednesday => fl!(I18N, "weekday-wednesday"),
+        Weekday::Thursday => fl!(I18N, "weekday-thursday"),
+        Weekday::Friday => fl!(I18N, "weekday-friday"),
+        Weekday::Saturday => fl!(I18N, "weekday-saturday"),
+        Weekday::Sunday => fl!(I18N, "weekday-sunday"),
+    }
+}
+
+fn stat_label(stat: ProfileRuleStat) -> String {
+    match stat {
+        ProfileRuleStat::BusyPercent => fl!(I18N, "profile-rule-gpu-stat-busy"),
+        ProfileRuleStat::Temperature => fl!(I18N, "profile-rule-gpu-stat-temperature"),
+        ProfileRuleStat::Power => fl!(I18N, "profile-rule-gpu-stat-power"),
+        ProfileRuleStat::VramUsed => fl!(I18N, "profile-rule-gpu-stat-vram"),
+    }
+}
+
 struct ProcessListItem(ProfileProcessInfo);
 
 struct ProcessListItemWidgets {
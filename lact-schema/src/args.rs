@@ -4,6 +4,7 @@ pub use clap;
 
 use crate::args::cli::CliArgs;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Args {
@@ -28,4 +29,14 @@ pub struct GuiArgs {
     /// Remote TCP address to connect to
     #[arg(long)]
     pub tcp_address: Option<String>,
+    /// Token to authenticate with on the remote TCP connection.
+    /// Without a token, only read-only access is allowed if the daemon has tokens configured.
+    #[arg(long, requires = "tcp_address")]
+    pub tcp_token: Option<String>,
+    /// Use TLS for the remote TCP connection
+    #[arg(long, requires = "tcp_address")]
+    pub tcp_tls: bool,
+    /// CA certificate to verify the remote daemon with. Implies `--tcp-tls`
+    #[arg(long, requires = "tcp_address")]
+    pub tcp_tls_ca: Option<PathBuf>,
 }
@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -18,6 +19,8 @@ pub enum CliCommand {
     Info,
     /// Show GPU stats
     Stats,
+    /// Show min/avg/max of the stats recorded in the daemon's stats history
+    History(HistoryArgs),
     /// Generate debug snapshot
     Snapshot,
     /// Manage GPU power limit
@@ -25,12 +28,25 @@ pub enum CliCommand {
         #[command(subcommand)]
         cmd: Option<PowerLimitCmd>,
     },
+    /// Measure the fan speed at different PWM values and store the minimum fan speeds in the config
+    CalibrateFan,
     /// Manage profiles
     Profile(ProfileArgs),
+    /// Show and roll back previous GPU settings
+    Config(ConfigArgs),
     /// Detach the GPU from LACT (temporarily ignore it)
     Detach,
     /// Reattach a previously detached GPU
     Reattach,
+    /// Report the focused window of the desktop session to the daemon, used by focused window profile rules
+    FocusAgent(FocusAgentArgs),
+}
+
+#[derive(Parser)]
+pub struct HistoryArgs {
+    /// Time window to summarize, in seconds
+    #[arg(short, long, default_value_t = 600)]
+    pub window: u64,
 }
 
 #[derive(Parser, Clone, Copy)]
@@ -57,6 +73,66 @@ pub enum ProfileCommand {
     Set(SetProfileArgs),
     /// Manage profile auto switching
     AutoSwitch(ProfileAutoSwitchArgs),
+    /// Export a profile to a file, which can be imported on another system
+    Export(ExportProfileArgs),
+    /// Import a profile from a file
+    Import(ImportProfileArgs),
+}
+
+#[derive(Parser)]
+pub struct ExportProfileArgs {
+    pub name: String,
+    /// Output file path
+    pub path: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct ImportProfileArgs {
+    pub path: PathBuf,
+    /// Name of the new profile, defaults to the name from the file
+    #[arg(short, long)]
+    pub name: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub subcommand: ConfigCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// List saved revisions of the GPU settings
+    History,
+    /// Show the changes between two revisions
+    Diff {
+        from: u64,
+        /// Revision to compare with, defaults to the current settings
+        to: Option<u64>,
+    },
+    /// Apply the settings of a previous revision
+    Rollback { revision: u64 },
+}
+
+#[derive(Parser)]
+pub struct FocusAgentArgs {
+    /// Source of the focus changes, detected from the desktop environment if not specified
+    #[arg(short, long, value_enum)]
+    pub backend: Option<FocusAgentBackend>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FocusAgentBackend {
+    /// `_NET_ACTIVE_WINDOW` of the root window, for X11 sessions
+    X11,
+    /// KWin script
+    Kwin,
+    /// GNOME Shell introspection interface
+    Gnome,
+    /// Sway IPC
+    Sway,
+    /// Only the D-Bus interface of the agent, for compositors that report focus changes with their own scripts
+    Dbus,
 }
 
 #[derive(Parser)]
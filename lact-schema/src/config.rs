@@ -202,9 +202,35 @@ pub struct FanControlSettings {
     pub temperature_key: String,
     pub interval_ms: u64,
     pub curve: FanCurve,
+    /// Settings of the PID controller used with `mode` set to `pid`
+    pub pid: Option<FanPidSettings>,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
     pub auto_threshold: Option<u64>,
+    /// Time span in milliseconds of the exponential moving average applied to the temperature
+    pub smoothing_window_ms: Option<u64>,
+    /// Minimum temperature increase in degrees over the last applied point to speed up the fan
+    pub hysteresis_up: Option<u64>,
+    /// Minimum temperature decrease in degrees from the last applied point to slow down the fan
+    pub hysteresis_down: Option<u64>,
+    /// Maximum fan speed change per tick, from 0 to 1
+    pub ramp_rate: Option<f32>,
+    /// Separate curves for multiple temperature sensors, used instead of `curve` and `temperature_key` when not empty
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub sensor_curves: IndexMap<String, SensorCurve>,
+    /// How the speeds from `sensor_curves` are combined, defaults to the maximum
+    pub sensor_combination: Option<SensorCombination>,
+    /// Temperature in degrees below which the fan is stopped
+    pub zero_rpm_stop_temperature: Option<u64>,
+    /// Temperature in degrees above which a stopped fan is started again, defaults to the stop temperature
+    pub zero_rpm_start_temperature: Option<u64>,
+    /// Minimum fan speed from 0 to 1 used when starting a stopped fan
+    pub zero_rpm_start_speed: Option<f32>,
+    /// What to do when the curve can't be followed safely
+    pub failsafe: Option<FanFailsafe>,
+    /// Lowest speed from 0 to 1 at which the fan keeps spinning, set by fan calibration.
+    /// Non-zero speeds of the software curve are raised to at least this value.
+    pub min_speed: Option<f32>,
 }
 
 impl Default for FanControlSettings {
@@ -215,13 +241,120 @@ impl Default for FanControlSettings {
             temperature_key: "edge".to_owned(),
             interval_ms: 500,
             curve: FanCurve(default_fan_curve()),
+            pid: None,
             spindown_delay_ms: None,
             change_threshold: None,
             auto_threshold: None,
+            smoothing_window_ms: None,
+            hysteresis_up: None,
+            hysteresis_down: None,
+            ramp_rate: None,
+            sensor_curves: IndexMap::new(),
+            sensor_combination: None,
+            zero_rpm_stop_temperature: None,
+            zero_rpm_start_temperature: None,
+            zero_rpm_start_speed: None,
+            failsafe: None,
+            min_speed: None,
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FanPidSettings {
+    /// Temperature in degrees that the fan speed is adjusted to hold
+    pub target_temperature: f32,
+    /// Proportional gain, in fan speed (from 0 to 1) per degree above the target
+    #[serde(default = "default_pid_kp")]
+    pub kp: f32,
+    /// Integral gain, in fan speed per degree-second above the target
+    #[serde(default = "default_pid_ki")]
+    pub ki: f32,
+    /// Derivative gain, in fan speed per degree/second of temperature change
+    #[serde(default)]
+    pub kd: f32,
+    /// Lowest fan speed from 0 to 1 the controller can set
+    #[serde(default)]
+    pub min_speed: f32,
+    /// Highest fan speed from 0 to 1 the controller can set
+    #[serde(default = "default_pid_max_speed")]
+    pub max_speed: f32,
+    /// Maximum absolute fan speed contribution of the integral term, limits windup
+    #[serde(default = "default_pid_integral_limit")]
+    pub integral_limit: f32,
+}
+
+impl Default for FanPidSettings {
+    fn default() -> Self {
+        Self {
+            target_temperature: 80.0,
+            kp: default_pid_kp(),
+            ki: default_pid_ki(),
+            kd: 0.0,
+            min_speed: 0.0,
+            max_speed: default_pid_max_speed(),
+            integral_limit: default_pid_integral_limit(),
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct FanFailsafe {
+    #[serde(default)]
+    pub action: FailsafeAction,
+    /// Temperature in degrees at which the failsafe is triggered
+    pub critical_temperature: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailsafeAction {
+    /// Run the fans at full speed
+    #[default]
+    MaxSpeed,
+    /// Hand fan control back to the firmware
+    Auto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SensorCurve {
+    pub curve: FanCurve,
+    /// Weight of this sensor when using the `weighted` combination
+    #[serde(default = "default_sensor_weight")]
+    pub weight: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorCombination {
+    /// The fan follows the highest speed of all sensor curves
+    #[default]
+    Max,
+    /// The fan follows the weighted average of the sensor curve speeds
+    Weighted,
+}
+
+pub fn default_sensor_weight() -> f32 {
+    1.0
+}
+
+pub fn default_pid_kp() -> f32 {
+    0.05
+}
+
+pub fn default_pid_ki() -> f32 {
+    0.005
+}
+
+pub fn default_pid_max_speed() -> f32 {
+    1.0
+}
+
+pub fn default_pid_integral_limit() -> f32 {
+    1.0
+}
+
 pub fn default_fan_static_speed() -> f32 {
     0.5
 }
@@ -0,0 +1,66 @@
+use crate::stability::StabilityTestState;
+use serde::{Deserialize, Serialize};
+
+/// Events sent to clients subscribed with `Request::SubscribeEvents`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// The active profile was changed. `None` means the default profile.
+    ProfileChanged {
+        name: Option<String>,
+    },
+    /// A configuration change was applied and is waiting for confirmation
+    ConfigPending {
+        id: String,
+        apply_timer: u64,
+    },
+    /// A pending configuration change was confirmed and saved
+    ConfigApplied {
+        id: String,
+    },
+    /// A pending configuration change was reverted, either explicitly or after the confirmation timeout
+    ConfigReverted {
+        id: String,
+    },
+    /// The configuration was replaced without going through the confirmation flow,
+    /// e.g. when the config file was edited or the config was reset
+    ConfigReloaded,
+    /// A profile was created, deleted, moved or had its rule changed, or auto switching was toggled
+    ProfilesUpdated,
+    GpuAdded {
+        id: String,
+    },
+    GpuRemoved {
+        id: String,
+    },
+    /// The fan control task stopped on its own due to an error
+    FanControlExited {
+        id: String,
+        reason: String,
+    },
+    /// The fan control failsafe was triggered
+    FanFailsafeTriggered {
+        id: String,
+        reason: String,
+    },
+    /// The fan control failsafe is no longer active and the fan curve is followed again
+    FanFailsafeCleared {
+        id: String,
+    },
+    /// The settings of a GPU were rolled back after it was reset repeatedly
+    ConfigRecovered {
+        id: String,
+        reason: String,
+        restored_known_good: bool,
+    },
+    /// A stability test finished or was stopped
+    StabilityTestFinished {
+        id: String,
+        state: StabilityTestState,
+        last_stable_value: Option<i32>,
+    },
+    /// The subscriber was not reading events fast enough and some of them were dropped
+    EventsMissed {
+        count: u64,
+    },
+}
@@ -0,0 +1,160 @@
+use crate::{
+    ProfileRule,
+    config::{GpuConfig, Profile, ProfileHooks},
+};
+use anyhow::{Context, bail};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+
+/// Version of the profile file format, increased on incompatible changes
+pub const PROFILE_EXPORT_VERSION: u32 = 1;
+
+/// A profile in a portable format, which can be imported on a different system
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileExport {
+    pub version: u32,
+    pub name: String,
+    /// GPU settings keyed by the PCI model id (see [`gpu_model_id`]) instead of the slot-specific GPU id
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub gpus: IndexMap<String, GpuConfig>,
+    pub rule: Option<ProfileRule>,
+    #[serde(default, skip_serializing_if = "ProfileHooks::is_empty")]
+    pub hooks: ProfileHooks,
+}
+
+impl ProfileExport {
+    /// Settings of GPUs with the same model are only exported once
+    pub fn new(name: String, profile: Profile) -> Self {
+        let mut gpus = IndexMap::new();
+        for (id, config) in profile.gpus {
+            gpus.entry(gpu_model_id(&id).to_owned()).or_insert(config);
+        }
+
+        Self {
+            version: PROFILE_EXPORT_VERSION,
+            name,
+            gpus,
+            rule: profile.rule,
+            hooks: profile.hooks,
+        }
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        serde_json::to_string_pretty(self).context("Could not serialize profile")
+    }
+
+    pub fn from_json(data: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(data).context("Invalid profile file")?;
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .context("Profile file is missing a version")?;
+        if version > u64::from(PROFILE_EXPORT_VERSION) {
+            bail!("Profile file version {version} is not supported, please update LACT");
+        }
+
+        serde_json::from_value(value).context("Invalid profile file")
+    }
+
+    /// Builds a profile for the given GPUs, applying the settings to every GPU of a matching model.
+    /// Returns the profile and the models which did not match any GPU.
+    pub fn into_profile<'a>(
+        self,
+        gpu_ids: impl IntoIterator<Item = &'a str>,
+    ) -> (Profile, Vec<String>) {
+        let gpu_ids: Vec<&str> = gpu_ids.into_iter().collect();
+        let mut gpus = IndexMap::new();
+        let mut unmatched = Vec::new();
+
+        for (model_id, config) in self.gpus {
+            let mut matched = false;
+            for id in gpu_ids.iter().filter(|id| gpu_model_id(id) == model_id) {
+                gpus.insert((*id).to_owned(), config.clone());
+                matched = true;
+            }
+
+            if !matched {
+                unmatched.push(model_id);
+            }
+        }
+
+        let profile = Profile {
+            gpus,
+            rule: self.rule,
+            hooks: self.hooks,
+        };
+        (profile, unmatched)
+    }
+}
+
+/// Strips the PCI slot from a GPU id, leaving `<vendor>:<device>-<subsystem vendor>:<subsystem device>`
+pub fn gpu_model_id(gpu_id: &str) -> &str {
+    gpu_id
+        .rsplit_once('-')
+        .map_or(gpu_id, |(model_id, _slot)| model_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PROFILE_EXPORT_VERSION, ProfileExport, gpu_model_id};
+    use crate::config::{GpuConfig, Profile};
+    use indexmap::IndexMap;
+
+    #[test]
+    fn export_import_profile() {
+        let config = GpuConfig {
+            power_cap: Some(250.0),
+            ..Default::default()
+        };
+        let profile = Profile {
+            gpus: IndexMap::from([(
+                "1002:73BF-1DA2:E438-0000:0c:00.0".to_owned(),
+                config.clone(),
+            )]),
+            ..Default::default()
+        };
+
+        let export = ProfileExport::new("gaming".to_owned(), profile);
+        assert_eq!(Some(&config), export.gpus.get("1002:73BF-1DA2:E438"));
+
+        let data = export.to_json().unwrap();
+        let imported = ProfileExport::from_json(&data).unwrap();
+        assert_eq!(export, imported);
+
+        let (profile, unmatched) = imported.into_profile([
+            "1002:73BF-1DA2:E438-0000:03:00.0",
+            "1002:73BF-1DA2:E438-0000:04:00.0",
+            "10DE:2704-1462:5110-0000:09:00.0",
+        ]);
+        assert!(unmatched.is_empty());
+        assert_eq!(
+            vec![
+                "1002:73BF-1DA2:E438-0000:03:00.0",
+                "1002:73BF-1DA2:E438-0000:04:00.0"
+            ],
+            profile.gpus.keys().collect::<Vec<_>>()
+        );
+
+        let (profile, unmatched) = ProfileExport::from_json(&data)
+            .unwrap()
+            .into_profile(["10DE:2704-1462:5110-0000:09:00.0"]);
+        assert!(profile.gpus.is_empty());
+        assert_eq!(vec!["1002:73BF-1DA2:E438"], unmatched);
+    }
+
+    #[test]
+    fn reject_newer_version() {
+        let data = format!(
+            r#"{{"version": {}, "name": "test"}}"#,
+            PROFILE_EXPORT_VERSION + 1
+        );
+        assert!(ProfileExport::from_json(&data).is_err());
+        assert_eq!(
+            "10DE:2704-1462:5110",
+            gpu_model_id("10DE:2704-1462:5110-0000:09:00.0")
+        );
+    }
+}
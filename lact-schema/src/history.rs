@@ -0,0 +1,195 @@
+use crate::DeviceStats;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// A single stats sample as kept in the daemon's stats history
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatsHistorySample {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub values: Vec<(StatType, f64)>,
+    /// Throttle reasons, empty when the GPU is not throttling
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub throttling: Vec<String>,
+}
+
+impl StatsHistorySample {
+    pub fn from_stats(timestamp: i64, stats: &DeviceStats) -> Self {
+        let mut values = Vec::new();
+
+        for (name, temperature) in &stats.temps {
+            if let Some(value) = temperature.value.current {
+                values.push((StatType::Temperature(name.to_owned()), value.into()));
+            }
+        }
+
+        for (name, value) in &stats.voltage.sensors {
+            values.push((StatType::Voltage(name.clone()), *value as f64));
+        }
+
+        for (name, value) in &stats.clockspeed.sensors {
+            values.push((StatType::Clockspeed(name.clone()), *value as f64));
+        }
+
+        for (name, value) in &stats.power.sensors {
+            values.push((StatType::Power(name.clone()), *value));
+        }
+
+        let stats_values = [
+            (
+                StatType::GpuClock,
+                stats.clockspeed.gpu_clockspeed.map(|val| val as f64),
+            ),
+            (
+                StatType::GpuTargetClock,
+                stats.clockspeed.target_gpu_clockspeed.map(|val| val as f64),
+            ),
+            (
+                StatType::VramClock,
+                stats.clockspeed.vram_clockspeed.map(|val| val as f64),
+            ),
+            (
+                StatType::GpuVoltage,
+                stats.voltage.gpu.map(|val| val as f64),
+            ),
+            (StatType::PowerAverage, stats.power.average),
+            (StatType::PowerCurrent, stats.power.current),
+            (StatType::PowerCap, stats.power.cap_current),
+            (
+                StatType::FanPwm,
+                stats
+                    .fan
+                    .pwm_current
+                    .map(|val| f64::from(val) / f64::from(u8::MAX) * 100.0),
+            ),
+            (StatType::FanRpm, stats.fan.speed_current.map(f64::from)),
+            (StatType::GpuUsage, stats.busy_percent.map(f64::from)),
+            (
+                StatType::VramSize,
+                stats.vram.total.map(|val| (val / 1024 / 1024) as f64),
+            ),
+            (
+                StatType::VramUsed,
+                stats.vram.used.map(|val| (val / 1024 / 1024) as f64),
+            ),
+            (
+                StatType::GttSize,
+                stats
+                    .vram
+                    .gtt_total_usable
+                    .map(|val| (val / 1024 / 1024) as f64),
+            ),
+            (
+                StatType::GttUsed,
+                stats.vram.gtt_used.map(|val| (val / 1024 / 1024) as f64),
+            ),
+        ];
+
+        for (stat_type, value) in stats_values {
+            if let Some(value) = value {
+                values.push((stat_type, value));
+            }
+        }
+
+        let throttling = stats
+            .throttle_info
+            .iter()
+            .flatten()
+            .map(|(throttle_type, details)| {
+                if details.is_empty() {
+                    throttle_type.clone()
+                } else {
+                    format!("{throttle_type} ({})", details.join(","))
+                }
+            })
+            .collect();
+
+        Self {
+            timestamp,
+            values,
+            throttling,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Serialize, Deserialize)]
+pub enum StatType {
+    GpuClock,
+    GpuTargetClock,
+    GpuUsage,
+    Temperature(String),
+    FanRpm,
+    FanPwm,
+    PowerCurrent,
+    PowerAverage,
+    PowerCap,
+    Power(String),
+    VramClock,
+    VramSize,
+    VramUsed,
+    GttSize,
+    GttUsed,
+    GpuVoltage,
+    Clockspeed(String),
+    Voltage(String),
+}
+
+impl StatType {
+    pub fn display(&self) -> Cow<'static, str> {
+        use StatType::*;
+        match self {
+            GpuClock => "Clockspeed (GPU)".into(),
+            GpuTargetClock => "Clockspeed (GPU Target)".into(),
+            GpuVoltage => "GPU Voltage".into(),
+            VramClock => "Clockspeed (VRAM)".into(),
+            VramSize => "VRAM Size".into(),
+            VramUsed => "VRAM Used".into(),
+            GttSize => "GTT Size".into(),
+            GttUsed => "GTT Used".into(),
+            GpuUsage => "GPU Usage".into(),
+            Temperature(name) => format!("Temp ({name})").into(),
+            Clockspeed(name) => format!("Clockspeed ({name})").into(),
+            Voltage(name) => format!("Voltage ({name})").into(),
+            Power(name) => format!("Power ({name})").into(),
+            FanRpm => "Fan RPM".into(),
+            FanPwm => "Fan".into(),
+            PowerCurrent => "Power Draw".into(),
+            PowerAverage => "Power Draw (Avg)".into(),
+            PowerCap => "Power Cap".into(),
+        }
+    }
+
+    pub fn metric(&self) -> &'static str {
+        use StatType::*;
+        match self {
+            GpuClock | GpuTargetClock | VramClock | Clockspeed(_) => "MHz",
+            VramSize | VramUsed | GttSize | GttUsed => "MiB",
+            GpuVoltage | Voltage(_) => "mV",
+            Temperature(_) => "℃",
+            FanRpm => "RPM",
+            FanPwm => "%",
+            GpuUsage => "%",
+            PowerCurrent | PowerAverage | PowerCap | Power(_) => "W",
+        }
+    }
+
+    /// How many digits should be formatted
+    pub fn precision(&self) -> usize {
+        use StatType::*;
+        match self {
+            GpuClock | GpuTargetClock | VramClock | Clockspeed(_) => 0,
+            FanPwm => 1,
+            FanRpm => 0,
+            PowerCurrent | PowerAverage | Power(_) => 1,
+            PowerCap => 0,
+            Temperature(_) => 1,
+            GpuUsage | VramSize | VramUsed | GttSize | GttUsed => 0,
+            GpuVoltage | Voltage(_) => 0,
+        }
+    }
+
+    pub fn show_peak(&self) -> bool {
+        use StatType::*;
+        !matches!(self, VramSize | PowerCap)
+    }
+}
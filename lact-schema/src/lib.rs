@@ -1,14 +1,20 @@
 #[cfg(feature = "args")]
 pub mod args;
 pub mod config;
+pub mod event;
+pub mod export;
+pub mod history;
 pub mod i18n;
 mod profiles;
 pub mod request;
 mod response;
+pub mod revisions;
+pub mod stability;
 
 #[cfg(test)]
 mod tests;
 
+pub use event::DaemonEvent;
 use i18n_embed_fl::fl;
 pub use request::Request;
 pub use response::Response;
@@ -27,13 +33,17 @@ use nvml_wrapper::enums::device::PowerMizerMode;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::{self, Debug, Display, Write},
     str::FromStr,
     sync::Arc,
 };
 
-use crate::{config::ProfileHooks, i18n::LANGUAGE_LOADER};
+use crate::{
+    config::{FanPidSettings, ProfileHooks, SensorCombination, SensorCurve},
+    history::StatsHistorySample,
+    i18n::LANGUAGE_LOADER,
+};
 
 pub const GIT_COMMIT: &str = env!("VERGEN_GIT_SHA");
 
@@ -43,6 +53,8 @@ pub enum FanControlMode {
     Static,
     #[default]
     Curve,
+    /// Holds a target temperature using a PID controller
+    Pid,
 }
 
 impl FromStr for FanControlMode {
@@ -52,6 +64,7 @@ impl FromStr for FanControlMode {
         match s {
             "curve" => Ok(Self::Curve),
             "static" => Ok(Self::Static),
+            "pid" => Ok(Self::Pid),
             _ => Err("unknown fan control mode".to_string()),
         }
     }
@@ -672,11 +685,56 @@ pub struct FanStats {
     pub change_threshold: Option<u64>,
     /// Nvidia-only
     pub auto_threshold: Option<u64>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub sensor_curves: IndexMap<String, SensorCurve>,
+    pub sensor_combination: Option<SensorCombination>,
+    pub pid: Option<FanPidSettings>,
+    /// Keys of system hwmon sensors that can be used as `temperature_key` (non-PMFW AMD only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system_sensors: Vec<String>,
+    /// Current values of the system sensors used by the fan control
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub system_temperatures: IndexMap<String, f32>,
+    /// Reason of the currently active fan control failsafe
+    pub failsafe: Option<String>,
+    /// Fan PWM (0-255) last applied by the daemon's fan control, unset while the firmware controls the fans
+    pub pwm_target: Option<u8>,
     // RDNA3+ params
     #[serde(default)]
     pub pmfw_info: PmfwInfo,
 }
 
+/// Result of a fan calibration
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FanCalibration {
+    /// Measured fan speed at each tested PWM value, from the highest PWM to the lowest
+    pub points: Vec<FanCalibrationPoint>,
+    /// Lowest PWM at which a spinning fan keeps spinning
+    pub stall_pwm: Option<u8>,
+    /// Lowest PWM at which a stopped fan starts spinning
+    pub start_pwm: Option<u8>,
+    /// Confirmation timer of the pending config change that stores the measured speeds in the fan control settings.
+    /// Not set when nothing was measured or the change could not be applied.
+    pub apply_timer: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FanCalibrationPoint {
+    pub pwm: u8,
+    pub rpm: u32,
+}
+
+/// Settings rollback done by the daemon after the GPU was reset repeatedly
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigRecovery {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub reason: String,
+    /// Whether the last known good settings were restored, as opposed to the defaults
+    pub restored_known_good: bool,
+}
+
 impl FanStats {
     pub fn percent(&self) -> Option<u64> {
         self.pwm_current
@@ -880,6 +938,20 @@ impl PartialEq for ProfilesInfo {
 pub enum ProfileRule {
     Process(ProcessProfileRule),
     Gamemode(Option<ProcessProfileRule>),
+    Schedule(ScheduleProfileRule),
+    #[serde(rename = "power_source")]
+    PowerSource {
+        on_battery: bool,
+        /// Only matches while the battery charge is below this percentage
+        battery_below: Option<u8>,
+        /// Only matches while the battery charge is above this percentage
+        battery_above: Option<u8>,
+    },
+    #[serde(rename = "gpu_stat")]
+    GpuStat(GpuStatProfileRule),
+    /// Requires a focus agent running in the desktop session, see `lact cli focus-agent`
+    #[serde(rename = "focused_window")]
+    FocusedWindow(FocusedWindowProfileRule),
     And(Vec<ProfileRule>),
     Or(Vec<ProfileRule>),
 }
@@ -890,20 +962,144 @@ impl Default for ProfileRule {
     }
 }
 
+/// Matches a running process. All of the specified filters have to match.
+/// An empty name matches any process, as long as another filter is specified.
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(try_from = "ProcessProfileRuleFilters")]
 pub struct ProcessProfileRule {
     pub name: Arc<str>,
+    /// Contained in the command line in `exact` mode
     pub args: Option<String>,
+    /// How `name`, `args`, `exe`, `cwd` and `cgroup` are matched
+    #[serde(skip_serializing_if = "ProcessMatchMode::is_exact")]
+    pub match_mode: ProcessMatchMode,
+    /// Resolved path of the executable
+    pub exe: Option<String>,
+    /// Working directory of the process
+    pub cwd: Option<String>,
+    pub uid: Option<u32>,
+    /// Cgroup path or one of its components, such as a systemd unit or a Steam app scope
+    pub cgroup: Option<String>,
+    /// Compiled when the rule is deserialized, see [`ProcessProfileRule::compile`]
+    #[serde(skip)]
+    pub patterns: ProcessRulePatterns,
+}
+
+#[derive(Deserialize)]
+struct ProcessProfileRuleFilters {
+    #[serde(default)]
+    name: Arc<str>,
+    args: Option<String>,
+    #[serde(default)]
+    match_mode: ProcessMatchMode,
+    exe: Option<String>,
+    cwd: Option<String>,
+    uid: Option<u32>,
+    cgroup: Option<String>,
 }
 
-impl Default for ProcessProfileRule {
-    fn default() -> Self {
-        Self {
-            name: String::new().into(),
-            args: None,
-        }
-    }
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessMatchMode {
+    /// Arguments are matched as a substring, other filters have to be equal
+    #[default]
+    Exact,
+    /// The whole value has to match a pattern with `*` and `?` wildcards
+    Glob,
+    /// Regular expression which can match any part of the value
+    Regex,
+}
+
+/// Glob and regex patterns of a process rule, empty in `exact` mode
+#[derive(Clone, Default)]
+pub struct ProcessRulePatterns {
+    name: Option<regex::Regex>,
+    args: Option<regex::Regex>,
+    exe: Option<regex::Regex>,
+    cwd: Option<regex::Regex>,
+    cgroup: Option<regex::Regex>,
+}
+
+/// Time range in local time. Ranges where `end` is before `start` go past midnight.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScheduleProfileRule {
+    pub start: ScheduleTime,
+    /// Same as `start` for the whole day
+    pub end: ScheduleTime,
+    /// Days on which the range starts, every day if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weekdays: Vec<Weekday>,
+}
+
+/// Matches the focused window. All of the specified filters have to match.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct FocusedWindowProfileRule {
+    /// Wayland app id or X11 WM class, compared case-insensitively
+    pub app_id: Option<String>,
+    /// Name of the process that owns the window
+    pub process_name: Option<Arc<str>>,
+}
+
+/// Window that is currently focused in the desktop session, as reported by a focus agent
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct FocusedWindow {
+    pub app_id: Option<String>,
+    pub wm_class: Option<String>,
+    pub pid: Option<i32>,
+}
+
+/// Matches when a GPU stat stays past a threshold for a period of time.
+/// The rule also only stops matching once the threshold is not reached for the same period, so that it does not flap.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct GpuStatProfileRule {
+    /// Any GPU can match if not specified
+    pub gpu_id: Option<String>,
+    pub stat: ProfileRuleStat,
+    /// Temperature sensor name, such as `junction` or `edge`. The highest temperature is used if not specified.
+    pub sensor: Option<String>,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    /// How long the condition has to be met in seconds
+    #[serde(default)]
+    pub duration: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileRuleStat {
+    /// Percentage
+    #[default]
+    BusyPercent,
+    /// Degrees celsius
+    Temperature,
+    /// Watts
+    Power,
+    /// MiB
+    VramUsed,
+}
+
+/// Time of day in `HH:MM` format
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(try_from = "String", into = "String")]
+pub struct ScheduleTime {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
 }
 
 pub type ProfileProcessMap = IndexMap<i32, ProfileProcessInfo>;
@@ -913,13 +1109,38 @@ pub struct ProfileWatcherState {
     pub process_list: ProfileProcessMap,
     pub gamemode_games: IndexSet<i32>,
     pub process_names_map: HashMap<Arc<str>, HashSet<i32>>,
+    #[serde(default)]
+    pub power_source: PowerSourceState,
+    #[serde(default)]
+    pub focused_window: Option<FocusedWindow>,
+    /// Recent stats samples of every GPU, only collected when there are GPU stat rules
+    #[serde(skip)]
+    pub gpu_stats: HashMap<String, VecDeque<StatsHistorySample>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerSourceState {
+    pub on_battery: bool,
+    /// Average charge of the system batteries, `None` if there are no batteries
+    pub battery_percent: Option<u8>,
 }
 
 #[allow(clippy::module_name_repetitions)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ProfileProcessInfo {
     pub name: Arc<str>,
     pub cmdline: Box<str>,
+    /// Resolved path of the executable
+    #[serde(default)]
+    pub exe: Option<Box<str>>,
+    #[serde(default)]
+    pub cwd: Option<Box<str>>,
+    #[serde(default)]
+    pub uid: Option<u32>,
+    /// Cgroup v2 path
+    #[serde(default)]
+    pub cgroup: Option<Box<str>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
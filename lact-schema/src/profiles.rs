@@ -1,5 +1,11 @@
-use crate::{ProfileProcessInfo, ProfileWatcherState};
-use std::{collections::hash_map::Entry, fmt};
+use crate::{
+    FocusedWindow, FocusedWindowProfileRule, GpuStatProfileRule, ProcessMatchMode,
+    ProcessProfileRule, ProcessProfileRuleFilters, ProcessRulePatterns, ProfileProcessInfo,
+    ProfileRuleStat, ProfileWatcherState, ScheduleProfileRule, ScheduleTime, Weekday,
+    history::{StatType, StatsHistorySample},
+};
+use regex::Regex;
+use std::{collections::hash_map::Entry, fmt, iter, str::FromStr};
 
 impl fmt::Debug for ProfileWatcherState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -7,6 +13,9 @@ impl fmt::Debug for ProfileWatcherState {
             .field("process_list", &self.process_list.len())
             .field("gamemode_games", &self.gamemode_games.len())
             .field("process_names_map", &self.process_names_map.len())
+            .field("power_source", &self.power_source)
+            .field("focused_window", &self.focused_window)
+            .field("gpu_stats", &self.gpu_stats.len())
             .finish()
     }
 }
@@ -43,3 +52,312 @@ impl ProfileWatcherState {
         None
     }
 }
+
+impl ProcessProfileRule {
+    /// Compiles the glob or regex patterns of the rule.
+    /// This is done when deserializing, so it is only needed for rules that are constructed directly.
+    pub fn compile(&mut self) -> Result<(), regex::Error> {
+        let match_mode = self.match_mode;
+        let compile = |filter: Option<&str>| match (match_mode, filter) {
+            (ProcessMatchMode::Exact, _) | (_, None) => Ok(None),
+            (ProcessMatchMode::Glob, Some(filter)) => Regex::new(&glob_to_regex(filter)).map(Some),
+            (ProcessMatchMode::Regex, Some(filter)) => Regex::new(filter).map(Some),
+        };
+
+        self.patterns = ProcessRulePatterns {
+            name: compile(Some(self.name.as_ref()).filter(|name| !name.is_empty()))?,
+            args: compile(self.args.as_deref())?,
+            exe: compile(self.exe.as_deref())?,
+            cwd: compile(self.cwd.as_deref())?,
+            cgroup: compile(self.cgroup.as_deref())?,
+        };
+        Ok(())
+    }
+
+    /// Whether matching processes can be looked up by their exact name
+    pub fn has_exact_name(&self) -> bool {
+        self.match_mode == ProcessMatchMode::Exact && !self.name.is_empty()
+    }
+
+    /// Rules without any filters never match
+    pub fn matches(&self, info: &ProfileProcessInfo) -> bool {
+        if self.name.is_empty()
+            && self.args.is_none()
+            && self.exe.is_none()
+            && self.cwd.is_none()
+            && self.uid.is_none()
+            && self.cgroup.is_none()
+        {
+            return false;
+        }
+
+        let patterns = &self.patterns;
+        let exact = |filter: &str, value: &str| filter == value;
+
+        (self.name.is_empty()
+            || filter_matches(&self.name, patterns.name.as_ref(), &info.name, exact))
+            && self.args.as_deref().is_none_or(|args| {
+                filter_matches(
+                    args,
+                    patterns.args.as_ref(),
+                    &info.cmdline,
+                    |filter, value| value.contains(filter),
+                )
+            })
+            && self.exe.as_deref().is_none_or(|exe| {
+                info.exe
+                    .as_deref()
+                    .is_some_and(|value| filter_matches(exe, patterns.exe.as_ref(), value, exact))
+            })
+            && self.cwd.as_deref().is_none_or(|cwd| {
+                info.cwd
+                    .as_deref()
+                    .is_some_and(|value| filter_matches(cwd, patterns.cwd.as_ref(), value, exact))
+            })
+            && self.uid.is_none_or(|uid| info.uid == Some(uid))
+            && self.cgroup.as_deref().is_none_or(|cgroup| {
+                info.cgroup.as_deref().is_some_and(|path| {
+                    iter::once(path)
+                        .chain(path.split('/'))
+                        .any(|value| filter_matches(cgroup, patterns.cgroup.as_ref(), value, exact))
+                })
+            })
+    }
+}
+
+fn filter_matches(
+    filter: &str,
+    pattern: Option<&Regex>,
+    value: &str,
+    exact: impl Fn(&str, &str) -> bool,
+) -> bool {
+    match pattern {
+        Some(pattern) => pattern.is_match(value),
+        None => exact(filter, value),
+    }
+}
+
+/// Converts a glob with `*` and `?` wildcards into a regex that matches the whole value
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut buf = [0; 4];
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(c.encode_utf8(&mut buf))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+impl TryFrom<ProcessProfileRuleFilters> for ProcessProfileRule {
+    type Error = regex::Error;
+
+    fn try_from(filters: ProcessProfileRuleFilters) -> Result<Self, Self::Error> {
+        let mut rule = Self {
+            name: filters.name,
+            args: filters.args,
+            match_mode: filters.match_mode,
+            exe: filters.exe,
+            cwd: filters.cwd,
+            uid: filters.uid,
+            cgroup: filters.cgroup,
+            patterns: ProcessRulePatterns::default(),
+        };
+        rule.compile()?;
+        Ok(rule)
+    }
+}
+
+impl ProcessMatchMode {
+    pub fn is_exact(&self) -> bool {
+        *self == Self::Exact
+    }
+}
+
+/// Patterns are derived from the rule filters, so they are not compared
+impl PartialEq for ProcessRulePatterns {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for ProcessRulePatterns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                [&self.name, &self.args, &self.exe, &self.cwd, &self.cgroup]
+                    .into_iter()
+                    .flatten()
+                    .map(Regex::as_str),
+            )
+            .finish()
+    }
+}
+
+impl ScheduleProfileRule {
+    pub fn is_active(&self, weekday: Weekday, time: ScheduleTime) -> bool {
+        let active_on = |day: Weekday| self.weekdays.is_empty() || self.weekdays.contains(&day);
+
+        if self.start < self.end {
+            active_on(weekday) && self.start <= time && time < self.end
+        } else if self.start > self.end {
+            (active_on(weekday) && time >= self.start)
+                || (active_on(weekday.previous()) && time < self.end)
+        } else {
+            active_on(weekday)
+        }
+    }
+}
+
+impl FocusedWindowProfileRule {
+    /// Rules without any filters never match
+    pub fn matches(&self, window: &FocusedWindow, process_name: Option<&str>) -> bool {
+        if self.app_id.is_none() && self.process_name.is_none() {
+            return false;
+        }
+
+        let app_id_matches = self.app_id.as_ref().is_none_or(|app_id| {
+            [&window.app_id, &window.wm_class]
+                .into_iter()
+                .flatten()
+                .any(|value| value.eq_ignore_ascii_case(app_id))
+        });
+        let process_matches = self
+            .process_name
+            .as_deref()
+            .is_none_or(|name| process_name == Some(name));
+
+        app_id_matches && process_matches
+    }
+}
+
+impl GpuStatProfileRule {
+    /// Evaluates the rule over the stats samples of a GPU, ordered from oldest to newest.
+    /// The rule becomes active once the condition is met for `duration`, and inactive once it is not met for `duration`.
+    pub fn is_active<'a>(&self, samples: impl IntoIterator<Item = &'a StatsHistorySample>) -> bool {
+        let duration_ms = i64::try_from(self.duration.saturating_mul(1000)).unwrap_or(i64::MAX);
+
+        let mut active = false;
+        // When the condition started to differ from the current state
+        let mut changed_since = None;
+
+        for sample in samples {
+            let condition_met = self
+                .value(sample)
+                .is_some_and(|value| self.condition_met(value));
+            if condition_met == active {
+                changed_since = None;
+                continue;
+            }
+
+            let since = *changed_since.get_or_insert(sample.timestamp);
+            if sample.timestamp - since >= duration_ms {
+                active = condition_met;
+                changed_since = None;
+            }
+        }
+
+        active
+    }
+
+    pub fn condition_met(&self, value: f64) -> bool {
+        self.above.is_none_or(|threshold| value > threshold)
+            && self.below.is_none_or(|threshold| value < threshold)
+    }
+
+    pub fn value(&self, sample: &StatsHistorySample) -> Option<f64> {
+        let find = |stat_type: StatType| {
+            sample
+                .values
+                .iter()
+                .find(|(sample_type, _)| *sample_type == stat_type)
+                .map(|(_, value)| *value)
+        };
+
+        match self.stat {
+            ProfileRuleStat::BusyPercent => find(StatType::GpuUsage),
+            ProfileRuleStat::Temperature => sample
+                .values
+                .iter()
+                .filter_map(|(stat_type, value)| match stat_type {
+                    StatType::Temperature(name)
+                        if self.sensor.as_ref().is_none_or(|sensor| sensor == name) =>
+                    {
+                        Some(*value)
+                    }
+                    _ => None,
+                })
+                .reduce(f64::max),
+            ProfileRuleStat::Power => {
+                find(StatType::PowerCurrent).or_else(|| find(StatType::PowerAverage))
+            }
+            ProfileRuleStat::VramUsed => find(StatType::VramUsed),
+        }
+    }
+}
+
+impl ScheduleTime {
+    pub fn new(hour: u8, minute: u8) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self { hour, minute })
+    }
+}
+
+impl fmt::Display for ScheduleTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl FromStr for ScheduleTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid time '{s}', expected HH:MM");
+
+        let (hour, minute) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let hour = hour.parse().map_err(|_| invalid())?;
+        let minute = minute.parse().map_err(|_| invalid())?;
+        Self::new(hour, minute).ok_or_else(invalid)
+    }
+}
+
+impl TryFrom<String> for ScheduleTime {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ScheduleTime> for String {
+    fn from(value: ScheduleTime) -> Self {
+        value.to_string()
+    }
+}
+
+impl Weekday {
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// Day from a number where Monday is 1 and Sunday is 7
+    pub fn from_monday_one_offset(day: i8) -> Option<Self> {
+        Self::ALL
+            .get(usize::try_from(day).ok()?.checked_sub(1)?)
+            .copied()
+    }
+
+    pub fn previous(self) -> Self {
+        let index = Self::ALL.iter().position(|day| *day == self).unwrap_or(0);
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
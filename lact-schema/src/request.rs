@@ -1,8 +1,11 @@
 use std::fmt;
 
 use crate::{
-    FanOptions, ProfileRule,
+    FanOptions, FocusedWindow, ProfileRule,
     config::{GpuConfig, Profile, ProfileHooks},
+    export::ProfileExport,
+    history::StatType,
+    stability::StabilityTestOptions,
 };
 use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
 use serde::{Deserialize, Serialize};
@@ -11,6 +14,10 @@ use serde::{Deserialize, Serialize};
 #[serde(tag = "command", content = "args", rename_all = "snake_case")]
 pub enum Request<'a> {
     Ping,
+    /// Authenticates a TCP client with one of the tokens from the daemon config.
+    Authenticate {
+        token: String,
+    },
     ListDevices,
     SystemInfo,
     DeviceInfo {
@@ -24,6 +31,22 @@ pub enum Request<'a> {
     DeviceStats {
         id: &'a str,
     },
+    /// Turns the connection into a stream of `DeviceStats` responses sent every `interval_ms`.
+    SubscribeStats {
+        id: &'a str,
+        interval_ms: u64,
+    },
+    /// Turns the connection into a stream of `DaemonEvent` responses.
+    SubscribeEvents,
+    /// Samples from the daemon's stats history, if it's enabled.
+    /// `since` is a unix timestamp in milliseconds, `stats` limits which values are returned (all if empty).
+    StatsHistory {
+        id: &'a str,
+        #[serde(default)]
+        since: Option<i64>,
+        #[serde(default)]
+        stats: Vec<StatType>,
+    },
     DisplaysInfo {
         id: &'a str,
     },
@@ -37,6 +60,13 @@ pub enum Request<'a> {
     ResetPmfw {
         id: &'a str,
     },
+    /// Measures the fan speed at different PWM values to find the minimum usable speeds.
+    /// Takes around a minute, the previous fan state is restored afterwards.
+    /// Aborted if the GPU gets too hot. The measured minimum speeds are stored in the fan control settings
+    /// as a pending config change, which needs to be confirmed with `ConfirmPendingConfig`.
+    CalibrateFan {
+        id: &'a str,
+    },
     SetPowerCap {
         id: &'a str,
         cap: Option<f64>,
@@ -89,6 +119,17 @@ pub enum Request<'a> {
     DeleteProfile {
         name: String,
     },
+    /// Exports a profile in a portable format. Exports the default profile if `name` is not specified.
+    ExportProfile {
+        name: Option<String>,
+    },
+    /// Creates a profile from an export, using the name from the export if `name` is not specified.
+    /// Returns the GPU models from the export which are not present in this system.
+    ImportProfile {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        profile: Box<ProfileExport>,
+    },
     MoveProfile {
         name: String,
         new_position: usize,
@@ -102,6 +143,11 @@ pub enum Request<'a> {
     EvaluateProfileRule {
         rule: ProfileRule,
     },
+    /// Reported by a focus agent in the desktop session, `None` when no window is focused or the agent exits.
+    /// The window is also cleared when the connection that reported it is closed.
+    SetFocusedWindow {
+        window: Option<FocusedWindow>,
+    },
     SetProfileRule {
         name: String,
         rule: Option<ProfileRule>,
@@ -118,6 +164,38 @@ pub enum Request<'a> {
     ProcessList {
         id: &'a str,
     },
+    /// Runs a stability test in the background, see `StabilityTestOptions`.
+    /// Tested settings are applied as pending changes that are never confirmed, so they are not saved.
+    /// Requires polkit authorization, and is only available to local clients.
+    StartStabilityTest {
+        id: &'a str,
+        options: StabilityTestOptions,
+    },
+    StabilityTestStatus {
+        id: &'a str,
+    },
+    StopStabilityTest {
+        id: &'a str,
+    },
+    /// The last settings rollback after repeated GPU resets, if there was one since the daemon started
+    ConfigRecovery {
+        id: &'a str,
+    },
+    /// Saved revisions of the GPU settings, oldest first. Lists the revisions of all GPUs if `id` is not specified.
+    ListConfigRevisions {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<&'a str>,
+    },
+    /// Changes between two revisions. Compares to the current settings of the same profile if `to` is not specified.
+    DiffConfigRevisions {
+        from: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to: Option<u64>,
+    },
+    /// Applies the settings of a revision of the active profile. Needs to be confirmed like any other config change.
+    RollbackConfig {
+        revision: u64,
+    },
     DetachGpu {
         id: &'a str,
     },
@@ -131,6 +209,156 @@ pub enum Request<'a> {
     RestConfig,
 }
 
+impl Request<'_> {
+    /// The access class needed to use this request
+    pub fn class(&self) -> RequestClass {
+        match self {
+            Request::Ping
+            | Request::Authenticate { .. }
+            | Request::ListDevices
+            | Request::SystemInfo
+            | Request::DeviceInfo { .. }
+            | Request::DeviceApiInfo { .. }
+            | Request::DisplaysInfo { .. }
+            | Request::DeviceClocksInfo { .. }
+            | Request::DevicePowerProfileModes { .. }
+            | Request::GetPowerStates { .. }
+            | Request::ListProfiles { .. }
+            | Request::GetProfile { .. }
+            | Request::ExportProfile { .. }
+            | Request::EvaluateProfileRule { .. }
+            | Request::GetGpuConfig { .. }
+            | Request::ListConfigRevisions { .. }
+            | Request::DiffConfigRevisions { .. } => RequestClass::Read,
+            Request::DeviceStats { .. }
+            | Request::SubscribeStats { .. }
+            | Request::SubscribeEvents
+            | Request::StatsHistory { .. }
+            | Request::ProcessList { .. }
+            | Request::StabilityTestStatus { .. }
+            | Request::ConfigRecovery { .. }
+            | Request::VbiosDump { .. } => RequestClass::Monitor,
+            Request::SetFanControl(_)
+            | Request::ResetPmfw { .. }
+            | Request::CalibrateFan { .. }
+            | Request::SetPowerCap { .. }
+            | Request::SetPerformanceLevel { .. }
+            | Request::SetClocksValue { .. }
+            | Request::BatchSetClocksValue { .. }
+            | Request::SetPowerProfileMode { .. }
+            | Request::SetEnabledPowerStates { .. }
+            | Request::SetGpuConfig { .. }
+            | Request::ConfirmPendingConfig(_)
+            | Request::SetProfile { .. }
+            | Request::CreateProfile { .. }
+            | Request::DeleteProfile { .. }
+            | Request::ImportProfile { .. }
+            | Request::MoveProfile { .. }
+            | Request::HoldProfile { .. }
+            | Request::ReleaseProfile { .. }
+            | Request::SetProfileRule { .. }
+            | Request::SetFocusedWindow { .. }
+            | Request::StopStabilityTest { .. }
+            | Request::RollbackConfig { .. } => RequestClass::Tune,
+            // The workload is an arbitrary command run by the daemon
+            Request::StartStabilityTest { .. }
+            | Request::DetachGpu { .. }
+            | Request::ReattachGpu { .. }
+            | Request::EnableOverdrive
+            | Request::DisableOverdrive
+            | Request::GenerateSnapshot
+            | Request::RestConfig => RequestClass::Admin,
+        }
+    }
+
+    /// Name of the request, as used in the `command` field
+    pub fn command(&self) -> &'static str {
+        match self {
+            Request::Ping => "ping",
+            Request::Authenticate { .. } => "authenticate",
+            Request::ListDevices => "list_devices",
+            Request::SystemInfo => "system_info",
+            Request::DeviceInfo { .. } => "device_info",
+            Request::DeviceApiInfo { .. } => "device_api_info",
+            Request::DeviceStats { .. } => "device_stats",
+            Request::SubscribeStats { .. } => "subscribe_stats",
+            Request::SubscribeEvents => "subscribe_events",
+            Request::StatsHistory { .. } => "stats_history",
+            Request::DisplaysInfo { .. } => "displays_info",
+            Request::DeviceClocksInfo { .. } => "device_clocks_info",
+            Request::DevicePowerProfileModes { .. } => "device_power_profile_modes",
+            Request::SetFanControl(_) => "set_fan_control",
+            Request::ResetPmfw { .. } => "reset_pmfw",
+            Request::CalibrateFan { .. } => "calibrate_fan",
+            Request::SetPowerCap { .. } => "set_power_cap",
+            Request::SetPerformanceLevel { .. } => "set_performance_level",
+            Request::SetClocksValue { .. } => "set_clocks_value",
+            Request::BatchSetClocksValue { .. } => "batch_set_clocks_value",
+            Request::SetPowerProfileMode { .. } => "set_power_profile_mode",
+            Request::GetPowerStates { .. } => "get_power_states",
+            Request::SetEnabledPowerStates { .. } => "set_enabled_power_states",
+            Request::VbiosDump { .. } => "vbios_dump",
+            Request::ListProfiles { .. } => "list_profiles",
+            Request::GetProfile { .. } => "get_profile",
+            Request::SetProfile { .. } => "set_profile",
+            Request::CreateProfile { .. } => "create_profile",
+            Request::DeleteProfile { .. } => "delete_profile",
+            Request::ExportProfile { .. } => "export_profile",
+            Request::ImportProfile { .. } => "import_profile",
+            Request::MoveProfile { .. } => "move_profile",
+            Request::HoldProfile { .. } => "hold_profile",
+            Request::ReleaseProfile { .. } => "release_profile",
+            Request::EvaluateProfileRule { .. } => "evaluate_profile_rule",
+            Request::SetFocusedWindow { .. } => "set_focused_window",
+            Request::SetProfileRule { .. } => "set_profile_rule",
+            Request::GetGpuConfig { .. } => "get_gpu_config",
+            Request::SetGpuConfig { .. } => "set_gpu_config",
+            Request::ProcessList { .. } => "process_list",
+            Request::StartStabilityTest { .. } => "start_stability_test",
+            Request::StabilityTestStatus { .. } => "stability_test_status",
+            Request::StopStabilityTest { .. } => "stop_stability_test",
+            Request::ConfigRecovery { .. } => "config_recovery",
+            Request::ListConfigRevisions { .. } => "list_config_revisions",
+            Request::DiffConfigRevisions { .. } => "diff_config_revisions",
+            Request::RollbackConfig { .. } => "rollback_config",
+            Request::DetachGpu { .. } => "detach_gpu",
+            Request::ReattachGpu { .. } => "reattach_gpu",
+            Request::EnableOverdrive => "enable_overdrive",
+            Request::DisableOverdrive => "disable_overdrive",
+            Request::GenerateSnapshot => "generate_snapshot",
+            Request::ConfirmPendingConfig(_) => "confirm_pending_config",
+            Request::RestConfig => "rest_config",
+        }
+    }
+}
+
+/// Classes of requests used for access control, ordered from the least to the most privileged.
+/// A client that is allowed to use a class can also use all of the classes before it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestClass {
+    /// Static information about the system, GPUs and configuration
+    Read,
+    /// Live stats, processes and events
+    Monitor,
+    /// Changing GPU settings and profiles
+    Tune,
+    /// System-wide changes, such as enabling overdrive or detaching GPUs
+    Admin,
+}
+
+impl fmt::Display for RequestClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RequestClass::Read => "read",
+            RequestClass::Monitor => "monitor",
+            RequestClass::Tune => "tune",
+            RequestClass::Admin => "admin",
+        };
+        f.write_str(name)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(tag = "command", rename_all = "snake_case")]
 pub enum ConfirmCommand {
@@ -0,0 +1,127 @@
+use crate::config::GpuConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+use std::collections::BTreeSet;
+
+/// A saved version of the settings of a GPU
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigRevision {
+    /// Increasing number that identifies the revision
+    pub revision: u64,
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub gpu_id: String,
+    /// Profile the settings belong to, `None` for the default profile
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub config: GpuConfig,
+    pub source: ConfigChangeSource,
+}
+
+/// What caused a config change
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConfigChangeSource {
+    /// Process name of a local client, `tcp` for remote clients, `None` for changes made by the daemon itself
+    pub client: Option<String>,
+    pub uid: Option<u32>,
+    /// Name of the request (or the daemon feature) that made the change
+    pub request: String,
+}
+
+/// A single changed value between two configs
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigDiffEntry {
+    /// Dot-separated path of the value, e.g. `fan_control_settings.static_speed`
+    pub path: String,
+    /// `None` if the value was not set
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+pub fn diff_configs(old: &GpuConfig, new: &GpuConfig) -> anyhow::Result<Vec<ConfigDiffEntry>> {
+    let old = serde_json::to_value(old)?;
+    let new = serde_json::to_value(new)?;
+
+    let mut entries = Vec::new();
+    diff_values(String::new(), Some(&old), Some(&new), &mut entries);
+    Ok(entries)
+}
+
+fn diff_values(
+    path: String,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    entries: &mut Vec<ConfigDiffEntry>,
+) {
+    // Unset values are skipped when serializing, but can also show up as null
+    let old = old.filter(|value| !value.is_null());
+    let new = new.filter(|value| !value.is_null());
+
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                diff_values(path, old.get(key), new.get(key), entries);
+            }
+        }
+        (old, new) if old != new => entries.push(ConfigDiffEntry {
+            path,
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigDiffEntry, diff_configs};
+    use crate::config::{FanControlSettings, GpuConfig};
+    use serde_json::json;
+
+    #[test]
+    fn config_diff() {
+        let old = GpuConfig {
+            power_cap: Some(300.0),
+            fan_control_settings: Some(FanControlSettings::default()),
+            ..Default::default()
+        };
+        let mut new = old.clone();
+        new.power_cap = Some(280.0);
+        new.clocks_configuration.voltage_offset = Some(-50);
+        new.fan_control_settings.as_mut().unwrap().static_speed = 0.8;
+
+        let diff = diff_configs(&old, &new).unwrap();
+        assert_eq!(
+            vec![
+                ConfigDiffEntry {
+                    path: "fan_control_settings.static_speed".to_owned(),
+                    old: Some(json!(FanControlSettings::default().static_speed)),
+                    new: Some(json!(0.8f32)),
+                },
+                ConfigDiffEntry {
+                    path: "power_cap".to_owned(),
+                    old: Some(json!(300.0)),
+                    new: Some(json!(280.0)),
+                },
+                ConfigDiffEntry {
+                    path: "voltage_offset".to_owned(),
+                    old: None,
+                    new: Some(json!(-50)),
+                },
+            ],
+            diff
+        );
+
+        assert!(diff_configs(&new, &new).unwrap().is_empty());
+    }
+}
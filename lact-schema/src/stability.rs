@@ -0,0 +1,117 @@
+use crate::config::ClocksConfiguration;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+
+/// Parameters of a stability test, which steps a clocks setting while running a workload on the GPU
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StabilityTestOptions {
+    /// Shell command that puts load on the GPU, such as a compute or Vulkan benchmark.
+    /// It is restarted if it exits before the step is over.
+    /// When the daemon runs as root, the command runs as the requesting user.
+    pub workload_command: String,
+    /// The setting that is changed on every step
+    pub target: StabilityTestTarget,
+    /// Value used for the first step
+    pub start: i32,
+    /// Value added on every following step, usually negative for undervolting
+    pub step: i32,
+    /// Maximum number of steps
+    pub steps: u32,
+    /// How long the workload runs for every step, in seconds
+    pub step_duration: u64,
+}
+
+impl StabilityTestOptions {
+    pub fn values(&self) -> impl Iterator<Item = i32> + '_ {
+        (0..self.steps).map_while(|i| {
+            let offset = self.step.checked_mul(i32::try_from(i).ok()?)?;
+            self.start.checked_add(offset)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StabilityTestTarget {
+    VoltageOffset,
+    GpuClockOffset { pstate: u32 },
+    MemClockOffset { pstate: u32 },
+    MaxCoreClock,
+    MaxMemoryClock,
+}
+
+impl StabilityTestTarget {
+    pub fn apply(self, clocks: &mut ClocksConfiguration, value: i32) {
+        match self {
+            StabilityTestTarget::VoltageOffset => clocks.voltage_offset = Some(value),
+            StabilityTestTarget::GpuClockOffset { pstate } => {
+                clocks.gpu_clock_offsets.insert(pstate, value);
+            }
+            StabilityTestTarget::MemClockOffset { pstate } => {
+                clocks.mem_clock_offsets.insert(pstate, value);
+            }
+            StabilityTestTarget::MaxCoreClock => clocks.max_core_clock = Some(value),
+            StabilityTestTarget::MaxMemoryClock => clocks.max_memory_clock = Some(value),
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StabilityTestStatus {
+    pub options: StabilityTestOptions,
+    pub state: StabilityTestState,
+    /// Value of the step that is currently running
+    pub current_value: Option<i32>,
+    /// Value of the last step that passed without errors
+    pub last_stable_value: Option<i32>,
+    pub steps: Vec<StabilityTestStep>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StabilityTestState {
+    Running,
+    /// All steps passed, or the test stopped at the first failed step
+    Finished,
+    /// Stopped by a client before finishing
+    Stopped,
+    /// The test could not be run, e.g. because the settings could not be applied
+    Error,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StabilityTestStep {
+    pub value: i32,
+    /// Reason of the failure, `None` if the step was stable
+    pub failure: Option<String>,
+    /// Throttle reasons seen while the step was running
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub throttling: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StabilityTestOptions, StabilityTestTarget};
+    use crate::config::ClocksConfiguration;
+
+    #[test]
+    fn step_values() {
+        let options = StabilityTestOptions {
+            workload_command: "vkmark".to_owned(),
+            target: StabilityTestTarget::VoltageOffset,
+            start: 0,
+            step: -25,
+            steps: 4,
+            step_duration: 60,
+        };
+        assert_eq!(vec![0, -25, -50, -75], options.values().collect::<Vec<_>>());
+
+        let mut clocks = ClocksConfiguration::default();
+        options.target.apply(&mut clocks, -50);
+        StabilityTestTarget::GpuClockOffset { pstate: 0 }.apply(&mut clocks, 100);
+        assert_eq!(Some(-50), clocks.voltage_offset);
+        assert_eq!(Some(&100), clocks.gpu_clock_offsets.get(&0));
+    }
+}
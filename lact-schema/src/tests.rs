@@ -1,4 +1,11 @@
-use crate::{FanControlMode, FanOptions, PmfwOptions, Pong, Request, Response, clean_gpu_name};
+use crate::{
+    DaemonEvent, FanControlMode, FanOptions, FocusedWindow, FocusedWindowProfileRule,
+    GpuStatProfileRule, PmfwOptions, Pong, ProcessMatchMode, ProcessProfileRule,
+    ProfileProcessInfo, ProfileRule, ProfileRuleStat, Request, Response, ScheduleProfileRule,
+    ScheduleTime, Weekday, clean_gpu_name,
+    history::{StatType, StatsHistorySample},
+    stability::{StabilityTestOptions, StabilityTestTarget},
+};
 use anyhow::anyhow;
 use serde_json::json;
 use std::collections::BTreeMap;
@@ -87,6 +94,110 @@ fn set_fan_clocks() {
     assert_eq!(expected_request, request);
 }
 
+#[test]
+fn subscribe_stats_request() {
+    let value = r#"{
+        "command": "subscribe_stats",
+        "args": {
+            "id": "123",
+            "interval_ms": 1000
+        }
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+    let expected_request = Request::SubscribeStats {
+        id: "123",
+        interval_ms: 1000,
+    };
+    assert_eq!(expected_request, request);
+}
+
+#[test]
+fn stats_history_request() {
+    let value = r#"{
+        "command": "stats_history",
+        "args": {
+            "id": "123",
+            "stats": ["GpuUsage", {"Temperature": "edge"}]
+        }
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+    let expected_request = Request::StatsHistory {
+        id: "123",
+        since: None,
+        stats: vec![StatType::GpuUsage, StatType::Temperature("edge".to_owned())],
+    };
+    assert_eq!(expected_request, request);
+}
+
+#[test]
+fn start_stability_test_request() {
+    let value = r#"{
+        "command": "start_stability_test",
+        "args": {
+            "id": "123",
+            "options": {
+                "workload_command": "vkmark",
+                "target": {"gpu_clock_offset": {"pstate": 0}},
+                "start": 100,
+                "step": 25,
+                "steps": 8,
+                "step_duration": 300
+            }
+        }
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+    let expected_request = Request::StartStabilityTest {
+        id: "123",
+        options: StabilityTestOptions {
+            workload_command: "vkmark".to_owned(),
+            target: StabilityTestTarget::GpuClockOffset { pstate: 0 },
+            start: 100,
+            step: 25,
+            steps: 8,
+            step_duration: 300,
+        },
+    };
+    assert_eq!(expected_request, request);
+}
+
+#[test]
+fn request_command_name() {
+    let request = Request::DiffConfigRevisions { from: 3, to: None };
+    assert_eq!("diff_config_revisions", request.command());
+    assert_eq!(
+        json!({"command": "diff_config_revisions", "args": {"from": 3}}),
+        serde_json::to_value(&request).unwrap()
+    );
+    assert_eq!("ping", Request::Ping.command());
+    assert_eq!("vbios_dump", Request::VbiosDump { id: "123" }.command());
+}
+
+#[test]
+fn daemon_event_response() {
+    let expected_response = json!({
+        "status": "ok",
+        "data": {
+            "type": "profile_changed",
+            "data": {
+                "name": "gaming"
+            }
+        }
+    });
+    let response = Response::Ok(DaemonEvent::ProfileChanged {
+        name: Some("gaming".to_owned()),
+    });
+    assert_eq!(serde_json::to_value(response).unwrap(), expected_response);
+}
+
+#[test]
+fn daemon_event_without_data() {
+    let response = Response::Ok(DaemonEvent::ConfigReloaded);
+    assert_eq!(
+        serde_json::to_value(response).unwrap(),
+        json!({ "status": "ok", "data": { "type": "config_reloaded" } })
+    );
+}
+
 #[test]
 fn clean_gpu_name_removes_vendor_prefixes() {
     assert_eq!(clean_gpu_name("AMD Radeon RX 9070 XT"), "Radeon RX 9070 XT");
@@ -138,3 +249,213 @@ fn clean_gpu_name_keeps_professional_product_brands() {
     assert_eq!(clean_gpu_name("GK110GL [Tesla K20]"), "Tesla K20");
     assert_eq!(clean_gpu_name("NVIDIA Quadro RTX 6000"), "Quadro RTX 6000");
 }
+
+#[test]
+fn schedule_rule() {
+    let rule: ProfileRule = serde_json::from_value(json!({
+        "type": "schedule",
+        "filter": {
+            "start": "22:30",
+            "end": "07:00",
+            "weekdays": ["friday", "saturday"]
+        }
+    }))
+    .unwrap();
+    let ProfileRule::Schedule(schedule) = &rule else {
+        panic!("unexpected rule {rule:?}");
+    };
+    assert_eq!(ScheduleTime::new(22, 30).unwrap(), schedule.start);
+
+    let time = |value: &str| value.parse::<ScheduleTime>().unwrap();
+    assert!(!schedule.is_active(Weekday::Friday, time("22:29")));
+    assert!(schedule.is_active(Weekday::Friday, time("22:30")));
+    assert!(schedule.is_active(Weekday::Saturday, time("06:59")));
+    assert!(!schedule.is_active(Weekday::Saturday, time("07:00")));
+    assert!(schedule.is_active(Weekday::Sunday, time("03:00")));
+    assert!(!schedule.is_active(Weekday::Monday, time("03:00")));
+    assert!(!schedule.is_active(Weekday::Thursday, time("23:00")));
+
+    let office_hours = ScheduleProfileRule {
+        start: time("09:00"),
+        end: time("17:00"),
+        weekdays: vec![],
+    };
+    assert!(office_hours.is_active(Weekday::Sunday, time("12:00")));
+    assert!(!office_hours.is_active(Weekday::Sunday, time("17:00")));
+
+    assert!("24:00".parse::<ScheduleTime>().is_err());
+    assert!("7".parse::<ScheduleTime>().is_err());
+    assert_eq!(
+        json!({"start": "22:30", "end": "07:00", "weekdays": ["friday", "saturday"]}),
+        serde_json::to_value(schedule).unwrap()
+    );
+}
+
+#[test]
+fn power_source_rule() {
+    let rule: ProfileRule = serde_json::from_value(json!({
+        "type": "power_source",
+        "filter": {
+            "on_battery": true,
+            "battery_below": 30
+        }
+    }))
+    .unwrap();
+    assert_eq!(
+        ProfileRule::PowerSource {
+            on_battery: true,
+            battery_below: Some(30),
+            battery_above: None,
+        },
+        rule
+    );
+    assert_eq!(
+        json!({"type": "power_source", "filter": {"on_battery": true, "battery_below": 30}}),
+        serde_json::to_value(&rule).unwrap()
+    );
+}
+
+#[test]
+fn gpu_stat_rule() {
+    let rule: ProfileRule = serde_json::from_value(json!({
+        "type": "gpu_stat",
+        "filter": {
+            "stat": "busy_percent",
+            "above": 80.0,
+            "duration": 30
+        }
+    }))
+    .unwrap();
+    let ProfileRule::GpuStat(stat_rule) = &rule else {
+        panic!("unexpected rule {rule:?}");
+    };
+    assert_eq!(ProfileRuleStat::BusyPercent, stat_rule.stat);
+
+    let samples: Vec<StatsHistorySample> =
+        [90.0, 90.0, 90.0, 90.0, 50.0, 95.0, 50.0, 50.0, 50.0, 50.0]
+            .into_iter()
+            .zip((0..).step_by(10_000))
+            .map(|(value, timestamp)| StatsHistorySample {
+                timestamp,
+                values: vec![(StatType::GpuUsage, value)],
+                throttling: vec![],
+            })
+            .collect();
+    let active: Vec<bool> = (1..=samples.len())
+        .map(|count| stat_rule.is_active(&samples[..count]))
+        .collect();
+    assert_eq!(
+        vec![
+            false, false, false, true, true, true, true, true, true, false
+        ],
+        active
+    );
+
+    let junction_rule = GpuStatProfileRule {
+        stat: ProfileRuleStat::Temperature,
+        sensor: Some("junction".to_owned()),
+        above: Some(90.0),
+        ..Default::default()
+    };
+    let sample = StatsHistorySample {
+        timestamp: 0,
+        values: vec![
+            (StatType::Temperature("edge".to_owned()), 95.0),
+            (StatType::Temperature("junction".to_owned()), 85.0),
+        ],
+        throttling: vec![],
+    };
+    assert_eq!(Some(85.0), junction_rule.value(&sample));
+    assert!(!junction_rule.is_active([&sample]));
+}
+
+#[test]
+fn focused_window_rule() {
+    let window = FocusedWindow {
+        app_id: None,
+        wm_class: Some("steam_app_1091500".to_owned()),
+        pid: Some(1234),
+    };
+
+    let app_rule = FocusedWindowProfileRule {
+        app_id: Some("Steam_App_1091500".to_owned()),
+        process_name: None,
+    };
+    assert!(app_rule.matches(&window, None));
+
+    let process_rule = FocusedWindowProfileRule {
+        app_id: Some("steam_app_1091500".to_owned()),
+        process_name: Some("Cyberpunk2077.exe".into()),
+    };
+    assert!(process_rule.matches(&window, Some("Cyberpunk2077.exe")));
+    assert!(!process_rule.matches(&window, Some("steam")));
+    assert!(!FocusedWindowProfileRule::default().matches(&window, None));
+}
+
+#[test]
+fn process_rule_patterns() {
+    let info = ProfileProcessInfo {
+        name: "Cyberpunk2077.exe".into(),
+        cmdline: "Z:\\games\\Cyberpunk2077.exe --launcher-skip".into(),
+        exe: Some("/usr/bin/wine64-preloader".into()),
+        cwd: Some("/home/user/.steam/steam/steamapps/common/Cyberpunk 2077".into()),
+        uid: Some(1000),
+        cgroup: Some(
+            "/user.slice/user-1000.slice/user@1000.service/app.slice/app-steam-app1091500-4242.scope"
+                .into(),
+        ),
+    };
+
+    let exact_rule = ProcessProfileRule {
+        name: "Cyberpunk2077.exe".into(),
+        args: Some("--launcher-skip".to_owned()),
+        uid: Some(1000),
+        ..Default::default()
+    };
+    assert!(exact_rule.has_exact_name());
+    assert!(exact_rule.matches(&info));
+    assert!(
+        !ProcessProfileRule {
+            uid: Some(0),
+            ..exact_rule
+        }
+        .matches(&info)
+    );
+
+    let glob_rule: ProcessProfileRule = serde_json::from_value(json!({
+        "name": "",
+        "match_mode": "glob",
+        "cwd": "/home/*/.steam/steam/steamapps/common/*",
+        "cgroup": "app-steam-app1091500-*.scope"
+    }))
+    .unwrap();
+    assert!(!glob_rule.has_exact_name());
+    assert!(glob_rule.matches(&info));
+    assert!(!glob_rule.matches(&ProfileProcessInfo {
+        cgroup: Some("/user.slice/user-1000.slice/session-2.scope".into()),
+        ..info.clone()
+    }));
+
+    let regex_rule: ProcessProfileRule = serde_json::from_value(json!({
+        "name": "(?i)^cyberpunk\\d+\\.exe$",
+        "match_mode": "regex",
+        "exe": "wine"
+    }))
+    .unwrap();
+    assert_eq!(ProcessMatchMode::Regex, regex_rule.match_mode);
+    assert!(regex_rule.matches(&info));
+    assert!(!regex_rule.matches(&ProfileProcessInfo {
+        exe: None,
+        ..info.clone()
+    }));
+
+    let invalid_rule =
+        serde_json::from_value::<ProcessProfileRule>(json!({"name": "(", "match_mode": "regex"}));
+    assert!(invalid_rule.is_err());
+
+    assert!(!ProcessProfileRule::default().matches(&info));
+    assert_eq!(
+        json!({"name": "", "match_mode": "glob", "cwd": "/home/*/.steam/steam/steamapps/common/*", "cgroup": "app-steam-app1091500-*.scope"}),
+        serde_json::to_value(&glob_rule).unwrap()
+    );
+}
@@ -93,6 +93,7 @@ make install PREFIX=/usr DESTDIR=%{buildroot}
 %doc README.md
 /usr/bin/lact
 /usr/lib/systemd/system/lactd.service
+/usr/lib/systemd/user/lact-focus-agent.service
 /usr/share/applications/io.github.ilya_zlobintsev.LACT.desktop
 /usr/share/icons/hicolor/512x512/apps/io.github.ilya_zlobintsev.LACT.png
 /usr/share/icons/hicolor/scalable/apps/io.github.ilya_zlobintsev.LACT.svg